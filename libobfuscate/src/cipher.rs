@@ -0,0 +1,389 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use std::mem;
+
+use crate::bindings::*;
+use crate::multi::Iv;
+use crate::{to_password_buffer, Error};
+
+/// Block of data, as processed by the ciphers.
+pub type Block = Iv;
+
+/// Encrypts `data` in CBC mode, using `encrypt_block` to encrypt each block.
+fn cbc_encrypt(
+    iv: &mut Iv,
+    data: &mut [u8],
+    mut encrypt_block: impl FnMut(&mut Block),
+) -> Result<(), Error> {
+    if !data.len().is_multiple_of(iv.len()) {
+        return Err(Error::InvalidDataLength);
+    }
+
+    for chunk in data.chunks_exact_mut(iv.len()) {
+        let block: &mut Block = chunk.try_into().unwrap();
+
+        for (byte, iv_byte) in block.iter_mut().zip(iv.iter()) {
+            *byte ^= iv_byte;
+        }
+        encrypt_block(block);
+
+        *iv = *block;
+    }
+
+    Ok(())
+}
+
+/// Decrypts `data` in CBC mode, using `decrypt_block` to decrypt each block.
+fn cbc_decrypt(
+    iv: &mut Iv,
+    data: &mut [u8],
+    mut decrypt_block: impl FnMut(&mut Block),
+) -> Result<(), Error> {
+    if !data.len().is_multiple_of(iv.len()) {
+        return Err(Error::InvalidDataLength);
+    }
+
+    for chunk in data.chunks_exact_mut(iv.len()) {
+        let block: &mut Block = chunk.try_into().unwrap();
+
+        let ciphertext = *block;
+        decrypt_block(block);
+        for (byte, iv_byte) in block.iter_mut().zip(iv.iter()) {
+            *byte ^= iv_byte;
+        }
+
+        *iv = ciphertext;
+    }
+
+    Ok(())
+}
+
+macro_rules! cipher {
+    ($(#[$attr:meta])* $name:ident, $data:ident, $set_key:ident, $encrypt:ident, $decrypt:ident) => {
+        $(#[$attr])*
+        ///
+        /// Data is encrypted and decrypted in CBC mode. As with `Multi`, the object's state changes
+        /// when data is processed.
+        pub struct $name {
+            data: $data,
+            iv: Iv,
+        }
+
+        impl $name {
+            /// Creates a new cipher keyed with `password`, using `iv` as initialization vector.
+            pub fn new(password: &str, iv: &Iv) -> Result<Self, Error> {
+                let password = to_password_buffer(password)?;
+
+                let mut data: $data = unsafe { mem::zeroed() };
+
+                unsafe {
+                    $set_key(&mut data as *mut $data, password.as_ptr());
+                }
+
+                Ok($name { data, iv: *iv })
+            }
+
+            /// Encrypts a single `block`, without chaining.
+            pub fn encrypt_block(&mut self, block: &mut Block) {
+                unsafe {
+                    $encrypt(&mut self.data as *mut $data, block.as_mut_ptr());
+                }
+            }
+
+            /// Decrypts a single `block`, without chaining.
+            pub fn decrypt_block(&mut self, block: &mut Block) {
+                unsafe {
+                    $decrypt(&mut self.data as *mut $data, block.as_mut_ptr());
+                }
+            }
+
+            /// Encrypts `data`.
+            ///
+            /// # Errors
+            ///
+            /// Returns `Error::InvalidDataLength` if the length of `data` is not a multiple of the
+            /// block size.
+            pub fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+                let mut iv = self.iv;
+                cbc_encrypt(&mut iv, data, |block| self.encrypt_block(block))?;
+                self.iv = iv;
+
+                Ok(())
+            }
+
+            /// Decrypts `data`.
+            ///
+            /// # Errors
+            ///
+            /// Returns `Error::InvalidDataLength` if the length of `data` is not a multiple of the
+            /// block size.
+            pub fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+                let mut iv = self.iv;
+                cbc_decrypt(&mut iv, data, |block| self.decrypt_block(block))?;
+                self.iv = iv;
+
+                Ok(())
+            }
+        }
+    };
+}
+
+cipher!(
+    /// Wrapper around libObfuscate's Anubis.
+    Anubis, ANUBIS_DATA, Anubis_set_key, Anubis_encrypt, Anubis_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's Camellia.
+    Camellia, CAMELLIA_DATA, Camellia_set_key, Camellia_encrypt, Camellia_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's CAST-256.
+    Cast256, CAST256_DATA, Cast256_set_key, Cast256_encrypt, Cast256_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's CLEFIA.
+    Clefia, CLEFIA_DATA, Clefia_set_key, Clefia_encrypt, Clefia_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's FROG.
+    Frog, FROG_DATA, Frog_set_key, Frog_encrypt, Frog_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's Hierocrypt-3.
+    Hierocrypt3, HIEROCRYPT3_DATA, Hierocrypt3_set_key, Hierocrypt3_encrypt, Hierocrypt3_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's IDEA NXT 128.
+    IdeaNxt128, IDEANXT128_DATA, IdeaNxt128_set_key, IdeaNxt128_encrypt, IdeaNxt128_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's MARS.
+    Mars, MARS_DATA, Mars_set_key, Mars_encrypt, Mars_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's RC6.
+    Rc6, RC6_DATA, Rc6_set_key, Rc6_encrypt, Rc6_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's Rijndael.
+    Rijndael, RIJNDAEL_DATA, Rijndael_set_key, Rijndael_encrypt, Rijndael_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's SAFER+.
+    SaferPlus, SAFERPLUS_DATA, SaferPlus_set_key, SaferPlus_encrypt, SaferPlus_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's SC2000.
+    Sc2000, SC2000_DATA, Sc2000_set_key, Sc2000_encrypt, Sc2000_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's Serpent.
+    Serpent, SERPENT_DATA, Serpent_set_key, Serpent_encrypt, Serpent_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's SPEED.
+    Speed, SPEED_DATA, Speed_set_key, Speed_encrypt, Speed_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's Twofish.
+    Twofish, TWOFISH_DATA, Twofish_set_key, Twofish_encrypt, Twofish_decrypt
+);
+cipher!(
+    /// Wrapper around libObfuscate's Unicorn-A.
+    UnicornA, UNICORNA_DATA, UnicornA_set_key, UnicornA_encrypt, UnicornA_decrypt
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BLOCK_SIZE;
+    use std::collections::HashSet;
+
+    const PLAINTEXT: [u8; 32] = [51u8; 32];
+
+    macro_rules! cipher_test {
+        ($test:ident, $name:ident) => {
+            #[test]
+            fn $test() {
+                let iv = [7u8; BLOCK_SIZE];
+
+                let mut buffer = PLAINTEXT;
+                $name::new("testpass1", &iv)
+                    .unwrap()
+                    .encrypt(&mut buffer)
+                    .unwrap();
+                assert_ne!(buffer, PLAINTEXT);
+                // CBC mode: identical plaintext blocks give different ciphertext blocks.
+                assert_ne!(buffer[..16], buffer[16..]);
+
+                $name::new("testpass1", &iv)
+                    .unwrap()
+                    .decrypt(&mut buffer)
+                    .unwrap();
+                assert_eq!(buffer, PLAINTEXT);
+
+                let mut unaligned = [0u8; 17];
+                assert!(matches!(
+                    $name::new("testpass1", &iv)
+                        .unwrap()
+                        .encrypt(&mut unaligned),
+                    Err(Error::InvalidDataLength)
+                ));
+            }
+        };
+    }
+
+    // The empty password is an all-zero 256-bit key. With a zero IV, CBC encrypts the first block
+    // like ECB does: the expected ciphertexts are the published vectors for an all-zero key and
+    // plaintext.
+    macro_rules! known_answer_test {
+        ($test:ident, $name:ident, $expected:expr) => {
+            #[test]
+            fn $test() {
+                let mut block = [0u8; BLOCK_SIZE];
+                $name::new("", &[0; BLOCK_SIZE])
+                    .unwrap()
+                    .encrypt(&mut block)
+                    .unwrap();
+                assert_eq!(block, $expected);
+
+                $name::new("", &[0; BLOCK_SIZE])
+                    .unwrap()
+                    .decrypt(&mut block)
+                    .unwrap();
+                assert_eq!(block, [0; BLOCK_SIZE]);
+            }
+        };
+    }
+
+    // Camellia-256
+    known_answer_test!(
+        camellia_known_answer,
+        Camellia,
+        *b"\x39\x61\x54\x11\x1a\xde\xfc\x50\x0c\xf6\xe5\xc9\x90\x38\xbc\x17"
+    );
+    // CAST-256
+    known_answer_test!(
+        cast256_known_answer,
+        Cast256,
+        *b"\x31\xc2\x55\xaa\x87\x42\x02\xeb\xa0\xd7\x14\xe9\xb5\x33\x07\xf6"
+    );
+    // RC6-32/20/32
+    known_answer_test!(
+        rc6_known_answer,
+        Rc6,
+        *b"\x8f\x5f\xbd\x05\x10\xd1\x5f\xa8\x93\xfa\x3f\xda\x6e\x85\x7e\xc2"
+    );
+    // AES-256
+    known_answer_test!(
+        rijndael_known_answer,
+        Rijndael,
+        *b"\xdc\x95\xc0\x78\xa2\x40\x89\x89\xad\x48\xa2\x14\x92\x84\x20\x87"
+    );
+    // Serpent-256, in NESSIE byte order
+    known_answer_test!(
+        serpent_known_answer,
+        Serpent,
+        *b"\x49\x67\x2b\xa8\x98\xd9\x8d\xf9\x50\x19\x18\x04\x45\x49\x10\x89"
+    );
+    // Twofish-256
+    known_answer_test!(
+        twofish_known_answer,
+        Twofish,
+        *b"\x57\xff\x73\x9d\x4d\xc9\x2c\x1b\xd7\xfc\x01\x70\x0c\xc8\x21\x6f"
+    );
+
+    cipher_test!(anubis, Anubis);
+    cipher_test!(camellia, Camellia);
+    cipher_test!(cast256, Cast256);
+    cipher_test!(clefia, Clefia);
+    cipher_test!(frog, Frog);
+    cipher_test!(hierocrypt3, Hierocrypt3);
+    cipher_test!(idea_nxt128, IdeaNxt128);
+    cipher_test!(mars, Mars);
+    cipher_test!(rc6, Rc6);
+    cipher_test!(rijndael, Rijndael);
+    cipher_test!(saferp, SaferPlus);
+    cipher_test!(sc2000, Sc2000);
+    cipher_test!(serpent, Serpent);
+    cipher_test!(speed, Speed);
+    cipher_test!(twofish, Twofish);
+    cipher_test!(unicorn_a, UnicornA);
+
+    #[test]
+    fn distinct_ciphers() {
+        // Each wrapper must be bound to its own primitive, with distinct encryption and
+        // decryption functions.
+        macro_rules! zero_block_outputs {
+            ($($name:ident),*) => {
+                [$({
+                    let mut cipher = $name::new("", &[0; BLOCK_SIZE]).unwrap();
+                    let mut encrypted = [0u8; BLOCK_SIZE];
+                    cipher.encrypt_block(&mut encrypted);
+                    let mut decrypted = [0u8; BLOCK_SIZE];
+                    cipher.decrypt_block(&mut decrypted);
+                    (encrypted, decrypted)
+                }),*]
+            };
+        }
+
+        let outputs = zero_block_outputs!(
+            Anubis,
+            Camellia,
+            Cast256,
+            Clefia,
+            Frog,
+            Hierocrypt3,
+            IdeaNxt128,
+            Mars,
+            Rc6,
+            Rijndael,
+            SaferPlus,
+            Sc2000,
+            Serpent,
+            Speed,
+            Twofish,
+            UnicornA
+        );
+
+        let mut blocks = HashSet::new();
+        for (encrypted, decrypted) in outputs {
+            assert_ne!(encrypted, decrypted);
+            assert!(blocks.insert(encrypted));
+            assert!(blocks.insert(decrypted));
+        }
+    }
+
+    #[test]
+    fn cbc_chaining() {
+        let iv = [7u8; BLOCK_SIZE];
+
+        // Encrypting in two calls chains the blocks like a single call would.
+        let mut whole = PLAINTEXT;
+        Serpent::new("testpass1", &iv)
+            .unwrap()
+            .encrypt(&mut whole)
+            .unwrap();
+
+        let mut split = PLAINTEXT;
+        let mut serpent = Serpent::new("testpass1", &iv).unwrap();
+        serpent.encrypt(&mut split[..16]).unwrap();
+        serpent.encrypt(&mut split[16..]).unwrap();
+
+        assert_eq!(whole, split);
+    }
+}
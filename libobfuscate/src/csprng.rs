@@ -17,8 +17,9 @@
 use std::{mem, ptr};
 
 use crate::bindings::*;
-use crate::{to_password_buffer, Error};
+use crate::{buffer_length, to_password_buffer, Error};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Hash {
     Sha512,
     Grostl512,
@@ -27,6 +28,9 @@ pub enum Hash {
 }
 
 /// Wrapper around libObfuscate's `CSPRNG_DATA`
+///
+/// Cloning a `Csprng` forks the stream: both instances produce the same subsequent outputs.
+#[derive(Clone)]
 pub struct Csprng(CSPRNG_DATA);
 
 impl Csprng {
@@ -43,12 +47,29 @@ impl Csprng {
 
     /// Creates a new `Csprng` seeded using `password`, `nonce` and `hash`
     pub fn new_with_seed(hash: Hash, password: &str, nonce: u32) -> Result<Self, Error> {
-        if password.len() > MAX_PASSW_SIZE as usize {
-            return Err(Error::PasswordTooLong);
-        }
-        let password = to_password_buffer(password)?;
-
         let mut csprng = Csprng(unsafe { mem::zeroed() });
+        csprng.reseed(hash, password, nonce)?;
+
+        Ok(csprng)
+    }
+
+    /// Creates a new `Csprng` whose outputs only depend on `seed`, for tests and reproducible
+    /// outputs.
+    ///
+    /// Unlike `new`, nothing is random: anyone knowing `seed` can predict the outputs, so this
+    /// must never be used where unpredictability matters. libObfuscate can only be seeded with a
+    /// password, so this is `new_with_seed` with a password and a nonce derived from `seed`.
+    pub fn new_deterministic(seed: u64) -> Self {
+        let password = format!("deterministic:{seed:016x}");
+        let nonce = (seed >> 32) as u32 ^ seed as u32;
+
+        // The password is ASCII and shorter than `MAX_PASSW_SIZE`.
+        Self::new_with_seed(Hash::Sha512, &password, nonce).unwrap()
+    }
+
+    /// Reseeds the `Csprng` using `password`, `nonce` and `hash`, discarding its current state.
+    pub fn reseed(&mut self, hash: Hash, password: &str, nonce: u32) -> Result<(), Error> {
+        let password = to_password_buffer(password)?;
 
         let hash = match hash {
             Hash::Sha512 => ENUM_HASH_SHA512_HASH,
@@ -59,14 +80,14 @@ impl Csprng {
 
         unsafe {
             CSPRNG_set_seed(
-                &mut csprng.0 as *mut CSPRNG_DATA,
+                &mut self.0 as *mut CSPRNG_DATA,
                 hash,
                 mem::transmute(password.as_ptr()),
                 nonce,
             );
         }
 
-        Ok(csprng)
+        Ok(())
     }
 
     /// Returns a cryptographically-secure random byte.
@@ -86,11 +107,11 @@ impl Csprng {
 
     /// Randomizes `buffer`.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the length of `buffer` doesn't fit in a `u32`.
-    pub fn randomize(&mut self, buffer: &mut [u8]) {
-        let len = u32::try_from(buffer.len()).unwrap();
+    /// Returns `Error::DataTooLarge` if the length of `buffer` doesn't fit in a `u32`.
+    pub fn randomize(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        let len = buffer_length(buffer.len())?;
 
         unsafe {
             CSPRNG_randomize(
@@ -103,6 +124,30 @@ impl Csprng {
                 ptr::null_mut(),
             );
         }
+
+        Ok(())
+    }
+
+    /// Returns `n` cryptographically-secure random bytes.
+    pub fn random_bytes(&mut self, n: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; n];
+
+        // `randomize` only accepts buffers whose length fits in a `u32`
+        for chunk in bytes.chunks_mut(u32::MAX as usize) {
+            self.randomize(chunk).unwrap();
+        }
+
+        bytes
+    }
+
+    /// Returns `n` cryptographically-secure random dwords.
+    pub fn random_dwords(&mut self, n: usize) -> Vec<u32> {
+        (0..n).map(|_| self.get_dword()).collect()
+    }
+
+    /// Returns an infinite iterator over cryptographically-secure random bytes.
+    pub fn byte_iter(&mut self) -> impl Iterator<Item = u8> + '_ {
+        std::iter::repeat_with(|| self.get_byte())
     }
 
     /// Initializes `buffer` as a permutation.
@@ -128,7 +173,7 @@ mod tests {
         let mut csprng = Csprng::new_with_seed(Hash::Sha512, "password", 0x1234).unwrap();
 
         let mut data = [0u8; 32];
-        csprng.randomize(&mut data);
+        csprng.randomize(&mut data).unwrap();
 
         assert_eq!(
             data,
@@ -138,4 +183,69 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn random_bytes() {
+        let mut csprng = Csprng::new_with_seed(Hash::Sha512, "password", 0x1234).unwrap();
+        let mut data = [0u8; 32];
+        csprng.randomize(&mut data).unwrap();
+
+        let mut csprng = Csprng::new_with_seed(Hash::Sha512, "password", 0x1234).unwrap();
+        assert_eq!(csprng.random_bytes(32), data);
+    }
+
+    #[test]
+    fn random_dwords_and_bytes_iterator() {
+        let mut csprng = Csprng::new_with_seed(Hash::Grostl512, "password", 7).unwrap();
+        let mut fork = csprng.clone();
+
+        let dwords = csprng.random_dwords(4);
+        assert_eq!(dwords.len(), 4);
+        assert!(dwords.iter().all(|&dword| dword == fork.get_dword()));
+
+        let bytes: Vec<u8> = csprng.byte_iter().take(8).collect();
+        assert!(bytes.iter().all(|&byte| byte == fork.get_byte()));
+    }
+
+    #[test]
+    fn reseed() {
+        let mut reseeded = Csprng::new_with_seed(Hash::Keccak512, "other", 0).unwrap();
+        reseeded.get_dword();
+        reseeded.reseed(Hash::Sha512, "password", 0x1234).unwrap();
+
+        let mut csprng = Csprng::new_with_seed(Hash::Sha512, "password", 0x1234).unwrap();
+
+        for _ in 0..16 {
+            assert_eq!(reseeded.get_dword(), csprng.get_dword());
+        }
+    }
+
+    #[test]
+    fn deterministic_seed() {
+        let mut csprng = Csprng::new_deterministic(2023);
+        let mut same_seed = Csprng::new_deterministic(2023);
+        assert_eq!(csprng.random_bytes(64), same_seed.random_bytes(64));
+        assert_eq!(csprng.random_dwords(16), same_seed.random_dwords(16));
+
+        let mut other_seed = Csprng::new_deterministic(2024);
+        let mut csprng = Csprng::new_deterministic(2023);
+        assert_ne!(csprng.random_bytes(64), other_seed.random_bytes(64));
+
+        let mut large_seed = Csprng::new_deterministic(u64::MAX);
+        assert_eq!(
+            large_seed.random_bytes(16),
+            Csprng::new_deterministic(u64::MAX).random_bytes(16)
+        );
+    }
+
+    #[test]
+    fn clone() {
+        let mut csprng = Csprng::new_with_seed(Hash::Skein512, "password", 42).unwrap();
+        csprng.get_dword();
+
+        let mut fork = csprng.clone();
+        for _ in 0..16 {
+            assert_eq!(fork.get_dword(), csprng.get_dword());
+        }
+    }
 }
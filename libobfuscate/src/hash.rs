@@ -0,0 +1,185 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use std::mem;
+use std::os::raw::c_void;
+
+use crate::bindings::*;
+pub use crate::csprng::Hash;
+
+/// Size, in bytes, of the digests produced by every hash.
+pub const DIGEST_SIZE: usize = 64;
+
+/// Incremental hasher, wrapping the state of one of libObfuscate's hashes.
+pub enum Hasher {
+    Sha512(SHA512_DATA),
+    Grostl512(GROSTL512_DATA),
+    Keccak512(KECCAK512_DATA),
+    Skein512(SKEIN512_DATA),
+}
+
+impl Hasher {
+    /// Creates a new hasher using `hash`.
+    pub fn new(hash: Hash) -> Self {
+        unsafe {
+            match hash {
+                Hash::Sha512 => {
+                    let mut data: SHA512_DATA = mem::zeroed();
+                    Sha512_init(&mut data as *mut SHA512_DATA);
+                    Hasher::Sha512(data)
+                }
+                Hash::Grostl512 => {
+                    let mut data: GROSTL512_DATA = mem::zeroed();
+                    Grostl512_init(&mut data as *mut GROSTL512_DATA);
+                    Hasher::Grostl512(data)
+                }
+                Hash::Keccak512 => {
+                    let mut data: KECCAK512_DATA = mem::zeroed();
+                    Keccak512_init(&mut data as *mut KECCAK512_DATA);
+                    Hasher::Keccak512(data)
+                }
+                Hash::Skein512 => {
+                    let mut data: SKEIN512_DATA = mem::zeroed();
+                    Skein512_init(&mut data as *mut SKEIN512_DATA);
+                    Hasher::Skein512(data)
+                }
+            }
+        }
+    }
+
+    /// Feeds `data` to the hasher.
+    pub fn update(&mut self, data: &[u8]) {
+        // The C functions take the length as a `DWORD`
+        for chunk in data.chunks(u32::MAX as usize) {
+            let buffer = chunk.as_ptr() as *const c_void;
+            let len = chunk.len() as u32;
+
+            unsafe {
+                match self {
+                    Hasher::Sha512(data) => Sha512_data(data as *mut SHA512_DATA, buffer, len),
+                    Hasher::Grostl512(data) => {
+                        Grostl512_data(data as *mut GROSTL512_DATA, buffer, len)
+                    }
+                    Hasher::Keccak512(data) => {
+                        Keccak512_data(data as *mut KECCAK512_DATA, buffer, len)
+                    }
+                    Hasher::Skein512(data) => {
+                        Skein512_data(data as *mut SKEIN512_DATA, buffer, len)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Consumes the hasher and returns the digest of the data fed to it.
+    pub fn finalize(mut self) -> Vec<u8> {
+        let mut digest = vec![0u8; DIGEST_SIZE];
+        let output = digest.as_mut_ptr();
+
+        unsafe {
+            match &mut self {
+                Hasher::Sha512(data) => Sha512_finalize(data as *mut SHA512_DATA, output),
+                Hasher::Grostl512(data) => Grostl512_finalize(data as *mut GROSTL512_DATA, output),
+                Hasher::Keccak512(data) => Keccak512_finalize(data as *mut KECCAK512_DATA, output),
+                Hasher::Skein512(data) => Skein512_finalize(data as *mut SKEIN512_DATA, output),
+            }
+        }
+
+        digest
+    }
+}
+
+/// Returns the digest of `data` using `hash`.
+pub fn digest(hash: Hash, data: &[u8]) -> Vec<u8> {
+    let mut hasher = Hasher::new(hash);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sha512() {
+        assert_eq!(
+            digest(Hash::Sha512, b"abc"),
+            from_hex(
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+            )
+        );
+    }
+
+    #[test]
+    fn grostl512() {
+        assert_eq!(
+            digest(Hash::Grostl512, b""),
+            from_hex(
+                "6d3ad29d279110eef3adbd66de2a0345a77baede1557f5d099fce0c03d6dc2ba\
+                 8e6d4a6633dfbd66053c20faa87d1a11f39a7fbe4a6c2f009801370308fc4ad8"
+            )
+        );
+    }
+
+    #[test]
+    fn keccak512() {
+        assert_eq!(
+            digest(Hash::Keccak512, b""),
+            from_hex(
+                "0eab42de4c3ceb9235fc91acffe746b29c29a8c366b7c60e4e67c466f36a4304\
+                 c00fa9caf9d87976ba469bcbe06713b435f091ef2769fb160cdab33d3670680e"
+            )
+        );
+    }
+
+    #[test]
+    fn skein512() {
+        assert_eq!(
+            digest(Hash::Skein512, b""),
+            from_hex(
+                "bc5b4c50925519c290cc634277ae3d6257212395cba733bbad37a4af0fa06af4\
+                 1fca7903d06564fea7a2d3730dbdb80c1f85562dfcc070334ea4d1d9e72cba7a"
+            )
+        );
+    }
+
+    #[test]
+    fn incremental() {
+        for hash in [
+            Hash::Sha512,
+            Hash::Grostl512,
+            Hash::Keccak512,
+            Hash::Skein512,
+        ] {
+            let mut hasher = Hasher::new(hash);
+            hasher.update(b"Libre");
+            hasher.update(b"");
+            hasher.update(b"Puff");
+
+            let digest = digest(hash, b"LibrePuff");
+            assert_eq!(digest.len(), DIGEST_SIZE);
+            assert_eq!(hasher.finalize(), digest);
+        }
+    }
+}
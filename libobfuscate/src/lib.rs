@@ -22,31 +22,114 @@ mod bindings {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+use std::error;
 use std::ffi::{CString, NulError};
+use std::fmt::{self, Display};
 
 #[derive(Debug)]
 pub enum Error {
     PasswordTooLong,
     ContainsNulByte,
+    /// The length of a buffer doesn't fit in a `u32`.
+    DataTooLarge,
+    /// The length of a buffer isn't a multiple of the block size.
+    InvalidDataLength,
 }
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PasswordTooLong => write!(f, "password is too long"),
+            Self::ContainsNulByte => write!(f, "password contains a nul byte"),
+            Self::DataTooLarge => write!(f, "data is too large"),
+            Self::InvalidDataLength => {
+                write!(f, "data length is not a multiple of the block size")
+            }
+        }
+    }
+}
+// None of the variants wraps another error.
+impl error::Error for Error {}
 impl From<NulError> for Error {
     fn from(_value: NulError) -> Self {
         Error::ContainsNulByte
     }
 }
 
-/// Returns a password buffer from a string slice.
+/// Maximum length of a password, in bytes.
+pub const MAX_PASSWORD_LENGTH: usize = bindings::MAX_PASSW_SIZE as usize;
+/// Size of a block of the ciphers, in bytes.
+pub const BLOCK_SIZE: usize = bindings::DATA_BLOCK_SIZE as usize;
+/// Number of ciphers combined by `multi`.
+pub const ALGORITHM_COUNT: usize = bindings::MAX_ALG as usize;
+
+/// Returns a password buffer from a string slice, zero-padded to `MAX_PASSWORD_LENGTH` bytes.
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics if `password.len() >= MAX_PASSW_SIZE`
+/// Returns `Error::PasswordTooLong` if `password` is longer than `MAX_PASSWORD_LENGTH` bytes,
+/// and `Error::ContainsNulByte` if it contains a nul byte.
 fn to_password_buffer(password: &str) -> Result<Vec<u8>, Error> {
+    if password.len() > MAX_PASSWORD_LENGTH {
+        return Err(Error::PasswordTooLong);
+    }
+
     let password = CString::new(password)?;
     let mut password = Vec::from(password.as_bytes());
-    password.resize(bindings::MAX_PASSW_SIZE as usize, 0);
+    password.resize(MAX_PASSWORD_LENGTH, 0);
     Ok(password)
 }
 
+/// Returns the length of a buffer, as expected by libObfuscate.
+fn buffer_length(len: usize) -> Result<u32, Error> {
+    u32::try_from(len).map_err(|_| Error::DataTooLarge)
+}
+
+pub mod cipher;
 pub mod csprng;
+pub mod hash;
 pub mod multi;
 pub mod scramble;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_buffers() {
+        let password = "a".repeat(MAX_PASSWORD_LENGTH);
+        assert_eq!(to_password_buffer(&password).unwrap(), password.as_bytes());
+
+        let mut buffer = vec![0; MAX_PASSWORD_LENGTH];
+        buffer[..4].copy_from_slice(b"test");
+        assert_eq!(to_password_buffer("test").unwrap(), buffer);
+
+        let password = "a".repeat(MAX_PASSWORD_LENGTH + 1);
+        assert!(matches!(
+            to_password_buffer(&password),
+            Err(Error::PasswordTooLong)
+        ));
+        assert!(matches!(
+            to_password_buffer("te\0st"),
+            Err(Error::ContainsNulByte)
+        ));
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(MAX_PASSWORD_LENGTH, 32);
+        assert_eq!(BLOCK_SIZE, 16);
+        assert_eq!(ALGORITHM_COUNT, 16);
+    }
+
+    #[test]
+    fn buffer_lengths() {
+        assert_eq!(buffer_length(0).unwrap(), 0);
+        assert_eq!(buffer_length(u32::MAX as usize).unwrap(), u32::MAX);
+
+        #[cfg(target_pointer_width = "64")]
+        assert!(matches!(
+            buffer_length(u32::MAX as usize + 1),
+            Err(Error::DataTooLarge)
+        ));
+    }
+}
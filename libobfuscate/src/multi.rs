@@ -17,10 +17,10 @@
 use std::{mem, ptr};
 
 use crate::bindings::*;
-use crate::{to_password_buffer, Error};
+use crate::{buffer_length, to_password_buffer, Error, ALGORITHM_COUNT, BLOCK_SIZE};
 
 /// Initialization vector
-pub type Iv = [u8; DATA_BLOCK_SIZE as usize];
+pub type Iv = [u8; BLOCK_SIZE];
 
 /// Initialization vectors for different cryptographic primitives
 #[derive(Default, Debug, Copy, Clone)]
@@ -44,36 +44,51 @@ pub struct Ivs {
     pub unicorn_a: Iv,
 }
 
+/// Size of `Ivs`, in bytes.
+pub const IVS_SIZE: usize = ALGORITHM_COUNT * BLOCK_SIZE;
+
+// `Ivs` is reinterpreted as a byte array, here and by libObfuscate: its fields must be laid out in
+// order, without padding. `#[repr(C)]` keeps them in order, and byte arrays have no alignment
+// requirement, so there is no padding. Being bytes, IVs don't depend on endianness.
+const _: () = assert!(mem::size_of::<Ivs>() == IVS_SIZE);
+const _: () = assert!(mem::align_of::<Ivs>() == 1);
+
 impl Ivs {
-    pub fn from_bytes(source: &[u8; (MAX_ALG * DATA_BLOCK_SIZE) as usize]) -> &Ivs {
+    /// Reinterprets `source` as the IVs of the 16 primitives, in the order of the fields.
+    pub fn from_bytes(source: &[u8; IVS_SIZE]) -> &Ivs {
+        // SAFETY: `Ivs` is made of `IVS_SIZE` bytes without padding and has an alignment of 1
+        // (see the assertions above), and any byte is a valid value for its fields.
         unsafe { mem::transmute(source) }
     }
-    pub fn as_bytes(&self) -> &[u8; (MAX_ALG * DATA_BLOCK_SIZE) as usize] {
+    /// Reinterprets the IVs as bytes, in the order of the fields.
+    pub fn as_bytes(&self) -> &[u8; IVS_SIZE] {
+        // SAFETY: see `from_bytes`.
         unsafe { mem::transmute(self) }
     }
 }
 
 /// Wrapper around libObfuscate's `MULTI_DATA`
 ///
-/// The object's state cannot be reset. As a result, calling `decrypt` after having called
-/// `encrypt` won't give back the original data.
-pub struct Multi(MULTI_DATA);
+/// The object's state changes when data is encrypted or decrypted. As a result, calling `decrypt`
+/// after having called `encrypt` won't give back the original data, unless `reset` is called in
+/// between.
+pub struct Multi {
+    data: MULTI_DATA,
+    /// State of `data` right after the keys were set.
+    initial_data: MULTI_DATA,
+}
 
 impl Multi {
     /// Creates a new `Multi`.
     pub fn new(ivs: &Ivs, password_1: &str, password_2: &str, nonce: u32) -> Result<Self, Error> {
-        let max_length = MAX_PASSW_SIZE as usize;
-        if password_1.len() > max_length || password_2.len() > max_length {
-            return Err(Error::PasswordTooLong);
-        }
         let password_1 = to_password_buffer(password_1)?;
         let password_2 = to_password_buffer(password_2)?;
 
-        let mut multi = Multi(unsafe { mem::zeroed() });
+        let mut data: MULTI_DATA = unsafe { mem::zeroed() };
 
         unsafe {
             Multi_setkey(
-                &mut multi.0 as *mut MULTI_DATA,
+                &mut data as *mut MULTI_DATA,
                 ivs.as_bytes().as_ptr(),
                 password_1.as_ptr(),
                 password_2.as_ptr(),
@@ -81,20 +96,32 @@ impl Multi {
             );
         }
 
-        Ok(multi)
+        Ok(Multi {
+            data,
+            initial_data: data,
+        })
+    }
+
+    /// Resets the object to the state it had after its creation, without computing the key
+    /// schedules again.
+    ///
+    /// `MULTI_DATA` is plain data (libObfuscate doesn't provide any function to release it), so
+    /// a copy of it made after the keys were set can be restored.
+    pub fn reset(&mut self) {
+        self.data = self.initial_data;
     }
 
     /// Encrypts `data`.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the length of `data` does not fit in a `u32`.
-    pub fn encrypt(&mut self, data: &mut [u8]) {
-        let len = u32::try_from(data.len()).unwrap();
+    /// Returns `Error::DataTooLarge` if the length of `data` does not fit in a `u32`.
+    pub fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        let len = buffer_length(data.len())?;
 
         unsafe {
             Multi_CBC_encrypt(
-                &mut self.0 as *mut MULTI_DATA,
+                &mut self.data as *mut MULTI_DATA,
                 len,
                 data.as_mut_ptr(),
                 None,
@@ -103,19 +130,21 @@ impl Multi {
                 ptr::null_mut(),
             );
         }
+
+        Ok(())
     }
 
     /// Decrypts `data`.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the length of `data` does not fit in a `u32`.
-    pub fn decrypt(&mut self, data: &mut [u8]) {
-        let len = u32::try_from(data.len()).unwrap();
+    /// Returns `Error::DataTooLarge` if the length of `data` does not fit in a `u32`.
+    pub fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        let len = buffer_length(data.len())?;
 
         unsafe {
             Multi_CBC_decrypt(
-                &mut self.0 as *mut MULTI_DATA,
+                &mut self.data as *mut MULTI_DATA,
                 len,
                 data.as_mut_ptr(),
                 None,
@@ -124,6 +153,8 @@ impl Multi {
                 ptr::null_mut(),
             );
         }
+
+        Ok(())
     }
 }
 
@@ -136,8 +167,7 @@ pub fn encrypt(
     nonce: u32,
 ) -> Result<(), Error> {
     let mut multi = Multi::new(ivs, password_1, password_2, nonce)?;
-    multi.encrypt(data);
-    Ok(())
+    multi.encrypt(data)
 }
 
 /// Decrypts `data`.
@@ -149,14 +179,25 @@ pub fn decrypt(
     nonce: u32,
 ) -> Result<(), Error> {
     let mut multi = Multi::new(ivs, password_1, password_2, nonce)?;
-    multi.decrypt(data);
-    Ok(())
+    multi.decrypt(data)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn ivs_layout() {
+        assert_eq!(mem::size_of::<Ivs>(), 256);
+
+        let bytes: [u8; IVS_SIZE] = std::array::from_fn(|i| i as u8);
+        let ivs = Ivs::from_bytes(&bytes);
+        assert_eq!(ivs.anubis[0], 0);
+        assert_eq!(ivs.camellia[0], 16);
+        assert_eq!(ivs.unicorn_a[15], 255);
+        assert_eq!(ivs.as_bytes(), &bytes);
+    }
+
     #[test]
     fn encrypt_decrypt() {
         let mut buffer = [51u8; 32];
@@ -174,4 +215,25 @@ mod tests {
         decrypt(&mut buffer, &ivs, "testpass1", "password2", 2023).unwrap();
         assert_eq!(buffer, [51u8; 32]);
     }
+
+    #[test]
+    fn reset() {
+        let ivs = Default::default();
+        let mut multi = Multi::new(&ivs, "testpass1", "password2", 2023).unwrap();
+
+        let mut first_buffer = [51u8; 32];
+        multi.encrypt(&mut first_buffer).unwrap();
+        multi.reset();
+        let mut second_buffer = [51u8; 32];
+        multi.encrypt(&mut second_buffer).unwrap();
+
+        let mut expected_buffer = [51u8; 32];
+        encrypt(&mut expected_buffer, &ivs, "testpass1", "password2", 2023).unwrap();
+        assert_eq!(first_buffer, expected_buffer);
+        assert_eq!(second_buffer, expected_buffer);
+
+        multi.reset();
+        multi.decrypt(&mut second_buffer).unwrap();
+        assert_eq!(second_buffer, [51u8; 32]);
+    }
 }
@@ -17,7 +17,7 @@
 use std::{mem, ptr};
 
 use crate::bindings::*;
-use crate::{to_password_buffer, Error};
+use crate::{buffer_length, to_password_buffer, Error};
 
 /// Wrapper around libObfuscate's `SCRAMBLE_DATA`.
 pub struct Scramble {
@@ -28,14 +28,12 @@ pub struct Scramble {
 impl Scramble {
     /// Creates a new `Scramble`.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if `block_size` does not fit in a `u32`.
+    /// Returns `Error::DataTooLarge` if `block_size` does not fit in a `u32`.
     pub fn new(block_size: usize, password: &str, nonce: u32) -> Result<Self, Error> {
-        if password.len() > MAX_PASSW_SIZE as usize {
-            return Err(Error::PasswordTooLong);
-        }
         let password = to_password_buffer(password)?;
+        let length = buffer_length(block_size)?;
 
         let mut scramble = Scramble {
             data: unsafe { mem::zeroed() },
@@ -45,7 +43,7 @@ impl Scramble {
         unsafe {
             Scramble_seed(
                 &mut scramble.data as *mut SCRAMBLE_DATA,
-                block_size.try_into().unwrap(),
+                length,
                 password.as_ptr(),
                 nonce,
             );
@@ -93,6 +91,34 @@ impl Scramble {
             );
         }
     }
+
+    /// Scrambles each of the consecutive blocks making up `data`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `data` is not a multiple of the block size specified during
+    /// construction.
+    pub fn scramble_blocks(&mut self, data: &mut [u8]) {
+        assert_eq!(data.len() % self.block_size, 0);
+
+        for block in data.chunks_exact_mut(self.block_size) {
+            self.scramble(block);
+        }
+    }
+
+    /// Descrambles each of the consecutive blocks making up `data`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `data` is not a multiple of the block size specified during
+    /// construction.
+    pub fn descramble_blocks(&mut self, data: &mut [u8]) {
+        assert_eq!(data.len() % self.block_size, 0);
+
+        for block in data.chunks_exact_mut(self.block_size) {
+            self.descramble(block);
+        }
+    }
 }
 
 impl Drop for Scramble {
@@ -131,4 +157,32 @@ mod tests {
         scrambler.descramble(&mut data);
         assert_eq!(data, TEST_ARRAY);
     }
+    #[test]
+    fn block_scrambling() {
+        const TEST_ARRAY: [u8; 30] = [
+            1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+            25, 26, 27, 28, 29, 30,
+        ];
+
+        let mut data = TEST_ARRAY;
+        let mut scrambler = Scramble::new(10, "testpassword1", 13).unwrap();
+        scrambler.scramble_blocks(&mut data);
+
+        let mut expected_data = TEST_ARRAY;
+        let mut block_scrambler = Scramble::new(10, "testpassword1", 13).unwrap();
+        for block in expected_data.chunks_mut(10) {
+            block_scrambler.scramble(block);
+        }
+        assert_eq!(data, expected_data);
+
+        scrambler.descramble_blocks(&mut data);
+        assert_eq!(data, TEST_ARRAY);
+    }
+
+    #[test]
+    #[should_panic]
+    fn partial_block_scrambling() {
+        let mut scrambler = Scramble::new(10, "testpassword1", 13).unwrap();
+        scrambler.scramble_blocks(&mut [0; 15]);
+    }
 }
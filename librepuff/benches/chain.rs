@@ -0,0 +1,61 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use bit_vec::BitVec;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use librepuff::carrier::EncryptedCarrier;
+use librepuff::chain;
+use librepuff::passwords::Passwords;
+
+/// Returns `count` carriers holding `size` bytes of data and decoy each.
+fn carriers(count: usize, size: usize) -> Vec<EncryptedCarrier> {
+    (0..count)
+        .map(|i| EncryptedCarrier {
+            iv: [i as u8; 256],
+            data: vec![0x55; size],
+            decoy: vec![0xaa; size],
+            other_bits: BitVec::new(),
+            warnings: Vec::new(),
+        })
+        .collect()
+}
+
+fn decrypt_carrier_chain(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decrypt_carrier_chain");
+    group.sample_size(10);
+
+    for count in [1, 16, 64] {
+        let carriers = carriers(count, 1 << 16);
+
+        group.bench_with_input(BenchmarkId::new("sequential", count), &carriers, |b, carriers| {
+            b.iter(|| {
+                let passwords = Passwords::from_fields("password", None, None).unwrap();
+                chain::decrypt_carrier_chain(carriers.clone(), passwords)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", count), &carriers, |b, carriers| {
+            b.iter(|| {
+                let passwords = Passwords::from_fields("password", None, None).unwrap();
+                chain::decrypt_carrier_chain_parallel(carriers.clone(), passwords)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, decrypt_carrier_chain);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Benchmarks of the steps of an extraction, up to the decryption (see `chain.rs`).
+//!
+//! Run with `cargo bench -p librepuff --bench extraction`. The parser benchmarks need the
+//! `fuzzing` feature, which exposes the parsers: `cargo bench -p librepuff --features fuzzing`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use librepuff::bit_selection::BitSelection;
+use librepuff::carrier;
+use librepuff::carrier_type::CarrierType;
+use librepuff::crc32;
+use librepuff::test_utils;
+
+/// Returns a 16-bit mono PCM WAVE file of `sample_count` samples, all selected by the parser.
+fn wav_file(sample_count: usize) -> Vec<u8> {
+    let samples: Vec<u16> = (0..sample_count).map(|i| 0b1000 | (i as u16 % 7)).collect();
+    test_utils::wav_file(&samples)
+}
+
+fn crc32(c: &mut Criterion) {
+    let data: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+
+    let mut group = c.benchmark_group("crc32");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("compute_1MiB", |b| b.iter(|| crc32::compute(&data)));
+    group.finish();
+}
+
+fn whitening_table(c: &mut Criterion) {
+    // The whitening table is private: it is measured through the smallest carrier usable at the
+    // maximum bit selection level, whose parsing is dominated by the table generation.
+    let wav = wav_file(8000);
+
+    c.bench_function("whitening_table", |b| {
+        b.iter(|| carrier::from_bytes(&wav, CarrierType::Wav, BitSelection::Maximum).unwrap())
+    });
+}
+
+fn carrier_from_bytes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("carrier_from_bytes");
+    group.sample_size(10);
+
+    for sample_count in [1 << 16, 1 << 21] {
+        let wav = wav_file(sample_count);
+
+        group.throughput(Throughput::Bytes(wav.len() as u64));
+        group.bench_with_input(BenchmarkId::new("wav", wav.len()), &wav, |b, wav| {
+            b.iter(|| carrier::from_bytes(wav, CarrierType::Wav, BitSelection::Medium).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+#[cfg(feature = "fuzzing")]
+fn wav_parse(c: &mut Criterion) {
+    use librepuff::parser;
+    use librepuff::wav_options::WavOptions;
+
+    let wav = wav_file(1 << 21);
+
+    let mut group = c.benchmark_group("wav_parse");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(wav.len() as u64));
+    group.bench_function("4MiB", |b| {
+        b.iter(|| parser::wav::parse(&mut &wav[..], &WavOptions::default()).unwrap())
+    });
+    group.finish();
+}
+
+#[cfg(not(feature = "fuzzing"))]
+criterion_group!(benches, crc32, whitening_table, carrier_from_bytes);
+#[cfg(feature = "fuzzing")]
+criterion_group!(
+    benches,
+    crc32,
+    whitening_table,
+    carrier_from_bytes,
+    wav_parse
+);
+criterion_main!(benches);
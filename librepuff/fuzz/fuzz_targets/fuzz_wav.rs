@@ -0,0 +1,38 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use librepuff::parser::wav;
+use librepuff::wav_options::WavOptions;
+
+// The parser must reject malformed files by returning an error, never by panicking.
+fuzz_target!(|data: &[u8]| {
+    let options = [
+        WavOptions::default(),
+        WavOptions {
+            strict: false,
+            placeholder_size_fallback: true,
+            extended_sample_sizes: true,
+            ..Default::default()
+        },
+    ];
+
+    for options in &options {
+        let _ = wav::parse(&mut &data[..], options);
+    }
+});
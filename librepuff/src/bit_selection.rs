@@ -14,8 +14,13 @@
 // You should have received a copy of the GNU General Public License
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::Error;
+
 /// Corresponds to OpenPuff's bit selection level.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BitSelection {
     Minimum,
     VeryLow,
@@ -34,6 +39,19 @@ impl Default for BitSelection {
 }
 
 impl BitSelection {
+    /// Returns every level, from the sparsest to the densest selection.
+    pub fn all() -> [BitSelection; 7] {
+        [
+            Self::Minimum,
+            Self::VeryLow,
+            Self::Low,
+            Self::Medium,
+            Self::High,
+            Self::VeryHigh,
+            Self::Maximum,
+        ]
+    }
+
     /// Returns the density of bits to select, ie. the ratio of selected bits to data bits.
     /// (Or decoy bits).
     pub fn divisor(&self) -> usize {
@@ -47,4 +65,87 @@ impl BitSelection {
             Self::Maximum => 2,
         }
     }
+
+    /// Returns the level whose `divisor()` is `divisor`, if any.
+    pub fn from_divisor(divisor: usize) -> Option<BitSelection> {
+        Self::all()
+            .into_iter()
+            .find(|level| level.divisor() == divisor)
+    }
+}
+
+impl Display for BitSelection {
+    /// Writes the name OpenPuff gives to the level (e.g. `Very Low`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Minimum => "Minimum",
+            Self::VeryLow => "Very Low",
+            Self::Low => "Low",
+            Self::Medium => "Medium",
+            Self::High => "High",
+            Self::VeryHigh => "Very High",
+            Self::Maximum => "Maximum",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for BitSelection {
+    type Err = Error;
+
+    /// Parses the name of a level, as displayed, ignoring case. Spaces may be omitted (e.g.
+    /// `verylow`).
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .into_iter()
+            .find(|level| {
+                let level_name = level.to_string();
+                level_name.eq_ignore_ascii_case(name)
+                    || level_name.replace(' ', "").eq_ignore_ascii_case(name)
+            })
+            .ok_or(Error::UnknownBitSelection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let names = [
+            "minimum", "verylow", "low", "medium", "high", "veryhigh", "maximum",
+        ];
+        for (level, name) in BitSelection::all().into_iter().zip(names) {
+            assert_eq!(name.parse::<BitSelection>().unwrap(), level);
+            assert_eq!(name.to_uppercase().parse::<BitSelection>().unwrap(), level);
+            assert_eq!(level.to_string().parse::<BitSelection>().unwrap(), level);
+        }
+
+        assert_eq!(BitSelection::VeryHigh.to_string(), "Very High");
+        assert_eq!(
+            "very low".parse::<BitSelection>().unwrap(),
+            BitSelection::VeryLow
+        );
+        assert!(matches!(
+            "lowest".parse::<BitSelection>(),
+            Err(Error::UnknownBitSelection)
+        ));
+        assert!("".parse::<BitSelection>().is_err());
+        assert!("very  low".parse::<BitSelection>().is_err());
+    }
+
+    #[test]
+    fn divisors() {
+        let divisors = BitSelection::all().map(|level| level.divisor());
+        assert_eq!(divisors, [8, 7, 6, 5, 4, 3, 2]);
+
+        for level in BitSelection::all() {
+            assert_eq!(BitSelection::from_divisor(level.divisor()), Some(level));
+        }
+        assert_eq!(BitSelection::from_divisor(0), None);
+        assert_eq!(BitSelection::from_divisor(1), None);
+        assert_eq!(BitSelection::from_divisor(9), None);
+    }
 }
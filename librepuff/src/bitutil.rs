@@ -0,0 +1,160 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use bit_vec::BitVec;
+
+/// Packs `bits` into bytes, most significant bit first.
+///
+/// Like OpenPuff, a trailing partial byte is left-aligned: its missing low bits are zeros. There
+/// are `bits.len().div_ceil(8)` bytes.
+pub fn pack_msb_first(bits: &BitVec) -> Vec<u8> {
+    let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+
+    for (i, bit) in bits.iter().enumerate() {
+        if bit {
+            bytes[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+
+    bytes
+}
+
+/// Packs the next `8 * N` bits yielded by `bits` into `N` bytes, most significant bit first, like
+/// `pack_msb_first` without collecting them first.
+///
+/// Returns `None` if `bits` yields fewer bits.
+pub fn pack_array_msb_first<const N: usize>(
+    bits: &mut impl Iterator<Item = bool>,
+) -> Option<[u8; N]> {
+    let mut bytes = [0u8; N];
+
+    for i in 0..8 * N {
+        if bits.next()? {
+            bytes[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+
+    Some(bytes)
+}
+
+/// Unpacks the first `bit_len` bits of `bytes`, most significant bit first.
+///
+/// This is the inverse of `pack_msb_first`: the low bits of the last byte past `bit_len` are
+/// ignored, whatever their value.
+///
+/// # Panics
+///
+/// Panics if `bytes` holds fewer than `bit_len` bits.
+pub fn unpack_msb_first(bytes: &[u8], bit_len: usize) -> BitVec {
+    assert!(
+        bit_len <= 8 * bytes.len(),
+        "{bit_len} bits can't be unpacked from {} bytes",
+        bytes.len()
+    );
+
+    (0..bit_len)
+        .map(|i| bytes[i / 8] & (0x80 >> (i % 8)) != 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_packing() {
+        let pattern: u16 = 0b10110011_10001111;
+
+        for len in 1..=16u32 {
+            let bits: BitVec = (0..len).map(|i| pattern & (0x8000 >> i) != 0).collect();
+
+            let expected = (pattern & !u16::MAX.checked_shr(len).unwrap_or(0)).to_be_bytes();
+            assert_eq!(
+                pack_msb_first(&bits),
+                expected[..(len as usize).div_ceil(8)],
+                "{} bits",
+                len
+            );
+        }
+
+        assert_eq!(pack_msb_first(&BitVec::new()), []);
+    }
+
+    #[test]
+    fn array_packing() {
+        let bits = unpack_msb_first(&[0x12, 0x34, 0x56], 20);
+
+        let mut iter = bits.iter();
+        assert_eq!(pack_array_msb_first(&mut iter), Some([0x12, 0x34]));
+        // Only the bits needed are consumed
+        assert_eq!(iter.len(), 4);
+        assert_eq!(pack_array_msb_first::<1>(&mut iter), None);
+
+        assert_eq!(pack_array_msb_first(&mut bits.iter()), Some([]));
+    }
+
+    #[test]
+    fn bit_unpacking() {
+        assert_eq!(
+            unpack_msb_first(&[0b1011_0000, 0xff], 4),
+            BitVec::from_fn(4, |i| i != 1)
+        );
+        // Bits past the requested length are ignored
+        assert_eq!(
+            unpack_msb_first(&[0b1011_0111], 4),
+            unpack_msb_first(&[0b1011_0000], 4)
+        );
+        assert!(unpack_msb_first(&[], 0).is_empty());
+        assert_eq!(
+            unpack_msb_first(&[0x12, 0x34], 16),
+            BitVec::from_bytes(&[0x12, 0x34])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_unpacking_too_short() {
+        unpack_msb_first(&[0xff], 9);
+    }
+
+    #[test]
+    fn round_trips() {
+        for len in 0..100usize {
+            for seed in 0..8usize {
+                let bits: BitVec = (0..len)
+                    .map(|i| ((i.wrapping_mul(2654435761) >> 7) ^ (seed * i)) % 3 == 0)
+                    .collect();
+
+                let bytes = pack_msb_first(&bits);
+                assert_eq!(bytes.len(), len.div_ceil(8));
+                assert_eq!(unpack_msb_first(&bytes, len), bits, "{len} bits");
+
+                // Only whole bytes round-trip from bytes.
+                if len % 8 == 0 {
+                    assert_eq!(pack_msb_first(&unpack_msb_first(&bytes, len)), bytes);
+                }
+            }
+        }
+
+        // Packing pads the last byte with zeros.
+        let bytes = [0xa5u8, 0xff];
+        for len in 9..=16 {
+            let repacked = pack_msb_first(&unpack_msb_first(&bytes, len));
+            assert_eq!(repacked[0], 0xa5);
+            assert_eq!(repacked[1], 0xffu8 << (16 - len));
+        }
+    }
+}
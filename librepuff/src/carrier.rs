@@ -15,88 +15,42 @@
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
 use bit_vec::BitVec;
-use libobfuscate::csprng::{self, Csprng};
 use log::warn;
+use std::fmt::{self, Display};
+#[cfg(feature = "fs")]
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+use std::io::{BufRead, Cursor, Read, Seek};
 use std::path::Path;
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
 
 use crate::bit_selection::BitSelection;
+use crate::bitutil;
+use crate::carrier_parser::ParserRegistry;
 use crate::carrier_type::CarrierType;
+use crate::chain;
 use crate::crc32;
-use crate::parser;
-use crate::Error;
-
-fn generate_whitening_lookup_table(seed: usize) -> [u8; 1 << 13] {
-    let mut csprng = Csprng::new_with_seed(
-        csprng::Hash::Skein512,
-        &format!("{:010}", seed),
-        seed as u32,
-    )
-    .unwrap();
-
-    let mut bit_mask = [0u32; 13];
-    let mut index = 0;
-    while index < 13 {
-        let bit_mask_index = (csprng.get_dword() % 13) as usize;
-
-        if bit_mask[bit_mask_index] == 0 {
-            bit_mask[bit_mask_index] = 1 << (index & 0b11111);
-            index += 1;
-        }
-    }
-
-    let bit_assembly_order: [u32; 6] = match csprng.get_byte() % 20 {
-        00 => [1 << 00, 1 << 02, 1 << 13, 1 << 17, 1 << 19, 1 << 28],
-        01 => [1 << 00, 1 << 04, 1 << 11, 1 << 16, 1 << 18, 1 << 28],
-        02 => [1 << 00, 1 << 04, 1 << 12, 1 << 18, 1 << 26, 1 << 28],
-        03 => [1 << 00, 1 << 07, 1 << 11, 1 << 12, 1 << 14, 1 << 16],
-        04 => [1 << 01, 1 << 04, 1 << 11, 1 << 15, 1 << 26, 1 << 28],
-        05 => [1 << 01, 1 << 04, 1 << 11, 1 << 15, 1 << 26, 1 << 30],
-        06 => [1 << 01, 1 << 04, 1 << 11, 1 << 15, 1 << 27, 1 << 30],
-        07 => [1 << 01, 1 << 04, 1 << 11, 1 << 26, 1 << 27, 1 << 30],
-        08 => [1 << 01, 1 << 12, 1 << 16, 1 << 18, 1 << 26, 1 << 31],
-        09 => [1 << 02, 1 << 03, 1 << 10, 1 << 12, 1 << 27, 1 << 31],
-        10 => [1 << 02, 1 << 08, 1 << 10, 1 << 12, 1 << 27, 1 << 31],
-        11 => [1 << 02, 1 << 13, 1 << 16, 1 << 17, 1 << 27, 1 << 30],
-        12 => [1 << 03, 1 << 10, 1 << 12, 1 << 17, 1 << 27, 1 << 31],
-        13 => [1 << 04, 1 << 11, 1 << 15, 1 << 18, 1 << 26, 1 << 28],
-        14 => [1 << 04, 1 << 11, 1 << 15, 1 << 26, 1 << 27, 1 << 30],
-        15 => [1 << 08, 1 << 10, 1 << 14, 1 << 15, 1 << 23, 1 << 27],
-        16 => [1 << 08, 1 << 12, 1 << 20, 1 << 22, 1 << 24, 1 << 31],
-        17 => [1 << 10, 1 << 14, 1 << 15, 1 << 23, 1 << 26, 1 << 29],
-        18 => [1 << 11, 1 << 15, 1 << 18, 1 << 26, 1 << 27, 1 << 29],
-        19 => [1 << 11, 1 << 17, 1 << 19, 1 << 27, 1 << 28, 1 << 30],
-        _ => unreachable!(),
-    };
-
-    let mut whitening_table = [0u8; 1 << 13];
-    for i in 0..(1 << 13) {
-        // Computing the CRC32 of the bits of i, in a custom order, using the polynomial 0x2608edb
-        // TODO: is it really standard?
-        let mut crc32: u32 = 0xffffffff;
-        for j in 0..13 {
-            let bit = i & bit_mask[j] != 0;
-            crc32::update_with_bit(&mut crc32, bit);
-        }
+use crate::embedded_file;
+use crate::wav_options::WavOptions;
+use crate::{Error, ParserWarning};
 
-        // Selects bits
-        let mut value = 0u8;
-        for j in 0..6 {
-            if crc32 & bit_assembly_order[j] != 0 {
-                value |= 1 << j;
-            }
-        }
+mod selected_bits;
+pub mod whitening;
 
-        whitening_table[i as usize] = value;
-    }
+pub use selected_bits::SelectedBitsReader;
+pub use whitening::unwhiten;
+use whitening::unwhitened_bit_count;
 
-    whitening_table
-}
+/// Size of the encrypted IV at the start of a carrier, in bytes.
+///
+/// The IV is made of the IVs of all the ciphers combined by `libobfuscate::multi`.
+pub const IV_SIZE: usize = libobfuscate::multi::IVS_SIZE;
 
-type EncryptedIv = [u8; 256];
+type EncryptedIv = [u8; IV_SIZE];
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncryptedCarrier {
     // TODO: document fields
     pub iv: EncryptedIv,
@@ -105,85 +59,496 @@ pub struct EncryptedCarrier {
     pub decoy: Vec<u8>,
 
     pub other_bits: BitVec,
+
+    /// Oddities found while parsing the carrier.
+    pub warnings: Vec<ParserWarning>,
 }
 impl EncryptedCarrier {
     /// Returns the number of data or decoy bits selected in this carrier.
+    ///
+    /// The count is in bits, `data` holding the selected bits packed into bytes: it is
+    /// `8 * data.len()`. Earlier versions returned `data.len()`, the number of bytes.
     pub fn selected_bit_count(&self) -> usize {
-        self.data.len()
+        8 * self.data.len()
+    }
+
+    /// Returns the filler bits, which OpenPuff ignores, packed into bytes like the data bits.
+    ///
+    /// The last byte is padded with zeros.
+    pub fn filler_bytes(&self) -> Vec<u8> {
+        bitutil::pack_msb_first(&self.other_bits)
+    }
+
+    /// Decrypts the IV of this carrier with `key`, like the chain does.
+    ///
+    /// The key of a carrier depends on the IVs of the previous ones (see `chain::derive_keys`);
+    /// the first carrier of a chain has the key `0x502239c3`.
+    pub fn decrypt_iv(&self, key: u32) -> [u8; IV_SIZE] {
+        let mut iv = self.iv;
+        chain::decrypt_iv(&mut iv, key);
+        iv
     }
 }
 
-pub fn from_file(path: &Path, selection_level: BitSelection) -> Result<EncryptedCarrier, Error> {
-    let file = File::open(path)?;
+impl Display for EncryptedCarrier {
+    /// Writes a one-line summary of the carrier, fit for logs. The IV is identified by its
+    /// CRC32, `Debug` shows it in full.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "IV CRC32 {:08x}, {} selected bits, {} data bytes, {} decoy bytes, {} filler bits",
+            crc32::compute(&self.iv),
+            self.selected_bit_count(),
+            self.data.len(),
+            self.decoy.len(),
+            self.other_bits.len()
+        )?;
+        if !self.warnings.is_empty() {
+            write!(f, ", {} parser warning(s)", self.warnings.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Information about a carrier, gathered without decrypting it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CarrierInfo {
+    pub file_type: CarrierType,
+    /// Number of bits the parser selected from the carrier, before unwhitening.
+    pub available_bit_count: usize,
+    /// Number of data or decoy bits selected in this carrier.
+    pub selected_bit_count: usize,
+    /// Whether the carrier has data past the end of its file format.
+    pub has_trailing_data: bool,
+    /// Oddities found while parsing the carrier.
+    pub warnings: Vec<ParserWarning>,
+}
+
+/// Options used to parse a carrier.
+///
+/// The default options match OpenPuff's behaviour.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CarrierOptions {
+    selection_level: BitSelection,
+    strict_trailing: bool,
+    pub(crate) wav: WavOptions,
+}
 
-    // Detect file type
-    //
-    // Compatiblity note: OpenPuff determines the file format solely based on the file
-    // extension. See `CarrierType::from_extension` for the list of recognized extensions.
+impl CarrierOptions {
+    /// Returns the default options.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the bit selection level the carrier was created with.
+    pub fn selection(mut self, selection_level: BitSelection) -> Self {
+        self.selection_level = selection_level;
+        self
+    }
+
+    /// Sets whether files OpenPuff would reject are rejected. See `WavOptions::strict`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.wav.strict = strict;
+        self
+    }
+
+    /// Sets whether carriers read from files are rejected with `Error::TrailingData` when they
+    /// have data past the end of their file format, instead of only logging a warning.
+    ///
+    /// Not present in OpenPuff, which accepts trailing data in most cases: it can hint at a
+    /// tampered or wrong file.
+    pub fn strict_trailing(mut self, strict_trailing: bool) -> Self {
+        self.strict_trailing = strict_trailing;
+        self
+    }
+
+    /// Sets the options of the WAVE parser.
+    pub fn wav(mut self, wav_options: WavOptions) -> Self {
+        self.wav = wav_options;
+        self
+    }
+}
+
+/// Detects the type of the carrier at `path`.
+///
+/// Compatiblity note: OpenPuff determines the file format solely based on the file
+/// extension. See `CarrierType::from_extension` for the list of recognized extensions.
+pub fn file_type_from_path(path: &Path) -> Result<CarrierType, Error> {
     let extension = path.extension().ok_or(Error::UnknownFiletype)?;
     let extension = extension.to_str().ok_or(Error::UnknownFiletype)?;
-    let file_type = CarrierType::from_extension(extension).ok_or(Error::UnknownFiletype)?;
+    CarrierType::from_extension(extension).ok_or(Error::UnknownFiletype)
+}
+
+/// Detects the type of the carrier at `path`, whose file starts with `magic`.
+///
+/// The extension is preferred, as OpenPuff does. The signature found in `magic` is used when the
+/// extension isn't recognized, and a warning is emitted when both disagree.
+#[cfg(feature = "fs")]
+fn sniffed_file_type(path: &Path, magic: &[u8]) -> Result<CarrierType, Error> {
+    let sniffed_type = CarrierType::from_magic(magic);
+
+    match (file_type_from_path(path), sniffed_type) {
+        (Ok(file_type), Some(sniffed_type)) if file_type != sniffed_type => {
+            warn!(
+                "{} has the extension of a {} file, but looks like a {} file",
+                path.display(),
+                file_type,
+                sniffed_type
+            );
+            Ok(file_type)
+        }
+        (Ok(file_type), _) => Ok(file_type),
+        (Err(_), Some(sniffed_type)) => Ok(sniffed_type),
+        (Err(err), None) => Err(err),
+    }
+}
+
+/// Parses the carrier at `path`, whose type is detected from its extension.
+#[cfg(feature = "fs")]
+pub fn from_file(path: &Path, selection_level: BitSelection) -> Result<EncryptedCarrier, Error> {
+    from_file_with(path, &CarrierOptions::new().selection(selection_level))
+}
+
+/// Like `from_file`, parsing the carrier according to `options`.
+#[cfg(feature = "fs")]
+pub fn from_file_with(path: &Path, options: &CarrierOptions) -> Result<EncryptedCarrier, Error> {
+    let file_type = file_type_from_path(path)?;
+
+    from_file_as(path, file_type, options)
+}
+
+/// Like `from_file_with`, parsing the carrier as a `file_type` file whatever its extension.
+///
+/// Not present in OpenPuff, which only relies on extensions: useful for mislabeled files.
+#[cfg(feature = "fs")]
+pub fn from_file_as(
+    path: &Path,
+    file_type: CarrierType,
+    options: &CarrierOptions,
+) -> Result<EncryptedCarrier, Error> {
+    let file = File::open(path)?;
+
+    let mut reader = BufReader::new(file);
+    from_buffered_file(&mut reader, path, file_type, options)
+}
+
+/// Parses each carrier of `paths` according to `options`, without stopping at the first error.
+///
+/// The results are returned in the order of `paths`, alongside their path.
+#[cfg(feature = "fs")]
+pub fn from_paths(
+    paths: &[PathBuf],
+    options: &CarrierOptions,
+) -> Vec<(PathBuf, Result<EncryptedCarrier, Error>)> {
+    paths
+        .iter()
+        .map(|path| (path.clone(), from_file_with(path, options)))
+        .collect()
+}
+
+/// Like `from_file`, also detecting the type of the carrier from its content.
+///
+/// Not present in OpenPuff: see `sniffed_file_type`.
+#[cfg(feature = "fs")]
+pub fn from_file_sniffed(
+    path: &Path,
+    selection_level: BitSelection,
+) -> Result<EncryptedCarrier, Error> {
+    let file = File::open(path)?;
 
     let mut reader = BufReader::new(file);
-    let carrier = from_reader(&mut reader, file_type, selection_level)?;
+    let file_type = sniffed_file_type(path, reader.fill_buf()?)?;
 
-    // Oddities detection - not present in OpenPuff
-    if reader.has_data_left()? {
+    from_buffered_file(
+        &mut reader,
+        path,
+        file_type,
+        &CarrierOptions::new().selection(selection_level),
+    )
+}
+
+/// Warns about the trailing data of the carrier at `path`, or rejects it with
+/// `Error::TrailingData` if `options` asks to.
+///
+/// Oddities detection - not present in OpenPuff
+#[cfg(feature = "fs")]
+fn check_trailing_data(
+    has_trailing_data: bool,
+    path: &Path,
+    options: &CarrierOptions,
+) -> Result<(), Error> {
+    if has_trailing_data {
+        if options.strict_trailing {
+            return Err(Error::TrailingData(path.to_path_buf()));
+        }
         warn!("{} has trailing data", path.display());
     }
 
+    Ok(())
+}
+
+#[cfg(feature = "fs")]
+fn from_buffered_file(
+    reader: &mut BufReader<File>,
+    path: &Path,
+    file_type: CarrierType,
+    options: &CarrierOptions,
+) -> Result<EncryptedCarrier, Error> {
+    let carrier = from_seekable_reader_with(reader, file_type, options)?;
+
+    check_trailing_data(reader.has_data_left()?, path, options)?;
+
+    Ok(carrier)
+}
+
+/// Like `from_file`, parsing the carrier according to `options` from a memory-mapped file.
+///
+/// This avoids copying large carriers through a buffer.
+/// The file must not be modified while it is parsed.
+#[cfg(feature = "mmap")]
+pub fn from_mmap(path: &Path, options: &CarrierOptions) -> Result<EncryptedCarrier, Error> {
+    let file = File::open(path)?;
+
+    let file_type = file_type_from_path(path)?;
+
+    // SAFETY: the mapping is only read, and only lives during this function. Changes made to the
+    // file by other processes meanwhile are documented as forbidden.
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
+
+    let mut cursor = Cursor::new(&mmap[..]);
+    let carrier = from_seekable_reader_with(&mut cursor, file_type, options)?;
+
+    check_trailing_data(cursor.has_data_left()?, path, options)?;
+
+    Ok(carrier)
+}
+
+/// Like `from_reader_with`, reading the carrier asynchronously.
+///
+/// The carrier is read into memory, then parsed on a thread where blocking is acceptable (see
+/// `tokio::task::spawn_blocking`), so a tokio runtime must be running. Like `from_file_with`,
+/// trailing data is reported, or rejected if `options` asks to, for the carrier at `path`.
+#[cfg(feature = "async")]
+pub async fn from_async_reader(
+    mut reader: impl tokio::io::AsyncRead + Unpin,
+    path: &Path,
+    file_type: CarrierType,
+    options: &CarrierOptions,
+) -> Result<EncryptedCarrier, Error> {
+    use tokio::io::AsyncReadExt;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+
+    let path = path.to_path_buf();
+    let options = options.clone();
+    let parsing = tokio::task::spawn_blocking(move || {
+        let mut cursor = Cursor::new(&bytes[..]);
+        let carrier = from_seekable_reader_with(&mut cursor, file_type, &options)?;
+
+        check_trailing_data(cursor.has_data_left()?, &path, &options)?;
+
+        Ok(carrier)
+    });
+
+    parsing
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+}
+
+/// Parses a carrier held in memory.
+pub fn from_bytes(
+    bytes: &[u8],
+    file_type: CarrierType,
+    selection_level: BitSelection,
+) -> Result<EncryptedCarrier, Error> {
+    let mut cursor = Cursor::new(bytes);
+    let carrier = from_seekable_reader_with(
+        &mut cursor,
+        file_type,
+        &CarrierOptions::new().selection(selection_level),
+    )?;
+
+    // Oddities detection - not present in OpenPuff
+    if cursor.has_data_left()? {
+        warn!("carrier has trailing data");
+    }
+
     Ok(carrier)
 }
 
+/// Parses the carrier at `path` and reports information about it.
+#[cfg(feature = "fs")]
+pub fn info_from_file(path: &Path, selection_level: BitSelection) -> Result<CarrierInfo, Error> {
+    let file_type = file_type_from_path(path)?;
+
+    info_from_file_as(path, file_type, selection_level)
+}
+
+/// Like `info_from_file`, parsing the carrier as a `file_type` file whatever its extension.
+#[cfg(feature = "fs")]
+pub fn info_from_file_as(
+    path: &Path,
+    file_type: CarrierType,
+    selection_level: BitSelection,
+) -> Result<CarrierInfo, Error> {
+    let file = File::open(path)?;
+
+    let mut reader = BufReader::new(file);
+    let (whitened_bits, warnings) = ParserRegistry::builtin().parse_seekable(
+        &mut reader,
+        file_type,
+        &CarrierOptions::new().selection(selection_level),
+    )?;
+    let available_bit_count = whitened_bits.len();
+    let carrier = from_whitened_bits(whitened_bits, warnings, selection_level)?;
+
+    Ok(CarrierInfo {
+        file_type,
+        available_bit_count,
+        selected_bit_count: carrier.selected_bit_count(),
+        has_trailing_data: reader.has_data_left()?,
+        warnings: carrier.warnings,
+    })
+}
+
+/// Number of unwhitened bits holding the encrypted IV, at the start of a carrier.
+pub(crate) const IV_BIT_COUNT: usize = 8 * IV_SIZE;
+
+/// Number of unwhitened bits OpenPuff reserves before computing how many bits can be selected.
+///
+/// The unwhitened bits of a carrier hold the encrypted IV (`IV_BIT_COUNT` bits), then the groups
+/// of selected bits, then bits that are never read. The reserved bits are the IV plus 936 of
+/// those unread bits: they aren't skipped before the selected bits, they only reduce the
+/// selectable bit count. Rounding the count down to a multiple of 128 can leave more unread bits.
+///
+/// The 936 bits come from OpenPuff, which doesn't document their purpose. They must be reserved
+/// all the same: with any other count, the number of selected bits, and so the length of the
+/// data and decoy contents of each carrier, wouldn't match OpenPuff's.
+pub(crate) const RESERVED_BIT_COUNT: usize = IV_BIT_COUNT + 936;
+
+/// Packs the next `IV_BIT_COUNT` bits of `bits` into an IV, like `bitutil::pack_msb_first`.
+///
+/// # Panics
+///
+/// Panics if `bits` yields fewer than `IV_BIT_COUNT` bits.
+fn pack_iv(bits: &mut impl Iterator<Item = bool>) -> EncryptedIv {
+    bitutil::pack_array_msb_first(bits).expect("too few bits for an IV")
+}
+
 pub fn from_reader(
     reader: &mut impl Read,
     file_type: CarrierType,
     selection_level: BitSelection,
+) -> Result<EncryptedCarrier, Error> {
+    from_reader_with(
+        reader,
+        file_type,
+        &CarrierOptions::new().selection(selection_level),
+    )
+}
+
+/// Like `from_reader`, parsing the carrier according to `options`.
+///
+/// Parsing isn't streamed: the whitened bits the parser selects (one per usable sample of a
+/// WAVE file) are all held in memory, as the whitening table depends on their count, only known
+/// once the whole carrier is parsed. Only the unwhitening is done lazily, so that the unwhitened
+/// bits aren't held in memory on top of them. See `SelectedBitsReader` to skip storing the IV,
+/// decoy and filler bits too.
+pub fn from_reader_with(
+    reader: &mut impl Read,
+    file_type: CarrierType,
+    options: &CarrierOptions,
+) -> Result<EncryptedCarrier, Error> {
+    from_reader_with_registry(reader, file_type, options, ParserRegistry::builtin())
+}
+
+/// Like `from_reader_with`, parsing the carrier with the parser `registry` holds for `file_type`.
+pub fn from_reader_with_registry(
+    reader: &mut impl Read,
+    file_type: CarrierType,
+    options: &CarrierOptions,
+    registry: &ParserRegistry,
 ) -> Result<EncryptedCarrier, Error> {
     // TODO: what about add_carriers' first parameter?
-    let whitened_bits = match file_type {
-        CarrierType::Wav => parser::wav::parse(reader),
-        _ => unimplemented!(), // TODO
-    }?;
-
-    let whitening_lookup_table = generate_whitening_lookup_table(whitened_bits.len());
-
-    let mut unwhitened_bits = BitVec::new();
-    for chunk_index in 0..(whitened_bits.len() / 13) {
-        let mut chunk: u16 = 0;
-        for j in 0..13 {
-            chunk <<= 1;
-            if whitened_bits[13 * chunk_index + j] {
-                chunk |= 1;
-            }
-        }
+    let (whitened_bits, warnings) = registry.parse(reader, file_type, options)?;
 
-        let unwhitened_chunk = whitening_lookup_table[chunk as usize];
-        for j in (0..6).rev() {
-            unwhitened_bits.push(unwhitened_chunk & (1 << j) != 0);
-        }
+    from_whitened_bits(whitened_bits, warnings, options.selection_level)
+}
+
+/// Like `from_reader_with`, seeking past the parts of the carrier which aren't used instead of
+/// reading them. This saves I/O for carriers read over a network, for instance.
+pub fn from_seekable_reader_with(
+    reader: &mut (impl Read + Seek),
+    file_type: CarrierType,
+    options: &CarrierOptions,
+) -> Result<EncryptedCarrier, Error> {
+    let (whitened_bits, warnings) =
+        ParserRegistry::builtin().parse_seekable(reader, file_type, options)?;
+
+    from_whitened_bits(whitened_bits, warnings, options.selection_level)
+}
+
+/// Returns how many bytes of content fit in `carriers`, once hidden as a file named `filename`.
+///
+/// The bit selection level is already accounted for by the carriers' `selected_bit_count`. The
+/// header of the embedded file and its filename are deducted from the selected bits.
+pub fn payload_capacity_bytes(carriers: &[EncryptedCarrier], filename: &[u8]) -> usize {
+    let selected_bit_count: usize = carriers
+        .iter()
+        .map(EncryptedCarrier::selected_bit_count)
+        .sum();
+
+    (selected_bit_count / 8).saturating_sub(embedded_file::HEADER_SIZE + filename.len())
+}
+
+/// Returns the number of data (or decoy) bits selected in a carrier made of `whitened_bit_count`
+/// whitened bits.
+fn selected_bit_count(
+    whitened_bit_count: usize,
+    selection_level: BitSelection,
+) -> Result<usize, Error> {
+    // A carrier made only of samples the parser skips (e.g. silence) is well-formed, but is
+    // reported separately as it is otherwise indistinguishable from a tiny carrier.
+    if whitened_bit_count == 0 {
+        return Err(Error::NoUsableBits);
     }
-    // TODO: should we warn about the %13 bits remaining ?
 
-    // TODO: explain the magic constant 2984
+    let unwhitened_bit_count = unwhitened_bit_count(whitened_bit_count);
+
     // TODO: find a way to read `selected_bit_count` bits more naturally
-    const MAGIC_VALUE: usize = 2984;
-    if unwhitened_bits.len() < MAGIC_VALUE {
+    if unwhitened_bit_count < RESERVED_BIT_COUNT {
         return Err(Error::CarrierTooSmall);
     }
     let selected_bit_count =
-        ((unwhitened_bits.len() - MAGIC_VALUE) / selection_level.divisor()) & !0b1111111;
+        ((unwhitened_bit_count - RESERVED_BIT_COUNT) / selection_level.divisor()) & !0b1111111;
+    if selected_bit_count == 0 {
+        return Err(Error::NoSelectableBits);
+    }
+
+    Ok(selected_bit_count)
+}
 
-    let mut bits_iter = unwhitened_bits.into_iter();
+fn from_whitened_bits(
+    whitened_bits: BitVec,
+    warnings: Vec<ParserWarning>,
+    selection_level: BitSelection,
+) -> Result<EncryptedCarrier, Error> {
+    let selected_bit_count = selected_bit_count(whitened_bits.len(), selection_level)?;
 
-    // The first 256 bytes is an encrypted IV used to encrypt the data.
-    let encrypted_iv_bits: BitVec = (&mut bits_iter).take(8 * 256).collect();
+    let mut bits_iter = whitening::unwhitened_bits(&whitened_bits, whitened_bits.len());
+
+    // The first `IV_SIZE` bytes is an encrypted IV used to encrypt the data.
+    let encrypted_iv = pack_iv(&mut bits_iter);
 
     // Then, one bit out of `selection_level.divisor()` is used for the hidden file,
     // one bit is used for the decoy file and the others are skipped.
-    let mut data_bits = BitVec::new();
-    let mut decoy_bits = BitVec::new();
-    let mut other_bits = BitVec::new();
+    // The last group stops after its decoy bit.
+    let mut data_bits = BitVec::with_capacity(selected_bit_count);
+    let mut decoy_bits = BitVec::with_capacity(selected_bit_count);
+    let mut other_bits =
+        BitVec::with_capacity((selected_bit_count - 1) * (selection_level.divisor() - 2));
 
     for (i, bit) in bits_iter
         .take((selected_bit_count - 1) * selection_level.divisor() + 2)
@@ -203,36 +568,15 @@ pub fn from_reader(
 
     // Note: nothing can be decrypted yet, as the decryption key depends on the other carriers.
 
-    let mut encrypted_iv = [0u8; 256];
-    for (i, bit) in encrypted_iv_bits.iter().enumerate() {
-        encrypted_iv[i / 8] <<= 1;
-        if bit {
-            encrypted_iv[i / 8] |= 1;
-        }
-    }
-
-    fn pack_bits(bits: BitVec) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        // TODO: check for correctness
-        bytes.resize((bits.len() + 7) / 8, 0);
-
-        for (i, bit) in bits.iter().enumerate() {
-            bytes[i / 8] <<= 1;
-            if bit {
-                bytes[i / 8] |= 1;
-            }
-        }
-
-        bytes
-    }
-
     Ok(EncryptedCarrier {
         iv: encrypted_iv,
 
-        data: pack_bits(data_bits),
-        decoy: pack_bits(decoy_bits),
+        data: bitutil::pack_msb_first(&data_bits),
+        decoy: bitutil::pack_msb_first(&decoy_bits),
 
         other_bits,
+
+        warnings,
     })
 }
 
@@ -241,9 +585,12 @@ pub fn from_reader(
 mod tests {
     use super::*;
     use crate::bit_selection::BitSelection;
+    use crate::test_utils;
+    #[cfg(feature = "fs")]
     use std::io;
 
     #[test]
+    #[cfg(feature = "fs")]
     fn carrier_not_existing() {
         let does_not_exist = Path::new("./does/not/exist.png");
         let result = from_file(does_not_exist, BitSelection::Medium);
@@ -256,4 +603,374 @@ mod tests {
 
     #[test]
     fn carrier_no_file_extension() {}
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn carrier_truncated() {
+        let wav = test_utils::wav_file(&[0b1000; 13000]);
+        let path = test_utils::temporary_file("carrier_truncated.wav", &wav[..1000]);
+
+        assert!(matches!(
+            from_file(&path, BitSelection::Medium),
+            Err(Error::InvalidCarrierFormat)
+        ));
+        assert!(matches!(
+            from_bytes(&wav[..30], CarrierType::Wav, BitSelection::Medium),
+            Err(Error::InvalidCarrierFormat)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn carrier_unsupported() {
+        let path = test_utils::temporary_file("carrier_unsupported.png", b"\x89PNG\r\n\x1a\n");
+
+        assert!(matches!(
+            from_file(&path, BitSelection::Medium),
+            Err(Error::UnsupportedCarrier(CarrierType::Png))
+        ));
+        assert_eq!(
+            Error::UnsupportedCarrier(CarrierType::Png).to_string(),
+            "PNG carriers are not yet supported"
+        );
+    }
+
+    #[test]
+    fn filler_packing() {
+        let wav = test_utils::wav_file(&[0b1000; 13000]);
+        for selection_level in [BitSelection::Minimum, BitSelection::Medium] {
+            let carrier = from_bytes(&wav, CarrierType::Wav, selection_level).unwrap();
+
+            assert!(!carrier.other_bits.is_empty());
+            assert_eq!(
+                carrier.filler_bytes().len(),
+                carrier.other_bits.len().div_ceil(8)
+            );
+            assert_eq!(carrier.filler_bytes(), carrier.other_bits.to_bytes());
+        }
+    }
+
+    #[test]
+    fn selected_bits_lengths() {
+        let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+        let wav = test_utils::wav_file(&samples);
+
+        for selection_level in BitSelection::all() {
+            let carrier = from_bytes(&wav, CarrierType::Wav, selection_level).unwrap();
+            let selected_bit_count = carrier.selected_bit_count();
+
+            assert_eq!(carrier.decoy.len(), carrier.data.len());
+            assert_eq!(
+                carrier.other_bits.len(),
+                (selected_bit_count - 1) * (selection_level.divisor() - 2)
+            );
+        }
+    }
+
+    #[test]
+    fn iv_size() {
+        assert_eq!(IV_SIZE, 256);
+    }
+
+    #[test]
+    fn iv_packing() {
+        let bits: BitVec = (0..IV_BIT_COUNT + 10)
+            .map(|i: usize| (i * 7 + i / 3) % 5 < 2)
+            .collect();
+
+        let mut bits_iter = bits.iter();
+        let iv = pack_iv(&mut bits_iter);
+        assert_eq!(
+            iv[..],
+            bitutil::pack_msb_first(&bits.iter().take(IV_BIT_COUNT).collect())
+        );
+        // Only the bits of the IV were consumed
+        assert_eq!(bits_iter.count(), 10);
+    }
+
+    #[test]
+    fn reserved_region() {
+        // Empty carriers are refused before being unwhitened with a seed of 0.
+        assert!(matches!(
+            from_whitened_bits(BitVec::new(), vec![], BitSelection::Medium),
+            Err(Error::NoUsableBits)
+        ));
+
+        // 2988 unwhitened bits: the reserved region, but not enough for any selectable bit.
+        let whitened_bits = BitVec::from_elem(13 * (RESERVED_BIT_COUNT / 6 + 1), false);
+        assert!(matches!(
+            from_whitened_bits(whitened_bits, vec![], BitSelection::Medium),
+            Err(Error::NoSelectableBits)
+        ));
+
+        // 2982 unwhitened bits: smaller than the reserved region.
+        let whitened_bits = BitVec::from_elem(13 * (RESERVED_BIT_COUNT / 6), false);
+        assert!(matches!(
+            from_whitened_bits(whitened_bits, vec![], BitSelection::Medium),
+            Err(Error::CarrierTooSmall)
+        ));
+
+        // Exactly enough unwhitened bits for 128 selected bits.
+        let whitened_bits = BitVec::from_elem(13 * (RESERVED_BIT_COUNT + 2 * 128) / 6, false);
+        let carrier = from_whitened_bits(whitened_bits, vec![], BitSelection::Maximum).unwrap();
+        assert_eq!(carrier.selected_bit_count(), 128);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn carrier_sniffed() {
+        let wav = test_utils::wav_file(&[0b1000; 13000]);
+
+        let path = test_utils::temporary_file("carrier_sniffed.wav", &wav);
+        let carrier = from_file_sniffed(&path, BitSelection::Medium).unwrap();
+        assert_eq!(carrier, from_file(&path, BitSelection::Medium).unwrap());
+
+        let uppercase_path = test_utils::temporary_file("CARRIER_SNIFFED.WAV", &wav);
+        assert_eq!(
+            from_file(&uppercase_path, BitSelection::Medium).unwrap(),
+            carrier
+        );
+
+        // Mislabeled file, only opened when sniffing
+        let mislabeled_path = test_utils::temporary_file("carrier_sniffed.dat", &wav);
+        assert!(matches!(
+            from_file(&mislabeled_path, BitSelection::Medium),
+            Err(Error::UnknownFiletype)
+        ));
+        assert_eq!(
+            from_file_sniffed(&mislabeled_path, BitSelection::Medium).unwrap(),
+            carrier
+        );
+
+        let text_path = test_utils::temporary_file("carrier_sniffed.txt", b"plain text");
+        assert!(matches!(
+            from_file_sniffed(&text_path, BitSelection::Medium),
+            Err(Error::UnknownFiletype)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn sniffed_file_type_conflict() {
+        let wav = test_utils::wav_file(&[0b1000; 10]);
+
+        // The extension wins over the signature
+        assert_eq!(
+            sniffed_file_type(Path::new("carrier.png"), &wav).unwrap(),
+            CarrierType::Png
+        );
+        assert_eq!(
+            sniffed_file_type(Path::new("carrier.png"), b"plain text").unwrap(),
+            CarrierType::Png
+        );
+        assert_eq!(
+            sniffed_file_type(Path::new("carrier"), &wav).unwrap(),
+            CarrierType::Wav
+        );
+    }
+
+    #[test]
+    fn carrier_options() {
+        let options = CarrierOptions::new();
+        assert_eq!(options.selection_level, BitSelection::Medium);
+        assert_eq!(options.wav, WavOptions::default());
+
+        let options = CarrierOptions::new()
+            .selection(BitSelection::Maximum)
+            .strict(false);
+        assert_eq!(options.selection_level, BitSelection::Maximum);
+        assert!(!options.wav.strict);
+        assert!(!options.wav.placeholder_size_fallback);
+
+        let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+        let wav = test_utils::wav_file(&samples);
+        assert_eq!(
+            from_reader_with(&mut &wav[..], CarrierType::Wav, &CarrierOptions::new()).unwrap(),
+            from_reader(&mut &wav[..], CarrierType::Wav, BitSelection::Medium).unwrap()
+        );
+        assert_eq!(
+            from_reader_with(&mut &wav[..], CarrierType::Wav, &options).unwrap(),
+            from_reader(&mut &wav[..], CarrierType::Wav, BitSelection::Maximum).unwrap()
+        );
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn trailing_data() {
+        let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+        let mut wav = test_utils::wav_file(&samples);
+        let path = test_utils::temporary_file("without_trailing_data.wav", &wav);
+        let strict = CarrierOptions::new().strict_trailing(true);
+        let carrier = from_file_with(&path, &CarrierOptions::new()).unwrap();
+        assert_eq!(from_file_with(&path, &strict).unwrap(), carrier);
+
+        wav.extend_from_slice(b"appended");
+        let path = test_utils::temporary_file("trailing_data.wav", &wav);
+        assert_eq!(
+            from_file_with(&path, &CarrierOptions::new()).unwrap(),
+            carrier
+        );
+        assert!(matches!(
+            from_file_with(&path, &strict),
+            Err(Error::TrailingData(error_path)) if error_path == path
+        ));
+    }
+
+    #[test]
+    fn carrier_summary() {
+        let carrier = EncryptedCarrier {
+            iv: [0; 256],
+            data: vec![0; 64],
+            decoy: vec![0; 64],
+            other_bits: BitVec::from_elem(1000, false),
+            warnings: Vec::new(),
+        };
+        let iv_crc32 = crc32::compute(&[0; 256]);
+
+        assert_eq!(
+            carrier.to_string(),
+            format!(
+                "IV CRC32 {iv_crc32:08x}, 512 selected bits, 64 data bytes, 64 decoy bytes, 1000 filler bits"
+            )
+        );
+
+        let carrier = EncryptedCarrier {
+            warnings: vec![ParserWarning::FmtTrailingData],
+            ..carrier
+        };
+        assert!(carrier
+            .to_string()
+            .ends_with(", 1000 filler bits, 1 parser warning(s)"));
+    }
+
+    #[test]
+    fn silent_carrier() {
+        let wav = test_utils::wav_file(&[0; 13000]);
+        assert!(matches!(
+            from_bytes(&wav, CarrierType::Wav, BitSelection::Medium),
+            Err(Error::NoUsableBits)
+        ));
+
+        // A few usable samples are just too few.
+        let mut samples = [0; 13000];
+        samples[..13].fill(0b1000);
+        let wav = test_utils::wav_file(&samples);
+        assert!(matches!(
+            from_bytes(&wav, CarrierType::Wav, BitSelection::Medium),
+            Err(Error::CarrierTooSmall)
+        ));
+    }
+
+    #[test]
+    fn payload_capacity() {
+        // 13000 selectable samples give 512 selected bits at the medium level, and 26000 give
+        // 2176 at the high one.
+        let small_wav = test_utils::wav_file(&[0b1000; 13000]);
+        let large_wav = test_utils::wav_file(&[0b1001; 26000]);
+        let medium = from_bytes(&small_wav, CarrierType::Wav, BitSelection::Medium).unwrap();
+        let high = from_bytes(&large_wav, CarrierType::Wav, BitSelection::High).unwrap();
+
+        assert_eq!(payload_capacity_bytes(&[], b""), 0);
+        assert_eq!(
+            payload_capacity_bytes(std::slice::from_ref(&medium), b""),
+            64 - 10
+        );
+        assert_eq!(
+            payload_capacity_bytes(&[medium.clone(), high], b"secret.txt"),
+            64 + 272 - 10 - 10
+        );
+        assert_eq!(payload_capacity_bytes(&[medium], &[b'a'; 60]), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn carrier_paths() {
+        let wav = test_utils::wav_file(&[0b1000; 13000]);
+        let good_path = test_utils::temporary_file("carrier_paths.wav", &wav);
+        let missing_path = PathBuf::from("./does/not/exist.wav");
+
+        let options = CarrierOptions::new().selection(BitSelection::High);
+        let results = from_paths(&[missing_path.clone(), good_path.clone()], &options);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, missing_path);
+        assert!(
+            matches!(&results[0].1, Err(Error::IoError(e)) if e.kind() == io::ErrorKind::NotFound)
+        );
+        assert_eq!(results[1].0, good_path);
+        assert_eq!(
+            results[1].1.as_ref().unwrap(),
+            &from_file(&good_path, BitSelection::High).unwrap()
+        );
+
+        assert!(from_paths(&[], &options).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn carrier_mmap() {
+        let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+        let mut wav = test_utils::wav_file(&samples);
+        wav.extend_from_slice(b"trailing");
+        let path = test_utils::temporary_file("carrier_mmap.wav", &wav);
+
+        for selection_level in [BitSelection::Medium, BitSelection::Maximum] {
+            assert_eq!(
+                from_mmap(&path, &CarrierOptions::new().selection(selection_level)).unwrap(),
+                from_file(&path, selection_level).unwrap()
+            );
+        }
+
+        let truncated_path = test_utils::temporary_file("carrier_mmap_truncated.wav", &wav[..100]);
+        assert!(matches!(
+            from_mmap(&truncated_path, &CarrierOptions::new()),
+            Err(Error::InvalidCarrierFormat)
+        ));
+        assert!(matches!(
+            from_mmap(Path::new("./does/not/exist.wav"), &CarrierOptions::new()),
+            Err(Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn carrier_info() {
+        // 13000 selectable samples unwhiten to 6000 bits.
+        let mut wav = test_utils::wav_file(&[0b1000; 13000]);
+        let path = test_utils::temporary_file("carrier_info.wav", &wav);
+
+        let info = info_from_file(&path, BitSelection::Medium).unwrap();
+        assert_eq!(
+            info,
+            CarrierInfo {
+                file_type: CarrierType::Wav,
+                available_bit_count: 13000,
+                selected_bit_count: 512,
+                has_trailing_data: false,
+                warnings: vec![],
+            }
+        );
+
+        wav.extend_from_slice(b"trailing");
+        let path = test_utils::temporary_file("carrier_info_trailing.wav", &wav);
+
+        let info = info_from_file(&path, BitSelection::Maximum).unwrap();
+        assert_eq!(info.selected_bit_count, 1408);
+        assert!(info.has_trailing_data);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn carrier_from_bytes() {
+        let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+        let wav = test_utils::wav_file(&samples);
+        let path = test_utils::temporary_file("carrier_from_bytes.wav", &wav);
+
+        let carrier = from_bytes(&wav, CarrierType::Wav, BitSelection::Medium).unwrap();
+        assert_eq!(carrier.selected_bit_count(), 512);
+        assert_eq!(carrier, from_file(&path, BitSelection::Medium).unwrap());
+
+        let result = from_bytes(&wav[..100], CarrierType::Wav, BitSelection::Medium);
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,156 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use bit_vec::BitVec;
+use std::io::{self, Read};
+
+use super::whitening::UnwhitenedBits;
+use super::{selected_bit_count, CarrierOptions, IV_BIT_COUNT};
+use crate::carrier_parser::ParserRegistry;
+use crate::carrier_type::CarrierType;
+use crate::{Error, ParserWarning};
+
+/// Reader yielding the data bits of a carrier, packed into bytes like `EncryptedCarrier::data`.
+///
+/// The carrier is parsed when the reader is created, but its bits are only unwhitened as bytes
+/// are read, and the IV, decoy and filler bits are skipped rather than stored. The whitened bits
+/// selected by the parser are still all held in memory (see `from_reader_with`).
+pub struct SelectedBitsReader {
+    unwhitened_bits: UnwhitenedBits<BitVec>,
+
+    selection_divisor: usize,
+    /// Number of data bits left to read.
+    remaining_bit_count: usize,
+    /// Whether the first data bit was read.
+    started: bool,
+
+    warnings: Vec<ParserWarning>,
+}
+
+impl SelectedBitsReader {
+    /// Parses a carrier from `reader`, like `from_reader_with`.
+    pub fn new(
+        reader: &mut impl Read,
+        file_type: CarrierType,
+        options: &CarrierOptions,
+    ) -> Result<Self, Error> {
+        let (whitened_bits, warnings) =
+            ParserRegistry::builtin().parse(reader, file_type, options)?;
+        let selected_bit_count = selected_bit_count(whitened_bits.len(), options.selection_level)?;
+        // Carriers are unwhitened with the number of whitened bits as the seed.
+        let seed = whitened_bits.len();
+
+        Ok(SelectedBitsReader {
+            unwhitened_bits: UnwhitenedBits::new(whitened_bits, seed),
+            selection_divisor: options.selection_level.divisor(),
+            remaining_bit_count: selected_bit_count,
+            started: false,
+            warnings,
+        })
+    }
+
+    /// Returns the number of bytes left to read.
+    pub fn remaining_bytes(&self) -> usize {
+        self.remaining_bit_count / 8
+    }
+
+    /// Returns the oddities found while parsing the carrier.
+    pub fn warnings(&self) -> &[ParserWarning] {
+        &self.warnings
+    }
+
+    /// Returns the next unwhitened bit.
+    fn next_unwhitened_bit(&mut self) -> bool {
+        // The selected bits all lie within the whole chunks of the carrier.
+        self.unwhitened_bits.next().unwrap()
+    }
+
+    /// Returns the next data bit, skipping the bits preceding it.
+    fn next_data_bit(&mut self) -> bool {
+        // The IV precedes the first data bit, and the decoy and filler bits the following ones.
+        let skipped_bit_count = if self.started {
+            self.selection_divisor - 1
+        } else {
+            IV_BIT_COUNT
+        };
+        self.started = true;
+
+        for _ in 0..skipped_bit_count {
+            self.next_unwhitened_bit();
+        }
+
+        self.remaining_bit_count -= 1;
+        self.next_unwhitened_bit()
+    }
+}
+
+impl Read for SelectedBitsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // The number of selected bits is a multiple of 8.
+        let len = buf.len().min(self.remaining_bytes());
+
+        for byte in &mut buf[..len] {
+            *byte = (0..8).fold(0, |byte, _| byte << 1 | self.next_data_bit() as u8);
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_selection::BitSelection;
+    use crate::carrier;
+    use crate::test_utils;
+
+    #[test]
+    fn same_bytes_as_carrier() {
+        let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+        let wav = test_utils::wav_file(&samples);
+
+        for level in [BitSelection::Minimum, BitSelection::Medium] {
+            let options = CarrierOptions::new().selection(level);
+            let carrier =
+                carrier::from_reader_with(&mut &wav[..], CarrierType::Wav, &options).unwrap();
+
+            let mut reader =
+                SelectedBitsReader::new(&mut &wav[..], CarrierType::Wav, &options).unwrap();
+            assert_eq!(reader.remaining_bytes(), carrier.data.len());
+
+            // Odd-sized reads
+            let mut bytes = Vec::new();
+            let mut buffer = [0; 7];
+            loop {
+                let len = reader.read(&mut buffer).unwrap();
+                if len == 0 {
+                    break;
+                }
+                bytes.extend_from_slice(&buffer[..len]);
+            }
+            assert_eq!(bytes, carrier.data);
+        }
+
+        assert!(matches!(
+            SelectedBitsReader::new(
+                &mut &test_utils::wav_file(&samples[..100])[..],
+                CarrierType::Wav,
+                &CarrierOptions::new()
+            ),
+            Err(Error::CarrierTooSmall)
+        ));
+    }
+}
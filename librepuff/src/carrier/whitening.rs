@@ -0,0 +1,331 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use bit_vec::BitVec;
+use libobfuscate::csprng::{self, Csprng};
+use log::warn;
+use std::borrow::Borrow;
+
+use crate::crc32;
+
+/// Generates the table mapping chunks of 13 whitened bits to 6 unwhitened bits.
+///
+/// OpenPuff seeds it with the number of whitened bits selected from the carrier. The first bit of
+/// a chunk is the most significant bit of its index, and the same goes for the 6 bits of an entry.
+///
+/// A seed of 0 isn't special-cased: like any other seed, it is formatted as `0000000000` and
+/// gives a valid table. Carriers never use it, as carriers without any whitened bit are refused
+/// with `Error::NoUsableBits` before being unwhitened, and OpenPuff doesn't accept them either.
+pub fn generate_whitening_lookup_table(seed: usize) -> [u8; 1 << 13] {
+    generate_whitening_lookup_table_with_hash(seed, csprng::Hash::Skein512)
+}
+
+/// Like `generate_whitening_lookup_table`, seeding the CSPRNG with `hash` instead of Skein-512.
+///
+/// Only Skein-512 is compatible with OpenPuff: other hashes give whitening tables unrelated to
+/// its own, to experiment with other derivations.
+pub fn generate_whitening_lookup_table_with_hash(seed: usize, hash: csprng::Hash) -> [u8; 1 << 13] {
+    let mut csprng = Csprng::new_with_seed(hash, &format!("{:010}", seed), seed as u32).unwrap();
+
+    let mut bit_mask = [0u32; 13];
+    let mut index = 0;
+    while index < 13 {
+        let bit_mask_index = (csprng.get_dword() % 13) as usize;
+
+        if bit_mask[bit_mask_index] == 0 {
+            bit_mask[bit_mask_index] = 1 << (index & 0b11111);
+            index += 1;
+        }
+    }
+
+    let bit_assembly_order: [u32; 6] = match csprng.get_byte() % 20 {
+        00 => [1 << 00, 1 << 02, 1 << 13, 1 << 17, 1 << 19, 1 << 28],
+        01 => [1 << 00, 1 << 04, 1 << 11, 1 << 16, 1 << 18, 1 << 28],
+        02 => [1 << 00, 1 << 04, 1 << 12, 1 << 18, 1 << 26, 1 << 28],
+        03 => [1 << 00, 1 << 07, 1 << 11, 1 << 12, 1 << 14, 1 << 16],
+        04 => [1 << 01, 1 << 04, 1 << 11, 1 << 15, 1 << 26, 1 << 28],
+        05 => [1 << 01, 1 << 04, 1 << 11, 1 << 15, 1 << 26, 1 << 30],
+        06 => [1 << 01, 1 << 04, 1 << 11, 1 << 15, 1 << 27, 1 << 30],
+        07 => [1 << 01, 1 << 04, 1 << 11, 1 << 26, 1 << 27, 1 << 30],
+        08 => [1 << 01, 1 << 12, 1 << 16, 1 << 18, 1 << 26, 1 << 31],
+        09 => [1 << 02, 1 << 03, 1 << 10, 1 << 12, 1 << 27, 1 << 31],
+        10 => [1 << 02, 1 << 08, 1 << 10, 1 << 12, 1 << 27, 1 << 31],
+        11 => [1 << 02, 1 << 13, 1 << 16, 1 << 17, 1 << 27, 1 << 30],
+        12 => [1 << 03, 1 << 10, 1 << 12, 1 << 17, 1 << 27, 1 << 31],
+        13 => [1 << 04, 1 << 11, 1 << 15, 1 << 18, 1 << 26, 1 << 28],
+        14 => [1 << 04, 1 << 11, 1 << 15, 1 << 26, 1 << 27, 1 << 30],
+        15 => [1 << 08, 1 << 10, 1 << 14, 1 << 15, 1 << 23, 1 << 27],
+        16 => [1 << 08, 1 << 12, 1 << 20, 1 << 22, 1 << 24, 1 << 31],
+        17 => [1 << 10, 1 << 14, 1 << 15, 1 << 23, 1 << 26, 1 << 29],
+        18 => [1 << 11, 1 << 15, 1 << 18, 1 << 26, 1 << 27, 1 << 29],
+        19 => [1 << 11, 1 << 17, 1 << 19, 1 << 27, 1 << 28, 1 << 30],
+        _ => unreachable!(),
+    };
+
+    whitening_table(&bit_mask, &bit_assembly_order)
+}
+
+/// Builds the whitening table once the CSPRNG has picked the order in which the bits of a chunk
+/// are fed to the CRC32 (`bit_mask`) and the bits of the CRC32 making up an entry
+/// (`bit_assembly_order`).
+fn whitening_table(bit_mask: &[u32; 13], bit_assembly_order: &[u32; 6]) -> [u8; 1 << 13] {
+    let mut whitening_table = [0u8; 1 << 13];
+    for i in 0..(1 << 13) {
+        // Computing the CRC32 of the bits of i, in a custom order, using the polynomial 0x2608edb
+        // TODO: is it really standard?
+        let mut crc32: u32 = 0xffffffff;
+        for j in 0..13 {
+            let bit = i & bit_mask[j] != 0;
+            crc32::update_with_bit(&mut crc32, bit);
+        }
+
+        // Selects bits
+        let mut value = 0u8;
+        for j in 0..6 {
+            if crc32 & bit_assembly_order[j] != 0 {
+                value |= 1 << j;
+            }
+        }
+
+        whitening_table[i as usize] = value;
+    }
+
+    whitening_table
+}
+
+/// Number of unwhitened bits obtained from `whitened_bit_count` whitened bits.
+pub fn unwhitened_bit_count(whitened_bit_count: usize) -> usize {
+    6 * (whitened_bit_count / 13)
+}
+
+/// Unwhitened bits of a carrier, decoded from its whitened bits one chunk at a time: every chunk
+/// of 13 bits is turned into 6 bits with the table generated from `seed`.
+///
+/// A partial chunk can't be looked up in the whitening table, so the up to 12 remaining bits
+/// are ignored.
+///
+/// The unwhitened bits are produced lazily, so that they are never all held in memory. The
+/// whitened bits themselves have to be known beforehand, as the whitening table depends on their
+/// count.
+pub(crate) struct UnwhitenedBits<B> {
+    whitened_bits: B,
+    whitening_lookup_table: Box<[u8; 1 << 13]>,
+    /// Index of the next chunk of 13 whitened bits to unwhiten.
+    next_chunk: usize,
+    /// Unwhitened bits of the current chunk, and how many of them are left.
+    unwhitened_chunk: u8,
+    unwhitened_chunk_bits: u32,
+}
+
+impl<B: Borrow<BitVec>> UnwhitenedBits<B> {
+    pub(crate) fn new(whitened_bits: B, seed: usize) -> Self {
+        let remaining_bits = whitened_bits.borrow().len() % 13;
+        if remaining_bits != 0 {
+            warn!(
+                "ignoring the last {} bits of the carrier, as they don't form a whole whitening chunk",
+                remaining_bits
+            );
+        }
+
+        Self::with_table(
+            whitened_bits,
+            Box::new(generate_whitening_lookup_table(seed)),
+        )
+    }
+
+    fn with_table(whitened_bits: B, whitening_lookup_table: Box<[u8; 1 << 13]>) -> Self {
+        UnwhitenedBits {
+            whitened_bits,
+            whitening_lookup_table,
+            next_chunk: 0,
+            unwhitened_chunk: 0,
+            unwhitened_chunk_bits: 0,
+        }
+    }
+}
+
+impl<B: Borrow<BitVec>> Iterator for UnwhitenedBits<B> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.unwhitened_chunk_bits == 0 {
+            let whitened_bits = self.whitened_bits.borrow();
+            if self.next_chunk == whitened_bits.len() / 13 {
+                return None;
+            }
+
+            let chunk = (0..13).fold(0u16, |chunk, j| {
+                chunk << 1 | whitened_bits[13 * self.next_chunk + j] as u16
+            });
+            self.next_chunk += 1;
+
+            self.unwhitened_chunk = self.whitening_lookup_table[chunk as usize];
+            self.unwhitened_chunk_bits = 6;
+        }
+
+        self.unwhitened_chunk_bits -= 1;
+        Some(self.unwhitened_chunk & (1 << self.unwhitened_chunk_bits) != 0)
+    }
+}
+
+/// Unwhitens `whitened_bits` with the table generated from `seed`, lazily. See `UnwhitenedBits`.
+pub(crate) fn unwhitened_bits(
+    whitened_bits: &BitVec,
+    seed: usize,
+) -> impl Iterator<Item = bool> + '_ {
+    UnwhitenedBits::new(whitened_bits, seed)
+}
+
+/// Unwhitens `whitened_bits` with the table generated from `seed`.
+///
+/// See `UnwhitenedBits`. Carriers are unwhitened with the number of whitened bits as the seed.
+pub fn unwhiten(whitened_bits: &BitVec, seed: usize) -> BitVec {
+    unwhitened_bits(whitened_bits, seed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_chunks() {
+        // The CSPRNG picking the bit orders is libObfuscate's, so the table is built from fixed
+        // orders: chunk bits fed most significant first, and the 4th CRC32 bit selection.
+        let bit_mask: [u32; 13] = std::array::from_fn(|j| 1 << (12 - j));
+        let bit_assembly_order = [1 << 0, 1 << 7, 1 << 11, 1 << 12, 1 << 14, 1 << 16];
+        let table = whitening_table(&bit_mask, &bit_assembly_order);
+
+        assert_eq!(crc32::compute(&table), 0xb7a6e78a);
+        for (chunk, entry) in [
+            (0b0_0000_0000_0000, 0b000011),
+            (0b0_0000_0000_0001, 0b101100),
+            (0b1_0000_0000_0001, 0b000001),
+            (0b0_1101_0010_1110, 0b001000),
+            (0b1_1111_1111_1111, 0b110000),
+        ] {
+            assert_eq!(table[chunk], entry, "{chunk:#015b}");
+        }
+
+        let chunks: [u16; 3] = [0b1_0000_0000_0001, 0b0_1101_0010_1110, 0b1_1111_1111_1111];
+        let whitened_bits: BitVec = chunks
+            .iter()
+            .flat_map(|chunk| (0..13).rev().map(move |j| chunk & (1 << j) != 0))
+            .collect();
+        let mut expected_bits = BitVec::from_bytes(&[0b00000100, 0b10001100, 0b00000000]);
+        expected_bits.truncate(18);
+
+        let unwhitened_bits: BitVec =
+            UnwhitenedBits::with_table(&whitened_bits, Box::new(table)).collect();
+        assert_eq!(unwhitened_bits, expected_bits);
+    }
+
+    #[test]
+    fn shuffled_bit_mask() {
+        let bit_mask = [5, 0, 12, 7, 3, 9, 1, 11, 4, 8, 2, 10, 6].map(|index| 1 << index);
+        let bit_assembly_order = [1 << 0, 1 << 2, 1 << 13, 1 << 17, 1 << 19, 1 << 28];
+        let table = whitening_table(&bit_mask, &bit_assembly_order);
+
+        assert_eq!(crc32::compute(&table), 0xabe650d3);
+        assert_eq!(
+            [
+                0,
+                1,
+                0b1_0000_0000_0001,
+                0b0_1101_0010_1110,
+                0b1_1111_1111_1111
+            ]
+            .map(|chunk| table[chunk]),
+            [27, 11, 14, 55, 60]
+        );
+    }
+
+    #[test]
+    fn whitening_hash() {
+        let seed = 39;
+        let table = generate_whitening_lookup_table(seed);
+        assert_eq!(
+            generate_whitening_lookup_table_with_hash(seed, csprng::Hash::Skein512),
+            table
+        );
+
+        for hash in [
+            csprng::Hash::Sha512,
+            csprng::Hash::Grostl512,
+            csprng::Hash::Keccak512,
+        ] {
+            let other_table = generate_whitening_lookup_table_with_hash(seed, hash);
+            assert!(other_table.iter().all(|&entry| entry < 1 << 6));
+            assert_ne!(other_table, table, "{hash:?}");
+        }
+    }
+
+    #[test]
+    fn zero_seed() {
+        let table = generate_whitening_lookup_table(0);
+        assert!(table.iter().all(|&entry| entry < 1 << 6));
+        assert_eq!(generate_whitening_lookup_table(0), table);
+
+        // Nothing to unwhiten
+        assert!(unwhiten(&BitVec::new(), 0).is_empty());
+    }
+
+    #[test]
+    fn unwhitening_length() {
+        for (whitened_len, unwhitened_len) in
+            [(0, 0), (12, 0), (13, 6), (25, 6), (26, 12), (1300, 600)]
+        {
+            let whitened_bits = BitVec::from_elem(whitened_len, true);
+            assert_eq!(unwhiten(&whitened_bits, whitened_len).len(), unwhitened_len);
+            assert_eq!(unwhitened_bit_count(whitened_len), unwhitened_len);
+        }
+    }
+
+    #[test]
+    fn streamed_unwhitening() {
+        // Unwhitens all the bits at once, as a reference.
+        fn buffered_unwhiten(whitened_bits: &BitVec) -> BitVec {
+            let whitening_lookup_table = generate_whitening_lookup_table(whitened_bits.len());
+
+            let mut unwhitened_bits = BitVec::new();
+            for chunk_index in 0..(whitened_bits.len() / 13) {
+                let mut chunk: u16 = 0;
+                for j in 0..13 {
+                    chunk <<= 1;
+                    if whitened_bits[13 * chunk_index + j] {
+                        chunk |= 1;
+                    }
+                }
+                let unwhitened_chunk = whitening_lookup_table[chunk as usize];
+                for j in (0..6).rev() {
+                    unwhitened_bits.push(unwhitened_chunk & (1 << j) != 0);
+                }
+            }
+
+            unwhitened_bits
+        }
+
+        for whitened_len in [0, 13, 100, 6500, 13007] {
+            let whitened_bits: BitVec = (0..whitened_len)
+                .map(|i: usize| (i * 31 + i / 7) % 11 < 5)
+                .collect();
+
+            assert_eq!(
+                unwhiten(&whitened_bits, whitened_len),
+                buffered_unwhiten(&whitened_bits)
+            );
+        }
+    }
+}
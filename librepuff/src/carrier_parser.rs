@@ -0,0 +1,212 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use bit_vec::BitVec;
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::sync::LazyLock;
+
+use crate::carrier::CarrierOptions;
+use crate::carrier_type::CarrierType;
+use crate::parser::{self, ParserWarning, ParsingError};
+use crate::Error;
+
+/// Parser of a carrier file format.
+///
+/// Like OpenPuff's parsers, it returns the bits selected from the carrier, before unwhitening, and
+/// must strictly only read bytes part of the file format, so that trailing data can be detected.
+pub trait CarrierParser {
+    fn parse(
+        &self,
+        reader: &mut dyn Read,
+        options: &CarrierOptions,
+    ) -> Result<(BitVec, Vec<ParserWarning>), ParsingError>;
+
+    /// Like `parse`, for readers that can seek past the parts of the file the parser skips.
+    ///
+    /// Defaults to `parse`.
+    fn parse_seekable(
+        &self,
+        mut reader: &mut dyn ReadSeek,
+        options: &CarrierOptions,
+    ) -> Result<(BitVec, Vec<ParserWarning>), ParsingError> {
+        self.parse(&mut reader, options)
+    }
+}
+
+/// Reader which can seek.
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// Parser of WAVE files.
+struct WavParser;
+
+impl CarrierParser for WavParser {
+    fn parse(
+        &self,
+        mut reader: &mut dyn Read,
+        options: &CarrierOptions,
+    ) -> Result<(BitVec, Vec<ParserWarning>), ParsingError> {
+        parser::wav::parse(&mut reader, &options.wav)
+    }
+
+    fn parse_seekable(
+        &self,
+        mut reader: &mut dyn ReadSeek,
+        options: &CarrierOptions,
+    ) -> Result<(BitVec, Vec<ParserWarning>), ParsingError> {
+        parser::wav::parse_seekable(&mut reader, &options.wav)
+    }
+}
+
+/// Mapping from carrier types to the parsers handling them.
+///
+/// The default registry holds the parsers of every supported carrier type.
+pub struct ParserRegistry {
+    parsers: HashMap<CarrierType, Box<dyn CarrierParser + Send + Sync>>,
+}
+
+static DEFAULT_REGISTRY: LazyLock<ParserRegistry> = LazyLock::new(ParserRegistry::default);
+
+impl ParserRegistry {
+    /// Returns a registry without any parser.
+    pub fn empty() -> Self {
+        Self {
+            parsers: HashMap::new(),
+        }
+    }
+
+    /// Returns a shared instance of the default registry.
+    pub fn builtin() -> &'static Self {
+        &DEFAULT_REGISTRY
+    }
+
+    /// Makes `parser` handle carriers of type `file_type`, replacing the previous parser of that
+    /// type, if any.
+    pub fn register(
+        &mut self,
+        file_type: CarrierType,
+        parser: impl CarrierParser + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.parsers.insert(file_type, Box::new(parser));
+        self
+    }
+
+    /// Returns the parser of carriers of type `file_type`.
+    pub fn get(&self, file_type: CarrierType) -> Option<&(dyn CarrierParser + Send + Sync)> {
+        self.parsers.get(&file_type).map(Box::as_ref)
+    }
+
+    /// Parses a carrier of type `file_type` with the registered parser.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnsupportedCarrier` if no parser is registered for `file_type`.
+    pub fn parse(
+        &self,
+        reader: &mut dyn Read,
+        file_type: CarrierType,
+        options: &CarrierOptions,
+    ) -> Result<(BitVec, Vec<ParserWarning>), Error> {
+        Ok(self.supported(file_type)?.parse(reader, options)?)
+    }
+
+    /// Like `parse`, using `CarrierParser::parse_seekable`.
+    pub fn parse_seekable(
+        &self,
+        reader: &mut dyn ReadSeek,
+        file_type: CarrierType,
+        options: &CarrierOptions,
+    ) -> Result<(BitVec, Vec<ParserWarning>), Error> {
+        Ok(self.supported(file_type)?.parse_seekable(reader, options)?)
+    }
+
+    fn supported(
+        &self,
+        file_type: CarrierType,
+    ) -> Result<&(dyn CarrierParser + Send + Sync), Error> {
+        self.get(file_type)
+            .ok_or(Error::UnsupportedCarrier(file_type))
+    }
+}
+
+impl Default for ParserRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(CarrierType::Wav, WavParser);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_selection::BitSelection;
+    use crate::carrier;
+
+    /// Selects every bit of the carrier.
+    struct RawParser;
+
+    impl CarrierParser for RawParser {
+        fn parse(
+            &self,
+            reader: &mut dyn Read,
+            _options: &CarrierOptions,
+        ) -> Result<(BitVec, Vec<ParserWarning>), ParsingError> {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+
+            Ok((BitVec::from_bytes(&bytes), Vec::new()))
+        }
+    }
+
+    #[test]
+    fn custom_parser() {
+        let bytes = vec![0b1010_0110; 4096];
+        let options = CarrierOptions::new().selection(BitSelection::Medium);
+
+        assert!(matches!(
+            carrier::from_reader_with(&mut &bytes[..], CarrierType::Tga, &options),
+            Err(Error::UnsupportedCarrier(CarrierType::Tga))
+        ));
+
+        let mut registry = ParserRegistry::default();
+        registry.register(CarrierType::Tga, RawParser);
+        let carrier = carrier::from_reader_with_registry(
+            &mut &bytes[..],
+            CarrierType::Tga,
+            &options,
+            &registry,
+        )
+        .unwrap();
+        assert!(carrier.selected_bit_count() > 0);
+
+        let wav = crate::test_utils::wav_file(&vec![0b1000; 13000]);
+        assert_eq!(
+            carrier::from_reader_with_registry(
+                &mut &wav[..],
+                CarrierType::Wav,
+                &options,
+                &registry
+            )
+            .unwrap(),
+            carrier::from_reader_with(&mut &wav[..], CarrierType::Wav, &options).unwrap()
+        );
+
+        assert!(ParserRegistry::empty().get(CarrierType::Wav).is_none());
+        assert!(ParserRegistry::builtin().get(CarrierType::Wav).is_some());
+    }
+}
@@ -15,8 +15,11 @@
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+use crate::Error;
+
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
 pub enum CarrierType {
     _3gp,
     Aiff,
@@ -34,7 +37,30 @@ pub enum CarrierType {
     Wav,
 }
 
+/// Every carrier type.
+const ALL: [CarrierType; 14] = [
+    CarrierType::_3gp,
+    CarrierType::Aiff,
+    CarrierType::Flv,
+    CarrierType::Jpeg,
+    CarrierType::Mp3,
+    CarrierType::Mp4,
+    CarrierType::Au,
+    CarrierType::Pcx,
+    CarrierType::Pdf,
+    CarrierType::Png,
+    CarrierType::Swf,
+    CarrierType::Tga,
+    CarrierType::Vob,
+    CarrierType::Wav,
+];
+
 impl CarrierType {
+    /// Returns every carrier type.
+    pub fn all() -> impl Iterator<Item = Self> {
+        ALL.into_iter()
+    }
+
     /// Returns a type from a file extension.
     ///
     /// The extensions recognized by OpenPuff are:
@@ -52,24 +78,117 @@ impl CarrierType {
     ///  - TGA: `tga`, `vda`, `icb`, `vst`;
     ///  - VOB: `vob`;
     ///  - WAV: `wav`, `wave`;
+    ///
+    /// Extensions are matched case-insensitively, as files coming from Windows often have
+    /// uppercase ones (e.g. `SONG.WAV`).
     pub fn from_extension(extension: &str) -> Option<Self> {
-        match extension {
-            "3gp" | "3gpp" | "3g2" | "3gp2" => Some(Self::_3gp),
-            "aif" | "aiff" => Some(Self::Aiff),
-            "flv" | "f4v" | "f4p" | "f4a" | "f4b" => Some(Self::Flv),
-            "jpg" | "jpe" | "jpeg" | "jfif" => Some(Self::Jpeg),
-            "mp3" => Some(Self::Mp3),
-            "mp4" | "mpg4" | "mpeg4" | "m4a" | "m4v" | "mp4a" => Some(Self::Mp4),
-            "au" | "snd" => Some(Self::Au),
-            "pcx" => Some(Self::Pcx),
-            "pdf" => Some(Self::Pdf),
-            "png" => Some(Self::Png),
-            "swf" => Some(Self::Swf),
-            "tga" | "vda" | "icb" | "vst" => Some(Self::Tga),
-            "vob" => Some(Self::Vob),
-            "wav" | "wave" => Some(Self::Wav),
-
-            _ => None,
+        Self::all().find(|file_type| {
+            file_type
+                .extensions()
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// Returns the file extensions recognized for this type, the canonical one first.
+    ///
+    /// See `CarrierType::from_extension`.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Self::_3gp => &["3gp", "3gpp", "3g2", "3gp2"],
+            Self::Aiff => &["aif", "aiff"],
+            Self::Flv => &["flv", "f4v", "f4p", "f4a", "f4b"],
+            Self::Jpeg => &["jpg", "jpe", "jpeg", "jfif"],
+            Self::Mp3 => &["mp3"],
+            Self::Mp4 => &["mp4", "mpg4", "mpeg4", "m4a", "m4v", "mp4a"],
+            Self::Au => &["au", "snd"],
+            Self::Pcx => &["pcx"],
+            Self::Pdf => &["pdf"],
+            Self::Png => &["png"],
+            Self::Swf => &["swf"],
+            Self::Tga => &["tga", "vda", "icb", "vst"],
+            Self::Vob => &["vob"],
+            Self::Wav => &["wav", "wave"],
+        }
+    }
+
+    /// Returns the canonical file extension of this type, to name new files.
+    pub fn default_extension(&self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    /// Returns whether this is an audio type: WAV, AIFF, MP3 or AU.
+    pub fn is_audio(&self) -> bool {
+        matches!(self, Self::Wav | Self::Aiff | Self::Mp3 | Self::Au)
+    }
+
+    /// Returns whether this is an image type: PNG, JPEG, PCX or TGA.
+    pub fn is_image(&self) -> bool {
+        matches!(self, Self::Png | Self::Jpeg | Self::Pcx | Self::Tga)
+    }
+
+    /// Returns whether this is a video type: MP4, 3GP, FLV, VOB or SWF.
+    pub fn is_video(&self) -> bool {
+        matches!(
+            self,
+            Self::Mp4 | Self::_3gp | Self::Flv | Self::Vob | Self::Swf
+        )
+    }
+
+    /// Returns whether this is a document type: PDF.
+    pub fn is_document(&self) -> bool {
+        matches!(self, Self::Pdf)
+    }
+
+    /// Returns a type from the first bytes of a file, by looking for its signature.
+    ///
+    /// Not present in OpenPuff. TGA files don't have a signature, and are never detected.
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        let starts_with_at = |offset: usize, signature: &[u8]| {
+            bytes.get(offset..offset + signature.len()) == Some(signature)
+        };
+
+        if starts_with_at(0, b"RIFF") && starts_with_at(8, b"WAVE") {
+            Some(Self::Wav)
+        } else if starts_with_at(0, b"FORM")
+            && (starts_with_at(8, b"AIFF") || starts_with_at(8, b"AIFC"))
+        {
+            Some(Self::Aiff)
+        } else if starts_with_at(4, b"ftyp") {
+            if starts_with_at(8, b"3gp") || starts_with_at(8, b"3g2") {
+                Some(Self::_3gp)
+            } else {
+                Some(Self::Mp4)
+            }
+        } else if starts_with_at(0, b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if starts_with_at(0, b"\xff\xd8\xff") {
+            Some(Self::Jpeg)
+        } else if starts_with_at(0, b"FLV") {
+            Some(Self::Flv)
+        } else if starts_with_at(0, b"%PDF") {
+            Some(Self::Pdf)
+        } else if starts_with_at(0, b"FWS")
+            || starts_with_at(0, b"CWS")
+            || starts_with_at(0, b"ZWS")
+        {
+            Some(Self::Swf)
+        } else if starts_with_at(0, b".snd") {
+            Some(Self::Au)
+        } else if starts_with_at(0, b"\x00\x00\x01\xba") {
+            Some(Self::Vob)
+        } else if starts_with_at(0, b"ID3")
+            || (bytes.len() >= 2 && bytes[0] == 0xff && bytes[1] & 0xe0 == 0xe0)
+        {
+            Some(Self::Mp3)
+        } else if bytes.len() >= 3
+            && bytes[0] == 0x0a
+            && [0, 2, 3, 4, 5].contains(&bytes[1])
+            && bytes[2] == 1
+        {
+            Some(Self::Pcx)
+        } else {
+            None
         }
     }
 }
@@ -96,3 +215,127 @@ impl fmt::Display for CarrierType {
         write!(f, "{}", name)
     }
 }
+
+impl FromStr for CarrierType {
+    type Err = Error;
+
+    /// Parses the name of a type, as displayed (e.g. `WAV`), ignoring case.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .find(|file_type| file_type.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::UnknownFiletype)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions() {
+        for file_type in ALL {
+            assert_eq!(
+                CarrierType::from_extension(file_type.default_extension()),
+                Some(file_type)
+            );
+
+            for extension in file_type.extensions() {
+                assert_eq!(CarrierType::from_extension(extension), Some(file_type));
+            }
+        }
+
+        assert_eq!(CarrierType::Wav.default_extension(), "wav");
+        assert_eq!(CarrierType::Jpeg.default_extension(), "jpg");
+        assert_eq!(CarrierType::from_extension("txt"), None);
+        assert_eq!(CarrierType::from_extension(""), None);
+    }
+
+    #[test]
+    fn case_insensitive_extensions() {
+        for extension in ["WAV", "Wav", "wAv", "WAVE"] {
+            assert_eq!(
+                CarrierType::from_extension(extension),
+                Some(CarrierType::Wav)
+            );
+        }
+        assert_eq!(CarrierType::from_extension("JPEG"), Some(CarrierType::Jpeg));
+        assert_eq!(CarrierType::from_extension("3GP2"), Some(CarrierType::_3gp));
+
+        assert_eq!(CarrierType::from_extension("TXT"), None);
+        assert_eq!(CarrierType::from_extension("WAVX"), None);
+    }
+
+    #[test]
+    fn categories() {
+        let category = |file_type: CarrierType| match (
+            file_type.is_audio(),
+            file_type.is_image(),
+            file_type.is_video(),
+            file_type.is_document(),
+        ) {
+            (true, false, false, false) => "audio",
+            (false, true, false, false) => "image",
+            (false, false, true, false) => "video",
+            (false, false, false, true) => "document",
+            _ => panic!("{file_type} must be in exactly one category"),
+        };
+
+        let categories: Vec<_> = ALL.into_iter().map(category).collect();
+        assert_eq!(
+            categories,
+            [
+                "video", "audio", "video", "image", "audio", "video", "audio", "image", "document",
+                "image", "video", "image", "video", "audio"
+            ]
+        );
+    }
+
+    #[test]
+    fn names() {
+        for file_type in CarrierType::all() {
+            let name = file_type.to_string();
+            assert_eq!(name.parse::<CarrierType>().unwrap(), file_type);
+            assert_eq!(
+                name.to_lowercase().parse::<CarrierType>().unwrap(),
+                file_type
+            );
+        }
+        assert_eq!(CarrierType::all().count(), 14);
+
+        assert_eq!("Jpeg".parse::<CarrierType>().unwrap(), CarrierType::Jpeg);
+        assert!(matches!(
+            "jpg".parse::<CarrierType>(),
+            Err(Error::UnknownFiletype)
+        ));
+        assert!("".parse::<CarrierType>().is_err());
+        assert!("WAVE".parse::<CarrierType>().is_err());
+    }
+
+    #[test]
+    fn magic() {
+        let signatures: [(&[u8], CarrierType); 13] = [
+            (b"RIFF\x24\x00\x00\x00WAVEfmt ", CarrierType::Wav),
+            (b"FORM\x00\x00\x00\x00AIFFCOMM", CarrierType::Aiff),
+            (b"\x00\x00\x00\x18ftyp3gp4", CarrierType::_3gp),
+            (b"\x00\x00\x00\x18ftypisom", CarrierType::Mp4),
+            (b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR", CarrierType::Png),
+            (b"\xff\xd8\xff\xe0\x00\x10JFIF", CarrierType::Jpeg),
+            (b"FLV\x01\x05", CarrierType::Flv),
+            (b"%PDF-1.7", CarrierType::Pdf),
+            (b"CWS\x0a", CarrierType::Swf),
+            (b".snd\x00\x00\x00\x18", CarrierType::Au),
+            (b"\x00\x00\x01\xba\x44", CarrierType::Vob),
+            (b"ID3\x04\x00", CarrierType::Mp3),
+            (b"\x0a\x05\x01\x08", CarrierType::Pcx),
+        ];
+
+        for (bytes, file_type) in signatures {
+            assert_eq!(CarrierType::from_magic(bytes), Some(file_type));
+        }
+
+        assert_eq!(CarrierType::from_magic(b""), None);
+        assert_eq!(CarrierType::from_magic(b"RIFF"), None);
+        assert_eq!(CarrierType::from_magic(b"RIFF\x24\x00\x00\x00AVI "), None);
+        assert_eq!(CarrierType::from_magic(b"plain text"), None);
+    }
+}
@@ -15,11 +15,23 @@
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
 use libobfuscate::{multi, scramble};
+use log::trace;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::collections::HashMap;
 
-use crate::carrier::EncryptedCarrier;
+use crate::carrier::{EncryptedCarrier, IV_SIZE};
+use crate::crc32;
 use crate::passwords::Passwords;
+use crate::Error;
 
-fn derive_next_prekey(previous_prekey: u16, previous_iv: &[u8; 256]) -> u16 {
+/// Derives the prekey of a carrier from the prekey and decrypted IV of the previous carrier.
+///
+/// Each byte of the IV is folded into a 16-bit sum, depending on its value rather than its
+/// position: bytes with an odd value contribute to the high byte of the sum (`byte << 8`), bytes
+/// with an even value to its low byte. The sum is added to the previous prekey, all additions
+/// wrapping around.
+pub(crate) fn derive_next_prekey(previous_prekey: u16, previous_iv: &[u8; IV_SIZE]) -> u16 {
     let function_of_iv = previous_iv
         .iter()
         .map(|&iv_value| {
@@ -29,16 +41,54 @@ fn derive_next_prekey(previous_prekey: u16, previous_iv: &[u8; 256]) -> u16 {
                 iv_value as u16
             }
         })
-        .sum::<u16>();
+        .fold(0u16, u16::wrapping_add);
 
-    previous_prekey + function_of_iv
+    previous_prekey.wrapping_add(function_of_iv)
 }
 
-fn derive_key(carrier_position: usize, prekey: u16) -> u32 {
+/// Derives the key of the carrier at position `carrier_position` in the chain from its prekey.
+///
+/// The prekey forms the high 16 bits of the key, to which OpenPuff's constant `0x502239c3` and the
+/// position of the carrier are added, wrapping around.
+///
+/// # Panics
+///
+/// Panics if `carrier_position` doesn't fit in a `u32`.
+pub(crate) fn derive_key(carrier_position: usize, prekey: u16) -> u32 {
     let carrier_position = u32::try_from(carrier_position).unwrap();
     let prekey = u32::from(prekey);
 
-    prekey * 0x10000 + 0x502239c3 + carrier_position
+    (prekey << 16)
+        .wrapping_add(0x502239c3)
+        .wrapping_add(carrier_position)
+}
+
+/// Returns the keys of the first `carrier_count` carriers of a chain, given their decrypted IVs.
+///
+/// This exposes the results of `derive_next_prekey` and `derive_key`, to check them against
+/// another implementation. The key of a carrier only depends on the IVs of the previous ones: the
+/// IV of the last carrier is not needed. Carriers without any selected bit must be left out, as
+/// they aren't part of the chain.
+///
+/// # Panics
+///
+/// Panics if fewer than `carrier_count - 1` IVs are given.
+pub fn derive_keys(carrier_count: usize, decrypted_ivs: &[[u8; IV_SIZE]]) -> Vec<u32> {
+    assert!(
+        decrypted_ivs.len() + 1 >= carrier_count,
+        "the IVs of the previous carriers are needed to derive a key"
+    );
+
+    let mut keys = Vec::with_capacity(carrier_count);
+    let mut prekey = 0;
+    for i in 0..carrier_count {
+        if i > 0 {
+            prekey = derive_next_prekey(prekey, &decrypted_ivs[i - 1]);
+        }
+        keys.push(derive_key(i, prekey));
+    }
+
+    keys
 }
 
 /// IVs used to decrypt carrier IVs.
@@ -60,31 +110,166 @@ const INITIALIZATION_VECTORS: multi::Ivs = multi::Ivs {
     twofish: *b"\xd7\xd5\xc0\x06\xa9\x21\xf6\x14\x7e\x14\x64\x83\x1c\x15\xab\x32",
     unicorn_a: *b"\xc0\x66\xb8\x23\xc0\xf6\xdf\x62\xa7\xc7\x60\x37\x88\xd1\xef\x95",
 };
-fn decrypt_iv(iv: &mut [u8; 256], key: u32) {
+/// Decrypts the IV of a carrier in place, with its key.
+pub(crate) fn decrypt_iv(iv: &mut [u8; IV_SIZE], key: u32) {
     let password = &format!("{key:010}");
     scramble::descramble(iv, password, key).unwrap();
     multi::decrypt(iv, &INITIALIZATION_VECTORS, password, password, key).unwrap();
 }
 
-fn decrypt_content(content: &mut [u8], ivs: &multi::Ivs, key: u32, passwords: &Passwords) {
+fn decrypt_content(content: &mut [u8], multi: &mut multi::Multi, key: u32, passwords: &Passwords) {
     scramble::descramble(content, &passwords.c, key).unwrap();
-    multi::decrypt(content, ivs, &passwords.a, &passwords.b, key).unwrap();
+    multi.decrypt(content).unwrap();
+}
+
+/// Encrypts the embeddings of a chain of carriers, given the decrypted IVs of the carriers: the
+/// inverse of `decrypt_carrier_chain`, to build carriers hiding known files.
+#[cfg(any(test, feature = "test-utils"))]
+pub(crate) fn encrypt_carrier_chain(
+    embeddings: Vec<CarrierEmbeddings>,
+    decrypted_ivs: &[[u8; IV_SIZE]],
+    passwords: &Passwords,
+) -> Vec<EncryptedCarrier> {
+    let keys = derive_keys(embeddings.len(), decrypted_ivs);
+
+    embeddings
+        .into_iter()
+        .zip(decrypted_ivs)
+        .zip(keys)
+        .map(
+            |(
+                (
+                    CarrierEmbeddings {
+                        mut data,
+                        mut decoy,
+                    },
+                    decrypted_iv,
+                ),
+                key,
+            )| {
+                let mut multi = multi::Multi::new(
+                    multi::Ivs::from_bytes(decrypted_iv),
+                    passwords.a,
+                    passwords.b,
+                    key,
+                )
+                .unwrap();
+                multi.encrypt(&mut data).unwrap();
+                scramble::scramble(&mut data, passwords.c, key).unwrap();
+
+                multi.reset();
+                multi.encrypt(&mut decoy).unwrap();
+                scramble::scramble(&mut decoy, passwords.c, key).unwrap();
+
+                let password = &format!("{key:010}");
+                let mut iv = *decrypted_iv;
+                multi::encrypt(&mut iv, &INITIALIZATION_VECTORS, password, password, key).unwrap();
+                scramble::scramble(&mut iv, password, key).unwrap();
+
+                EncryptedCarrier {
+                    iv,
+                    data,
+                    decoy,
+                    other_bits: bit_vec::BitVec::new(),
+                    warnings: Vec::new(),
+                }
+            },
+        )
+        .collect()
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct CarrierEmbeddings {
     pub data: Vec<u8>,
     pub decoy: Vec<u8>,
 }
 
-pub fn decrypt_carrier_chain(
-    carriers: impl IntoIterator<Item = EncryptedCarrier>,
-    passwords: Passwords,
-) -> Vec<CarrierEmbeddings> {
-    let mut embeddings = Vec::new();
+impl CarrierEmbeddings {
+    /// Returns whether the carrier holds neither data nor decoy bytes, like carriers left out of
+    /// the chain.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty() && self.decoy.is_empty()
+    }
+
+    /// Returns the number of data and decoy bytes of the carrier.
+    pub fn total_bytes(&self) -> usize {
+        self.data.len() + self.decoy.len()
+    }
+}
 
-    let mut previous_parameters: Option<(u16, [u8; 256])> = None;
+/// Concatenates the decrypted embeddings of a chain of carriers, in carrier order.
+///
+/// Returns the data and decoy streams, in which OpenPuff stores its embedded files (see
+/// `EmbeddedFile::from_bits`).
+pub fn concatenate_embeddings(embeddings: &[CarrierEmbeddings]) -> (Vec<u8>, Vec<u8>) {
+    let data = embeddings
+        .iter()
+        .flat_map(|embeddings| &embeddings.data)
+        .copied()
+        .collect();
+    let decoy = embeddings
+        .iter()
+        .flat_map(|embeddings| &embeddings.decoy)
+        .copied()
+        .collect();
+
+    (data, decoy)
+}
+
+/// Key and decrypted IV of a carrier, needed to decrypt its contents.
+struct CarrierKey {
+    key: u32,
+    iv: [u8; IV_SIZE],
+}
+
+/// Derives the key of each carrier, and decrypts their IV.
+///
+/// This has to be done sequentially, as the key of a carrier depends on the IVs of the previous
+/// ones.
+///
+/// Carriers without any selected bit get no key. OpenPuff refuses to add carriers too small to
+/// hold anything to its list of carriers, so they are left out of the chain: they don't take a
+/// position, and don't change the prekey of the next carrier.
+fn derive_carrier_keys(carriers: &[EncryptedCarrier]) -> Vec<Option<CarrierKey>> {
+    derive_partial_chain_keys(carriers.iter().map(Some))
+}
+
+/// Like `derive_carrier_keys`, where `None` stands for a missing carrier.
+///
+/// A missing carrier gets no key, but it keeps its position in the chain. Its decrypted IV is
+/// unknown and taken to be all zeros, so it doesn't change the prekey of the next carrier: the
+/// keys of the following carriers are most likely wrong.
+///
+/// The prekey, key and start of the decrypted IV of each carrier are logged at the trace level,
+/// to tell whether a failed extraction goes wrong this early.
+fn derive_partial_chain_keys<'a>(
+    carriers: impl IntoIterator<Item = Option<&'a EncryptedCarrier>>,
+) -> Vec<Option<CarrierKey>> {
+    let mut keys = Vec::new();
+
+    let mut previous_parameters: Option<(u16, [u8; IV_SIZE])> = None;
+
+    let mut i = 0;
+    for (index, encrypted_carrier) in carriers.into_iter().enumerate() {
+        let Some(encrypted_carrier) = encrypted_carrier else {
+            let prekey = match previous_parameters {
+                None => 0,
+                Some((prekey, iv)) => derive_next_prekey(prekey, &iv),
+            };
+            trace!("carrier #{index}: missing, at position {i} with prekey {prekey:04x}");
+
+            keys.push(None);
+            previous_parameters = Some((prekey, [0; IV_SIZE]));
+            i += 1;
+            continue;
+        };
+
+        if encrypted_carrier.selected_bit_count() == 0 {
+            trace!("carrier #{index}: no selected bit, left out of the chain");
+            keys.push(None);
+            continue;
+        }
 
-    for (i, encrypted_carrier) in carriers.into_iter().enumerate() {
         // A prekey is refered as a function of the previous carriers.
         // The first carrier's prekey is 0; for the following ones the decrypted IVs are also
         // taken into consideration.
@@ -96,22 +281,593 @@ pub fn decrypt_carrier_chain(
         let key = derive_key(i, prekey);
 
         // Decrypts the IV
-        let mut iv: [u8; 256] = encrypted_carrier.iv;
+        let mut iv: [u8; IV_SIZE] = encrypted_carrier.iv;
         decrypt_iv(&mut iv, key);
+        trace!(
+            "carrier #{index}: position {i}, prekey {prekey:04x}, key {key:08x}, decrypted IV starting with {:02x?}",
+            &iv[..8]
+        );
+
+        keys.push(Some(CarrierKey { key, iv }));
 
-        let ivs = multi::Ivs::from_bytes(&iv);
+        previous_parameters = Some((prekey, iv));
+        i += 1;
+    }
 
-        // Decrypt the two contents
-        let mut data: Vec<u8> = encrypted_carrier.data;
-        decrypt_content(&mut data, ivs, key, &passwords);
+    keys
+}
 
-        let mut decoy: Vec<u8> = encrypted_carrier.decoy;
-        decrypt_content(&mut decoy, ivs, key, &passwords);
+/// Finds carriers with the same content, whatever their path.
+///
+/// Returns the pairs of positions `(i, j)`, with `i < j`, such that carrier `j` duplicates
+/// carrier `i`, the first carrier with that content. OpenPuff refuses duplicate carriers.
+pub fn detect_duplicate_carriers(carriers: &[EncryptedCarrier]) -> Vec<(usize, usize)> {
+    // Carriers are first grouped by IV, to only compare the ones likely to be identical.
+    let mut carriers_by_iv: HashMap<&[u8; IV_SIZE], Vec<usize>> = HashMap::new();
+    let mut duplicates = Vec::new();
 
-        embeddings.push(CarrierEmbeddings { data, decoy });
+    for (j, carrier) in carriers.iter().enumerate() {
+        let same_iv = carriers_by_iv.entry(&carrier.iv).or_default();
 
-        previous_parameters = Some((prekey, iv));
+        match same_iv.iter().find(|&&i| carriers[i] == *carrier) {
+            Some(&i) => duplicates.push((i, j)),
+            None => same_iv.push(j),
+        }
     }
 
-    embeddings
+    duplicates
+}
+
+/// Checks that the total number of bits selected in `carriers` fits in 32 bits.
+///
+/// OpenPuff counts the selected bits of a chain using 32-bit arithmetic, and refuses chains
+/// exceeding that limit.
+///
+/// # Errors
+///
+/// Returns `Error::TooManyBits` if the total overflows a `u32`.
+pub fn validate_bit_budget(carriers: &[EncryptedCarrier]) -> Result<(), Error> {
+    validate_bit_counts(carriers.iter().map(EncryptedCarrier::selected_bit_count))
+}
+
+fn validate_bit_counts(selected_bit_counts: impl IntoIterator<Item = usize>) -> Result<(), Error> {
+    selected_bit_counts
+        .into_iter()
+        .try_fold(0u32, |total, count| {
+            u32::try_from(count)
+                .ok()
+                .and_then(|count| total.checked_add(count))
+        })
+        .map(|_| ())
+        .ok_or(Error::TooManyBits)
+}
+
+/// Number of carriers from which OpenPuff refuses a chain.
+pub const MAX_CARRIER_COUNT: usize = 65535;
+
+/// Summary of a chain of carriers, computed before decrypting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightReport {
+    pub carrier_count: usize,
+    /// Total number of data (or decoy) bits selected in the carriers.
+    pub total_capacity_bits: u64,
+    /// Whether the total number of selected bits overflows 32 bits. See `validate_bit_budget`.
+    pub too_many_bits: bool,
+    /// Whether there are `MAX_CARRIER_COUNT` carriers or more.
+    pub too_many_carriers: bool,
+    /// Carriers with the same content. See `detect_duplicate_carriers`.
+    pub duplicates: Vec<(usize, usize)>,
+}
+
+impl PreflightReport {
+    /// Returns whether OpenPuff would accept the chain.
+    pub fn is_openpuff_compatible(&self) -> bool {
+        !self.too_many_bits && !self.too_many_carriers && self.duplicates.is_empty()
+    }
+}
+
+/// Checks the conditions OpenPuff puts on a chain of carriers, and sums their capacity.
+pub fn preflight(carriers: &[EncryptedCarrier]) -> PreflightReport {
+    let selected_bit_counts = carriers.iter().map(EncryptedCarrier::selected_bit_count);
+
+    PreflightReport {
+        carrier_count: carriers.len(),
+        total_capacity_bits: selected_bit_counts.clone().map(|count| count as u64).sum(),
+        too_many_bits: validate_bit_counts(selected_bit_counts).is_err(),
+        too_many_carriers: carriers.len() >= MAX_CARRIER_COUNT,
+        duplicates: detect_duplicate_carriers(carriers),
+    }
+}
+
+/// Returns a checksum of the keys of a chain of carriers, which depends on their order.
+///
+/// The keys only depend on the carriers' encrypted IVs and positions, not on the passwords: the
+/// fingerprint can be recorded when hiding a file, and compared before extracting it to make
+/// sure the carriers are given in the same order. Not present in OpenPuff.
+pub fn chain_fingerprint(carriers: &[EncryptedCarrier]) -> u32 {
+    let keys: Vec<u8> = derive_carrier_keys(carriers)
+        .iter()
+        .flatten()
+        .flat_map(|carrier_key| carrier_key.key.to_le_bytes())
+        .collect();
+
+    crc32::compute(&keys)
+}
+
+/// Decrypts the data and decoy contents of a carrier.
+///
+/// Carriers left out of the chain, without a key, have empty embeddings.
+fn decrypt_carrier(
+    encrypted_carrier: EncryptedCarrier,
+    carrier_key: Option<&CarrierKey>,
+    passwords: &Passwords,
+) -> CarrierEmbeddings {
+    let Some(carrier_key) = carrier_key else {
+        return CarrierEmbeddings::default();
+    };
+
+    let ivs = multi::Ivs::from_bytes(&carrier_key.iv);
+    let mut multi = multi::Multi::new(ivs, passwords.a, passwords.b, carrier_key.key).unwrap();
+
+    // Decrypt the two contents
+    let mut data: Vec<u8> = encrypted_carrier.data;
+    decrypt_content(&mut data, &mut multi, carrier_key.key, passwords);
+
+    multi.reset();
+
+    let mut decoy: Vec<u8> = encrypted_carrier.decoy;
+    decrypt_content(&mut decoy, &mut multi, carrier_key.key, passwords);
+
+    trace!(
+        "carrier with key {:08x}: decrypted {} data bytes and {} decoy bytes",
+        carrier_key.key,
+        data.len(),
+        decoy.len()
+    );
+
+    CarrierEmbeddings { data, decoy }
+}
+
+/// Decrypts the data and decoy contents of a chain of carriers, given in order.
+///
+/// Carriers without any selected bit are left out of the chain, as OpenPuff does: their
+/// embeddings are empty.
+pub fn decrypt_carrier_chain(
+    carriers: impl IntoIterator<Item = EncryptedCarrier>,
+    passwords: Passwords,
+) -> Vec<CarrierEmbeddings> {
+    decrypt_carrier_chain_with_progress(carriers, passwords, |_, _| {})
+}
+
+/// Same as `decrypt_carrier_chain`, but `on_progress` is called with the number of carriers
+/// decrypted so far and the total number of carriers, after each carrier is decrypted.
+pub fn decrypt_carrier_chain_with_progress(
+    carriers: impl IntoIterator<Item = EncryptedCarrier>,
+    passwords: Passwords,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Vec<CarrierEmbeddings> {
+    let carriers: Vec<EncryptedCarrier> = carriers.into_iter().collect();
+    let keys = derive_carrier_keys(&carriers);
+
+    let total = carriers.len();
+    carriers
+        .into_iter()
+        .zip(&keys)
+        .enumerate()
+        .map(|(i, (carrier, key))| {
+            let embeddings = decrypt_carrier(carrier, key.as_ref(), &passwords);
+            on_progress(i + 1, total);
+            embeddings
+        })
+        .collect()
+}
+
+/// Same as `decrypt_carrier_chain`, where `None` stands for a missing carrier, for forensic
+/// analysis. Not present in OpenPuff, which can't extract anything when a carrier is missing.
+///
+/// A missing carrier has empty embeddings, but keeps its position in the chain (see
+/// `derive_partial_chain_keys`). Since its IV is unknown, the carriers following it are decrypted
+/// with keys that are most likely wrong: the embeddings of the carriers before the first missing
+/// one are right, the following ones are garbage.
+pub fn decrypt_partial_carrier_chain(
+    carriers: impl IntoIterator<Item = Option<EncryptedCarrier>>,
+    passwords: Passwords,
+) -> Vec<CarrierEmbeddings> {
+    let carriers: Vec<Option<EncryptedCarrier>> = carriers.into_iter().collect();
+    let keys = derive_partial_chain_keys(carriers.iter().map(Option::as_ref));
+
+    carriers
+        .into_iter()
+        .zip(&keys)
+        .map(|(carrier, key)| match carrier {
+            Some(carrier) => decrypt_carrier(carrier, key.as_ref(), &passwords),
+            None => CarrierEmbeddings::default(),
+        })
+        .collect()
+}
+
+/// Same as `decrypt_carrier_chain`, but the contents of the carriers are decrypted in parallel.
+///
+/// Only the carriers' IVs are decrypted sequentially, as the key of a carrier depends on the IVs
+/// of the previous ones.
+#[cfg(feature = "rayon")]
+pub fn decrypt_carrier_chain_parallel(
+    carriers: impl IntoIterator<Item = EncryptedCarrier>,
+    passwords: Passwords,
+) -> Vec<CarrierEmbeddings> {
+    let carriers: Vec<EncryptedCarrier> = carriers.into_iter().collect();
+    let keys = derive_carrier_keys(&carriers);
+
+    carriers
+        .into_par_iter()
+        .zip(keys.par_iter())
+        .map(|(carrier, key)| decrypt_carrier(carrier, key.as_ref(), &passwords))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bit_vec::BitVec;
+
+    fn carriers(count: u8) -> impl Iterator<Item = EncryptedCarrier> {
+        (0..count).map(|i| EncryptedCarrier {
+            iv: [i; 256],
+            data: vec![i.wrapping_mul(3); 512],
+            decoy: vec![i.wrapping_mul(5); 512],
+            other_bits: BitVec::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    fn passwords() -> Passwords<'static> {
+        Passwords::from_fields("password", None, None).unwrap()
+    }
+
+    #[test]
+    fn progress() {
+        let mut calls = Vec::new();
+        let embeddings = decrypt_carrier_chain_with_progress(carriers(3), passwords(), |i, n| {
+            calls.push((i, n))
+        });
+
+        assert_eq!(calls, [(1, 3), (2, 3), (3, 3)]);
+        assert_eq!(embeddings, decrypt_carrier_chain(carriers(3), passwords()));
+    }
+
+    #[test]
+    fn empty_carriers() {
+        let carriers: Vec<EncryptedCarrier> = carriers(3).collect();
+        let empty = EncryptedCarrier {
+            iv: [0xff; 256],
+            data: Vec::new(),
+            decoy: Vec::new(),
+            other_bits: BitVec::new(),
+            warnings: Vec::new(),
+        };
+        let with_empty = vec![
+            carriers[0].clone(),
+            empty.clone(),
+            carriers[1].clone(),
+            carriers[2].clone(),
+            empty,
+        ];
+
+        // Empty carriers don't change the keys of the following ones
+        assert_eq!(chain_fingerprint(&with_empty), chain_fingerprint(&carriers));
+
+        let mut expected = decrypt_carrier_chain(carriers, passwords());
+        expected.insert(1, CarrierEmbeddings::default());
+        expected.push(CarrierEmbeddings::default());
+        assert_eq!(decrypt_carrier_chain(with_empty, passwords()), expected);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn duplicate_carriers() {
+        use crate::bit_selection::BitSelection;
+        use crate::carrier;
+        use crate::test_utils;
+
+        let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+        let wav = test_utils::wav_file(&samples);
+        let other_wav = test_utils::wav_file(&samples[1..]);
+
+        let paths = [
+            test_utils::temporary_file("duplicate_carriers_1.wav", &wav),
+            test_utils::temporary_file("duplicate_carriers_2.wav", &other_wav),
+            test_utils::temporary_file("duplicate_carriers_copy.wav", &wav),
+        ];
+        let carriers: Vec<EncryptedCarrier> = paths
+            .iter()
+            .map(|path| carrier::from_file(path, BitSelection::Medium).unwrap())
+            .collect();
+
+        assert_eq!(detect_duplicate_carriers(&carriers), [(0, 2)]);
+        assert!(detect_duplicate_carriers(&carriers[..2]).is_empty());
+
+        // Same IV, different content
+        let mut modified = carriers[0].clone();
+        modified.data[0] ^= 1;
+        assert!(detect_duplicate_carriers(&[carriers[0].clone(), modified]).is_empty());
+
+        let same = vec![carriers[1].clone(); 3];
+        assert_eq!(detect_duplicate_carriers(&same), [(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn bit_budget() {
+        assert!(validate_bit_budget(&carriers(3).collect::<Vec<_>>()).is_ok());
+
+        assert!(validate_bit_counts([]).is_ok());
+        assert!(validate_bit_counts([1 << 31, (1 << 31) - 1]).is_ok());
+        assert!(matches!(
+            validate_bit_counts([1 << 31, 1 << 31]),
+            Err(Error::TooManyBits)
+        ));
+        assert!(matches!(
+            validate_bit_counts([128, usize::MAX]),
+            Err(Error::TooManyBits)
+        ));
+        assert!(matches!(
+            validate_bit_counts(std::iter::repeat_n(1 << 24, 256)),
+            Err(Error::TooManyBits)
+        ));
+    }
+
+    #[test]
+    fn preflight_report() {
+        let chain: Vec<EncryptedCarrier> = carriers(3).collect();
+        let report = preflight(&chain);
+        assert_eq!(
+            report,
+            PreflightReport {
+                carrier_count: 3,
+                total_capacity_bits: 3 * 512 * 8,
+                too_many_bits: false,
+                too_many_carriers: false,
+                duplicates: vec![],
+            }
+        );
+        assert!(report.is_openpuff_compatible());
+
+        let mut chain_with_duplicate = chain.clone();
+        chain_with_duplicate.push(chain[1].clone());
+        let report = preflight(&chain_with_duplicate);
+        assert_eq!(report.duplicates, [(1, 3)]);
+        assert!(!report.is_openpuff_compatible());
+
+        // 2 * 2^31 selected bits. The zeroed contents aren't backed by memory until written, and
+        // the carriers have different IVs so they're never compared.
+        let huge_carriers: Vec<EncryptedCarrier> = (0..2u8)
+            .map(|i| EncryptedCarrier {
+                iv: [0xf0 + i; 256],
+                data: vec![0; 1 << 28],
+                decoy: vec![],
+                other_bits: BitVec::new(),
+                warnings: Vec::new(),
+            })
+            .collect();
+        let report = preflight(&huge_carriers);
+        assert!(report.too_many_bits);
+        assert_eq!(report.total_capacity_bits, 1 << 32);
+        assert!(!report.is_openpuff_compatible());
+        assert!(!preflight(&huge_carriers[1..]).too_many_bits);
+
+        let many_carriers: Vec<EncryptedCarrier> = (0..MAX_CARRIER_COUNT)
+            .map(|i| {
+                let mut iv = [0; 256];
+                iv[..8].copy_from_slice(&(i as u64).to_le_bytes());
+                EncryptedCarrier {
+                    iv,
+                    data: vec![],
+                    decoy: vec![],
+                    other_bits: BitVec::new(),
+                    warnings: Vec::new(),
+                }
+            })
+            .collect();
+        let report = preflight(&many_carriers);
+        assert!(report.too_many_carriers);
+        assert!(preflight(&many_carriers[1..]).is_openpuff_compatible());
+    }
+
+    #[test]
+    fn prekey_derivation() {
+        assert_eq!(derive_next_prekey(0, &[0; 256]), 0);
+        assert_eq!(derive_next_prekey(0x1234, &[0; 256]), 0x1234);
+        // Bytes with an even value are summed as is
+        assert_eq!(derive_next_prekey(0, &[2; 256]), 0x0200);
+        // Bytes with an odd value are shifted: 256 * 0x0100 wraps around to 0
+        assert_eq!(derive_next_prekey(0x1234, &[1; 256]), 0x1234);
+
+        let mut iv = [0; 256];
+        iv[0] = 3;
+        iv[1] = 4;
+        iv[255] = 0xff;
+        // The values matter, not the positions: 0xfff0 + 0x0300 + 0x0004 + 0xff00, wrapping around
+        assert_eq!(derive_next_prekey(0xfff0, &iv), 0x01f4);
+    }
+
+    #[test]
+    fn key_derivation() {
+        assert_eq!(derive_key(0, 0), 0x502239c3);
+        assert_eq!(derive_key(3, 0x1234), 0x625639c6);
+        // 0xffff0000 + 0x502239c3 + 1, wrapping around
+        assert_eq!(derive_key(1, 0xffff), 0x502139c4);
+    }
+
+    #[test]
+    fn missing_carrier() {
+        let carriers: Vec<EncryptedCarrier> = carriers(3).collect();
+        let partial_chain = || [Some(carriers[0].clone()), None, Some(carriers[2].clone())];
+
+        // Without missing carriers, nothing changes
+        assert_eq!(
+            decrypt_partial_carrier_chain(carriers.iter().cloned().map(Some), passwords()),
+            decrypt_carrier_chain(carriers.clone(), passwords())
+        );
+
+        let keys = derive_partial_chain_keys(partial_chain().iter().map(Option::as_ref));
+        assert!(keys[1].is_none());
+        // The missing carrier keeps its position, and its IV is taken to be all zeros.
+        let first_iv = keys[0].as_ref().unwrap().iv;
+        assert_eq!(
+            [keys[0].as_ref().unwrap().key, keys[2].as_ref().unwrap().key],
+            [
+                derive_keys(3, &[first_iv, [0; 256]])[0],
+                derive_keys(3, &[first_iv, [0; 256]])[2]
+            ]
+        );
+
+        let complete = decrypt_carrier_chain(carriers.clone(), passwords());
+        let partial = decrypt_partial_carrier_chain(partial_chain(), passwords());
+        assert_eq!(partial.len(), 3);
+        assert_eq!(partial[0], complete[0]);
+        assert_eq!(partial[1], CarrierEmbeddings::default());
+
+        // Past the gap, the embeddings are only right if the decrypted IV of the missing carrier
+        // happens to be all zeros.
+        let embeddings: Vec<CarrierEmbeddings> = (0..3)
+            .map(|i| CarrierEmbeddings {
+                data: vec![b'a' + i; 16],
+                decoy: vec![b'x' + i; 16],
+            })
+            .collect();
+        for (missing_iv, recovered) in [([0; IV_SIZE], true), ([0x42; IV_SIZE], false)] {
+            let mut chain = encrypt_carrier_chain(
+                embeddings.clone(),
+                &[[7; IV_SIZE], missing_iv, [9; IV_SIZE]],
+                &passwords(),
+            )
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+            chain[1] = None;
+
+            let partial = decrypt_partial_carrier_chain(chain, passwords());
+            assert_eq!(partial[0], embeddings[0]);
+            assert_eq!(partial[1], CarrierEmbeddings::default());
+            assert_eq!(partial[2] == embeddings[2], recovered);
+        }
+    }
+
+    #[test]
+    fn single_iv_decryption() {
+        let carriers: Vec<EncryptedCarrier> = carriers(2).collect();
+        let keys = derive_carrier_keys(&carriers);
+
+        for (carrier, carrier_key) in carriers.iter().zip(keys.iter().flatten()) {
+            assert_eq!(carrier.decrypt_iv(carrier_key.key), carrier_key.iv);
+        }
+        // The key of the first carrier doesn't depend on the others.
+        assert_eq!(
+            carriers[0].decrypt_iv(0x502239c3),
+            keys[0].as_ref().unwrap().iv
+        );
+        assert_ne!(
+            carriers[0].decrypt_iv(0x502239c4),
+            keys[0].as_ref().unwrap().iv
+        );
+    }
+
+    #[test]
+    fn keys_derivation() {
+        let mut first_iv = [0; 256];
+        first_iv[0] = 1;
+        first_iv[1] = 2;
+        // First prekey: 0. Second prekey: 0x0100 + 0x0002, so the second key is
+        // 0x01020000 + 0x502239c3 + 1
+        assert_eq!(
+            derive_keys(2, &[first_iv, [0xff; 256]]),
+            [0x502239c3, 0x512439c4]
+        );
+        // The IV of the last carrier is not needed
+        assert_eq!(derive_keys(2, &[first_iv]), [0x502239c3, 0x512439c4]);
+        assert!(derive_keys(0, &[]).is_empty());
+
+        // Same keys as when decrypting
+        let carriers: Vec<EncryptedCarrier> = carriers(3).collect();
+        let carrier_keys: Vec<CarrierKey> = derive_carrier_keys(&carriers)
+            .into_iter()
+            .flatten()
+            .collect();
+        let ivs: Vec<[u8; 256]> = carrier_keys.iter().map(|key| key.iv).collect();
+        let keys: Vec<u32> = carrier_keys.iter().map(|key| key.key).collect();
+        assert_eq!(derive_keys(3, &ivs), keys);
+    }
+
+    #[test]
+    #[should_panic]
+    fn keys_derivation_missing_ivs() {
+        derive_keys(3, &[[0; 256]]);
+    }
+
+    #[test]
+    fn fingerprint() {
+        let ordered: Vec<EncryptedCarrier> = carriers(3).collect();
+        let mut reordered = ordered.clone();
+        reordered.swap(1, 2);
+
+        assert_eq!(chain_fingerprint(&ordered), chain_fingerprint(&ordered));
+        assert_ne!(chain_fingerprint(&ordered), chain_fingerprint(&reordered));
+        assert_ne!(
+            chain_fingerprint(&ordered),
+            chain_fingerprint(&ordered[..2])
+        );
+    }
+
+    #[test]
+    fn embeddings_size() {
+        let empty = CarrierEmbeddings::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.total_bytes(), 0);
+
+        let embeddings = CarrierEmbeddings {
+            data: vec![1, 2, 3],
+            decoy: vec![4, 5, 6],
+        };
+        assert!(!embeddings.is_empty());
+        assert_eq!(embeddings.total_bytes(), 6);
+
+        let decoy_only = CarrierEmbeddings {
+            data: Vec::new(),
+            decoy: vec![4],
+        };
+        assert!(!decoy_only.is_empty());
+        assert_eq!(decoy_only.total_bytes(), 1);
+    }
+
+    #[test]
+    fn embeddings_concatenation() {
+        let embeddings = [
+            CarrierEmbeddings {
+                data: vec![1, 2],
+                decoy: vec![10],
+            },
+            CarrierEmbeddings {
+                data: vec![],
+                decoy: vec![20, 21],
+            },
+            CarrierEmbeddings {
+                data: vec![3],
+                decoy: vec![30],
+            },
+        ];
+
+        assert_eq!(
+            concatenate_embeddings(&embeddings),
+            (vec![1, 2, 3], vec![10, 20, 21, 30])
+        );
+        assert_eq!(concatenate_embeddings(&[]), (vec![], vec![]));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_decryption() {
+        assert_eq!(
+            decrypt_carrier_chain_parallel(carriers(8), passwords()),
+            decrypt_carrier_chain(carriers(8), passwords())
+        );
+    }
 }
@@ -19,14 +19,18 @@
 
 const CRC32_POLYNOMIAL: u32 = 0x2608edb;
 
+/// Updates `crc32` with `bit`, without branching on the data.
 pub fn update_with_bit(crc32: &mut u32, bit: bool) {
-    if ((*crc32 >> 31) == 1) ^ bit {
-        *crc32 = (*crc32 ^ CRC32_POLYNOMIAL) << 1 | 1;
-    } else {
-        *crc32 <<= 1;
-    }
+    // All ones if the polynomial has to be applied, zero otherwise
+    let mask = 0u32.wrapping_sub((*crc32 >> 31) ^ bit as u32);
+
+    *crc32 = (*crc32 ^ (CRC32_POLYNOMIAL & mask)) << 1 | (mask & 1);
 }
 
+/// Updates `crc32` with the bits of `byte`, most significant first.
+///
+/// Unlike usual implementations, no lookup table is used: `EmbeddedFile::from_bits_constant_time`
+/// relies on the memory accesses not depending on `byte`.
 pub fn update_with_byte(crc32: &mut u32, byte: u8) {
     for i in (0..8).rev() {
         update_with_bit(crc32, byte & (1 << i) != 0);
@@ -15,11 +15,100 @@
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
 use byteorder::{LittleEndian, ReadBytesExt};
+use encoding_rs::Encoding;
+use libobfuscate::hash::{self, Hash};
+use std::borrow::Cow;
+use std::fmt::{self, Display};
 use std::io::Cursor;
+use std::iter;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 use crate::crc32;
+use crate::Error;
 
-#[derive(Debug)]
+/// Character encoding of the filename of an embedded file.
+///
+/// OpenPuff stores filenames as raw bytes: files hidden on Windows use the system's code page
+/// rather than UTF-8.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FilenameEncoding {
+    #[default]
+    Utf8,
+    /// Central European
+    Windows1250,
+    /// Cyrillic
+    Windows1251,
+    /// Western European
+    Windows1252,
+    /// Japanese
+    ShiftJis,
+    /// Simplified Chinese
+    Gbk,
+    /// Traditional Chinese
+    Big5,
+    /// Korean
+    EucKr,
+}
+
+impl FilenameEncoding {
+    const ALL: [FilenameEncoding; 8] = [
+        Self::Utf8,
+        Self::Windows1250,
+        Self::Windows1251,
+        Self::Windows1252,
+        Self::ShiftJis,
+        Self::Gbk,
+        Self::Big5,
+        Self::EucKr,
+    ];
+
+    /// Returns every supported encoding.
+    pub fn all() -> impl Iterator<Item = FilenameEncoding> {
+        Self::ALL.into_iter()
+    }
+
+    /// Decodes `bytes`, replacing invalid sequences.
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes),
+            _ => self.encoding().decode_without_bom_handling(bytes).0,
+        }
+    }
+
+    fn encoding(&self) -> &'static Encoding {
+        match self {
+            Self::Utf8 => encoding_rs::UTF_8,
+            Self::Windows1250 => encoding_rs::WINDOWS_1250,
+            Self::Windows1251 => encoding_rs::WINDOWS_1251,
+            Self::Windows1252 => encoding_rs::WINDOWS_1252,
+            Self::ShiftJis => encoding_rs::SHIFT_JIS,
+            Self::Gbk => encoding_rs::GBK,
+            Self::Big5 => encoding_rs::BIG5,
+            Self::EucKr => encoding_rs::EUC_KR,
+        }
+    }
+}
+
+impl Display for FilenameEncoding {
+    /// Writes the WHATWG name of the encoding (e.g. `windows-1252`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.encoding().name())
+    }
+}
+
+impl FromStr for FilenameEncoding {
+    type Err = Error;
+
+    /// Parses the name of an encoding, as displayed, ignoring case.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .find(|encoding| encoding.to_string().eq_ignore_ascii_case(name))
+            .ok_or(Error::UnknownFilenameEncoding)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct EmbeddedFile<'a> {
     pub filename: &'a [u8],
     pub content: &'a [u8],
@@ -28,11 +117,35 @@ pub struct EmbeddedFile<'a> {
     pub remaining_bytes: &'a [u8],
 }
 
-const HEADER_SIZE: usize = 10;
+/// Size of the filename length, content size and CRC32 fields preceding the filename.
+pub(crate) const HEADER_SIZE: usize = 10;
+
+/// Size of the integrity digest which may follow an embedded file. See
+/// `EmbeddedFile::verify_integrity`.
+pub const INTEGRITY_DIGEST_SIZE: usize = hash::DIGEST_SIZE;
 
 impl<'a> EmbeddedFile<'a> {
     // TODO: maybe extract this function out of the impl
     pub fn from_bits(bits: &'a [u8]) -> Option<Self> {
+        Self::from_bits_unchecked(bits).filter(EmbeddedFile::verify_crc)
+    }
+
+    /// Like `from_bits`, but a file whose CRC32 doesn't match is still returned, along with
+    /// whether its CRC32 matched.
+    ///
+    /// Not present in OpenPuff. This salvages files from slightly damaged carriers, whose
+    /// content may be corrupted: the header still has to be consistent with the size of `bits`.
+    /// With wrong passwords, a "file" is found whenever the decrypted header happens to declare
+    /// sizes that fit, so a mismatching CRC32 is far more likely to mean garbage than damage.
+    pub fn from_bits_lenient(bits: &'a [u8]) -> Option<(Self, bool)> {
+        Self::from_bits_unchecked(bits).map(|file| {
+            let crc_matches = file.verify_crc();
+            (file, crc_matches)
+        })
+    }
+
+    /// Parses the header and slices `bits` accordingly, without checking the CRC32.
+    fn from_bits_unchecked(bits: &'a [u8]) -> Option<Self> {
         if bits.len() < HEADER_SIZE {
             return None;
         }
@@ -57,11 +170,6 @@ impl<'a> EmbeddedFile<'a> {
         let content_offset = filename_offset + filename_length;
         let content = &bits[content_offset..(content_offset + content_size)];
 
-        let computed_crc32 = crc32::compute(&content);
-        if crc32 != computed_crc32 {
-            return None;
-        }
-
         let remaining_bytes = &bits[(content_offset + content_size)..];
 
         Some(EmbeddedFile {
@@ -72,4 +180,389 @@ impl<'a> EmbeddedFile<'a> {
             remaining_bytes,
         })
     }
+
+    /// Parses the files stored back to back in `bits`, each one from the `remaining_bytes` of the
+    /// previous one, until no file can be parsed.
+    ///
+    /// Not present in OpenPuff, which only hides one file per embedding: bundles of files can only
+    /// be made by other tools.
+    pub fn iter_all(bits: &'a [u8]) -> impl Iterator<Item = EmbeddedFile<'a>> {
+        let mut remaining_bytes = bits;
+
+        iter::from_fn(move || {
+            let file = Self::from_bits(remaining_bytes)?;
+            remaining_bytes = file.remaining_bytes;
+            Some(file)
+        })
+    }
+
+    /// Like `from_bits`, but the time taken doesn't depend on the content of `bits`, only on its
+    /// length.
+    ///
+    /// Not present in OpenPuff. When extracting on behalf of someone else (e.g. as a service), the
+    /// time `from_bits` takes reveals how far a wrong password went: whether the decrypted header
+    /// declared plausible sizes, and how large the content it checksummed was. Here, the CRC32 is
+    /// computed over every byte following the header, keeping only the ones part of the declared
+    /// content, and the header checks are only combined at the end.
+    ///
+    /// This is best effort: it relies on `crc32::update_with_byte` processing bits one at a time
+    /// without branching on them or indexing a table, so that the memory accesses don't depend on
+    /// the data either, and on the compiler not turning the masking back into branches. With
+    /// these, only whether a file was found should remain observable. This doesn't cover the
+    /// decryption, nor what is done with the extracted file.
+    pub fn from_bits_constant_time(bits: &'a [u8]) -> Option<Self> {
+        // The length of `bits` only depends on the carriers, not on the passwords.
+        if bits.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let mut cursor = Cursor::new(bits);
+
+        // Header
+        let filename_length = cursor.read_u16::<LittleEndian>().unwrap() as usize;
+        let content_size = cursor.read_u32::<LittleEndian>().unwrap() as usize;
+        let crc32 = cursor.read_u32::<LittleEndian>().unwrap();
+
+        let content_offset = HEADER_SIZE + filename_length;
+        let content_end = content_offset + content_size;
+        let is_plausible = content_end <= bits.len();
+
+        let mut computed_crc32: u32 = 0xffffffff;
+        for (index, &byte) in bits.iter().enumerate().skip(HEADER_SIZE) {
+            let is_content = (index >= content_offset) & (index < content_end);
+            // All ones if `byte` is part of the content, zero otherwise
+            let mask = 0u32.wrapping_sub(is_content as u32);
+
+            let mut updated_crc32 = computed_crc32;
+            crc32::update_with_byte(&mut updated_crc32, byte);
+            computed_crc32 = (updated_crc32 & mask) | (computed_crc32 & !mask);
+        }
+
+        let is_valid = is_plausible & ((crc32 ^ computed_crc32) == 0);
+        if !is_valid {
+            return None;
+        }
+
+        Some(EmbeddedFile {
+            filename: &bits[HEADER_SIZE..content_offset],
+            content: &bits[content_offset..content_end],
+            crc32,
+
+            remaining_bytes: &bits[content_end..],
+        })
+    }
+
+    /// Recomputes the CRC32 of the content, and compares it to the one stored in the header.
+    ///
+    /// `from_bits` already checks it: this is for content obtained or modified otherwise.
+    pub fn verify_crc(&self) -> bool {
+        crc32::compute(self.content) == self.crc32
+    }
+
+    /// Computes the integrity digest of the file: the SHA-512 digest of its header, filename and
+    /// content, as embedded.
+    ///
+    /// Not present in OpenPuff, whose format only has the CRC32 of the content. See
+    /// `verify_integrity`.
+    pub fn integrity_digest(&self) -> Vec<u8> {
+        let mut hasher = hash::Hasher::new(Hash::Sha512);
+        hasher.update(&(self.filename.len() as u16).to_le_bytes());
+        hasher.update(&(self.content.len() as u32).to_le_bytes());
+        hasher.update(&self.crc32.to_le_bytes());
+        hasher.update(self.filename);
+        hasher.update(self.content);
+        hasher.finalize()
+    }
+
+    /// Checks the integrity digest stored right after the file, in `remaining_bytes`.
+    ///
+    /// This is an extension of OpenPuff's format, where the embedding is padded with random bytes
+    /// after the file: files hidden by OpenPuff fail this check. Unlike the CRC32, the digest
+    /// also covers the header and the filename, and is far less likely to match by chance.
+    ///
+    /// The digest only covers this file: the data and decoy files are checked separately, and
+    /// neither is checked against the other. Nor does the digest cover the carriers or their
+    /// order: carriers given in the wrong order are decrypted as garbage, so no file is found
+    /// rather than a file failing this check.
+    pub fn verify_integrity(&self) -> bool {
+        self.remaining_bytes
+            .get(..INTEGRITY_DIGEST_SIZE)
+            .is_some_and(|digest| digest == self.integrity_digest())
+    }
+
+    /// Decodes the filename using `encoding`, replacing invalid sequences.
+    pub fn filename_str(&self, encoding: FilenameEncoding) -> Cow<'a, str> {
+        encoding.decode(self.filename)
+    }
+
+    /// Returns a filename under which the file can safely be written. See `sanitize_filename`.
+    pub fn sanitized_filename(&self, encoding: FilenameEncoding) -> Option<PathBuf> {
+        sanitize_filename(self.filename, encoding)
+    }
+}
+
+/// Embedded file owning its filename and content, e.g. to outlive the decrypted carriers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFileBuf {
+    pub filename: Vec<u8>,
+    pub content: Vec<u8>,
+    pub crc32: u32,
+}
+
+impl EmbeddedFileBuf {
+    /// Decodes the filename using `encoding`, replacing invalid sequences.
+    pub fn filename_str(&self, encoding: FilenameEncoding) -> Cow<'_, str> {
+        encoding.decode(&self.filename)
+    }
+
+    /// Returns a filename under which the file can safely be written. See `sanitize_filename`.
+    pub fn sanitized_filename(&self, encoding: FilenameEncoding) -> Option<PathBuf> {
+        sanitize_filename(&self.filename, encoding)
+    }
+}
+
+impl From<EmbeddedFile<'_>> for EmbeddedFileBuf {
+    fn from(file: EmbeddedFile<'_>) -> Self {
+        EmbeddedFileBuf {
+            filename: file.filename.to_vec(),
+            content: file.content.to_vec(),
+            crc32: file.crc32,
+        }
+    }
+}
+
+/// Turns the raw `filename` of an embedded file, which is attacker-controlled, into a plain file
+/// name.
+///
+/// Directories (separated by `/` or `\`, including drive letters) are stripped, and control
+/// characters are replaced by `_`. `None` is returned if no usable name remains, e.g. for `..`.
+pub fn sanitize_filename(filename: &[u8], encoding: FilenameEncoding) -> Option<PathBuf> {
+    let filename = encoding.decode(filename);
+
+    let name = filename.rsplit(['/', '\\', ':']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_control() { '_' } else { c })
+        .collect();
+
+    match name.trim() {
+        "" | "." | ".." => None,
+        _ => Some(PathBuf::from(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::embedded_file_bytes;
+
+    fn embedded_file(filename: &[u8]) -> EmbeddedFile<'_> {
+        EmbeddedFile {
+            filename,
+            content: &[],
+            crc32: 0,
+            remaining_bytes: &[],
+        }
+    }
+
+    #[test]
+    fn constant_time_parsing() {
+        let valid = [
+            embedded_file_bytes(b"file.txt", b"content"),
+            b"padding".to_vec(),
+        ]
+        .concat();
+        let file = EmbeddedFile::from_bits_constant_time(&valid).unwrap();
+        assert_eq!(file.filename, b"file.txt");
+        assert_eq!(file.content, b"content");
+        assert_eq!(file.remaining_bytes, b"padding");
+
+        let mut bad_crc = valid.clone();
+        bad_crc[6] ^= 1;
+        let mut bad_content = valid.clone();
+        bad_content[20] ^= 0x80;
+        let mut too_large = valid.clone();
+        too_large[2..6].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut long_filename = valid.clone();
+        long_filename[0..2].copy_from_slice(&u16::MAX.to_le_bytes());
+
+        let mut inputs = vec![
+            valid.clone(),
+            valid[..valid.len() - 7].to_vec(),
+            valid[..valid.len() - 8].to_vec(),
+            valid[..HEADER_SIZE].to_vec(),
+            valid[..HEADER_SIZE - 1].to_vec(),
+            embedded_file_bytes(b"", b""),
+            bad_crc,
+            bad_content,
+            too_large,
+            long_filename,
+            vec![],
+        ];
+        // Garbage, as decrypted with wrong passwords
+        inputs.extend((0..64u32).map(|seed| {
+            (0..64u32)
+                .map(|i| (seed.wrapping_mul(2654435761) ^ i.wrapping_mul(40503) >> 3) as u8)
+                .collect()
+        }));
+
+        for bits in &inputs {
+            assert_eq!(
+                EmbeddedFile::from_bits_constant_time(bits),
+                EmbeddedFile::from_bits(bits),
+                "{:?}",
+                bits
+            );
+        }
+    }
+
+    #[test]
+    fn crc_verification() {
+        let bytes = embedded_file_bytes(b"file.txt", b"content");
+        let file = EmbeddedFile::from_bits(&bytes).unwrap();
+        assert!(file.verify_crc());
+
+        let mut content = file.content.to_vec();
+        content[0] ^= 1;
+        let modified = EmbeddedFile {
+            content: &content,
+            ..file
+        };
+        assert!(!modified.verify_crc());
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        let valid = [
+            embedded_file_bytes(b"file.txt", b"content"),
+            b"padding".to_vec(),
+        ]
+        .concat();
+        assert_eq!(
+            EmbeddedFile::from_bits_lenient(&valid),
+            Some((EmbeddedFile::from_bits(&valid).unwrap(), true))
+        );
+
+        let mut wrong_crc = valid.clone();
+        wrong_crc[6] ^= 1;
+        assert_eq!(EmbeddedFile::from_bits(&wrong_crc), None);
+        let (file, crc_matches) = EmbeddedFile::from_bits_lenient(&wrong_crc).unwrap();
+        assert!(!crc_matches);
+        assert_eq!(file.filename, b"file.txt");
+        assert_eq!(file.content, b"content");
+        assert_eq!(file.remaining_bytes, b"padding");
+
+        // The header must still fit
+        assert_eq!(EmbeddedFile::from_bits_lenient(&wrong_crc[..20]), None);
+    }
+
+    #[test]
+    fn integrity() {
+        let mut bytes = embedded_file_bytes(b"file.txt", b"content");
+        let digest = EmbeddedFile::from_bits(&bytes).unwrap().integrity_digest();
+        assert_eq!(digest.len(), INTEGRITY_DIGEST_SIZE);
+
+        assert!(!EmbeddedFile::from_bits(&bytes).unwrap().verify_integrity());
+        bytes.extend_from_slice(&digest);
+        bytes.extend_from_slice(b"padding");
+        assert!(EmbeddedFile::from_bits(&bytes).unwrap().verify_integrity());
+
+        // The filename isn't covered by the CRC32
+        let mut tampered = bytes.clone();
+        tampered[HEADER_SIZE] ^= 1;
+        let file = EmbeddedFile::from_bits(&tampered).unwrap();
+        assert_eq!(file.filename, b"gile.txt");
+        assert!(!file.verify_integrity());
+
+        let mut tampered = bytes.clone();
+        tampered[HEADER_SIZE + 15] ^= 1;
+        assert!(!EmbeddedFile::from_bits(&tampered)
+            .unwrap()
+            .verify_integrity());
+    }
+
+    #[test]
+    fn chained_files() {
+        let mut bytes = embedded_file_bytes(b"first.txt", b"first content");
+        bytes.extend(embedded_file_bytes(b"second.txt", b"second content"));
+        // Not a file
+        bytes.extend([0xff; 12]);
+
+        let files: Vec<_> = EmbeddedFile::iter_all(&bytes).collect();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].filename, b"first.txt");
+        assert_eq!(files[0].content, b"first content");
+        assert_eq!(files[1].filename, b"second.txt");
+        assert_eq!(files[1].content, b"second content");
+        assert_eq!(files[1].remaining_bytes, [0xff; 12]);
+
+        assert_eq!(EmbeddedFile::iter_all(&bytes[..9]).count(), 0);
+    }
+
+    #[test]
+    fn filename_decoding() {
+        // "Café Noël.txt", as encoded by a French Windows system
+        let file = embedded_file(b"Caf\xe9 No\xebl.txt");
+        assert_eq!(
+            file.filename_str(FilenameEncoding::Windows1252),
+            "Caf\u{e9} No\u{eb}l.txt"
+        );
+        assert_eq!(
+            file.filename_str(FilenameEncoding::default()),
+            "Caf\u{fffd} No\u{fffd}l.txt"
+        );
+
+        let file = embedded_file("Café Noël.txt".as_bytes());
+        assert_eq!(file.filename_str(FilenameEncoding::Utf8), "Café Noël.txt");
+        assert!(matches!(
+            file.filename_str(FilenameEncoding::Utf8),
+            Cow::Borrowed(_)
+        ));
+
+        let file = embedded_file(b"\x93\xfa\x96\x7b.txt");
+        assert_eq!(file.filename_str(FilenameEncoding::ShiftJis), "日本.txt");
+    }
+
+    #[test]
+    fn filename_sanitization() {
+        let sanitized =
+            |filename: &[u8]| embedded_file(filename).sanitized_filename(FilenameEncoding::Utf8);
+
+        assert_eq!(sanitized(b"secret.txt"), Some(PathBuf::from("secret.txt")));
+        assert_eq!(sanitized(b"../../.bashrc"), Some(PathBuf::from(".bashrc")));
+        assert_eq!(sanitized(b"/etc/passwd"), Some(PathBuf::from("passwd")));
+        assert_eq!(
+            sanitized(b"C:\\Windows\\System32\\evil.dll"),
+            Some(PathBuf::from("evil.dll"))
+        );
+        assert_eq!(sanitized(b"C:evil.dll"), Some(PathBuf::from("evil.dll")));
+        assert_eq!(sanitized(b"a\0b\nc.txt"), Some(PathBuf::from("a_b_c.txt")));
+
+        for filename in [&b""[..], b".", b"..", b"dir/..", b"dir/", b"..\\", b" "] {
+            assert_eq!(sanitized(filename), None, "{:?}", filename);
+        }
+    }
+
+    #[test]
+    fn encoding_names() {
+        for encoding in FilenameEncoding::all() {
+            assert_eq!(
+                encoding.to_string().parse::<FilenameEncoding>().unwrap(),
+                encoding
+            );
+        }
+
+        assert_eq!(
+            "WINDOWS-1252".parse::<FilenameEncoding>().unwrap(),
+            FilenameEncoding::Windows1252
+        );
+        assert_eq!(
+            "utf-8".parse::<FilenameEncoding>().unwrap(),
+            FilenameEncoding::Utf8
+        );
+        assert!(matches!(
+            "latin-9".parse::<FilenameEncoding>(),
+            Err(Error::UnknownFilenameEncoding)
+        ));
+    }
 }
@@ -0,0 +1,409 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+#[cfg(feature = "fs")]
+use log::warn;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+use crate::carrier::EncryptedCarrier;
+#[cfg(feature = "fs")]
+use crate::carrier::{self, CarrierOptions};
+use crate::chain::{self, CarrierEmbeddings};
+use crate::embedded_file::{EmbeddedFile, EmbeddedFileBuf};
+use crate::passwords::Passwords;
+use crate::Error;
+
+/// Files found in a chain of carriers.
+///
+/// A file is missing when the passwords are wrong, or when no file was hidden in its embedding.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractionResult {
+    pub data: Option<EmbeddedFileBuf>,
+    pub decoy: Option<EmbeddedFileBuf>,
+    /// Concatenated data and decoy embeddings, whether they hold a file or not. Only kept when
+    /// extracting without validation (see `ExtractionOptions::validate`).
+    pub unvalidated: Option<CarrierEmbeddings>,
+}
+
+/// Options of `extract_from_carriers_with`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractionOptions {
+    /// Whether only valid embedded files are of interest, which is the default.
+    ///
+    /// When unset, the decrypted embeddings are also returned as is, for forensic analysis: with
+    /// a wrong password, they hold the bytes it produces instead of a file with a valid header.
+    pub validate: bool,
+    /// Whether embedded files are parsed with `EmbeddedFile::from_bits_constant_time`, so that the
+    /// time taken doesn't tell how far a wrong password went. Unset by default.
+    ///
+    /// Meant for extracting on behalf of someone else, e.g. as a service. The time taken to
+    /// decrypt the carriers isn't covered.
+    pub constant_time: bool,
+}
+
+impl Default for ExtractionOptions {
+    fn default() -> Self {
+        ExtractionOptions {
+            validate: true,
+            constant_time: false,
+        }
+    }
+}
+
+impl ExtractionResult {
+    /// Returns whether neither a data file nor a decoy file was found.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_none() && self.decoy.is_none()
+    }
+
+    /// Checks that no file found is larger than `max_output_size` bytes, before outputting them.
+    ///
+    /// The size of an embedded file is bounded by the capacity of the carriers, which can be much
+    /// larger than what a service extracting untrusted carriers is willing to handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FileTooLarge` for the first file exceeding the limit.
+    pub fn check_size(&self, max_output_size: usize) -> Result<(), Error> {
+        for file in self.data.iter().chain(&self.decoy) {
+            check_output_size(file.content.len(), max_output_size)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that an output of `size` bytes isn't larger than `max_output_size` bytes. See
+/// `ExtractionResult::check_size`.
+///
+/// # Errors
+///
+/// Returns `Error::FileTooLarge` if it is.
+pub fn check_output_size(size: usize, max_output_size: usize) -> Result<(), Error> {
+    if size > max_output_size {
+        return Err(Error::FileTooLarge {
+            size,
+            limit: max_output_size,
+        });
+    }
+
+    Ok(())
+}
+
+/// Extracts the data and decoy files hidden in the carriers at `carriers`, in hiding order.
+///
+/// Like OpenPuff, carriers too small to hold anything (see `Error::is_carrier_too_small`) are
+/// left out of the chain. Not finding any file isn't an error: see `ExtractionResult`.
+#[cfg(feature = "fs")]
+pub fn extract(
+    carriers: &[PathBuf],
+    passwords: Passwords,
+    options: &CarrierOptions,
+) -> Result<ExtractionResult, Error> {
+    let mut parsed_carriers = Vec::with_capacity(carriers.len());
+    for path in carriers {
+        if let Some(carrier) = leave_out_too_small(path, carrier::from_file_with(path, options))? {
+            parsed_carriers.push(carrier);
+        }
+    }
+
+    Ok(extract_from_carriers(parsed_carriers, passwords))
+}
+
+/// Returns `None` instead of the error for a carrier too small to hold anything, which is left
+/// out of the chain.
+#[cfg(feature = "fs")]
+fn leave_out_too_small(
+    path: &Path,
+    carrier: Result<EncryptedCarrier, Error>,
+) -> Result<Option<EncryptedCarrier>, Error> {
+    match carrier {
+        Ok(carrier) => Ok(Some(carrier)),
+        Err(err) if err.is_carrier_too_small() => {
+            warn!("{}: {err}, leaving it out of the chain", path.display());
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Like `extract`, reading the carriers with tokio and decrypting them on a thread where blocking
+/// is acceptable (see `tokio::task::spawn_blocking`).
+#[cfg(feature = "async")]
+pub async fn extract_async(
+    carriers: &[PathBuf],
+    passwords: Passwords<'_>,
+    options: &CarrierOptions,
+) -> Result<ExtractionResult, Error> {
+    let mut parsed_carriers = Vec::with_capacity(carriers.len());
+    for path in carriers {
+        let file_type = carrier::file_type_from_path(path)?;
+        let file = tokio::fs::File::open(path).await?;
+
+        let carrier = carrier::from_async_reader(file, path, file_type, options).await;
+        if let Some(carrier) = leave_out_too_small(path, carrier)? {
+            parsed_carriers.push(carrier);
+        }
+    }
+
+    let (a, b, c) = (
+        passwords.a.to_owned(),
+        passwords.b.to_owned(),
+        passwords.c.to_owned(),
+    );
+    let extraction = tokio::task::spawn_blocking(move || {
+        extract_from_carriers(
+            parsed_carriers,
+            Passwords {
+                a: &a,
+                b: &b,
+                c: &c,
+            },
+        )
+    });
+
+    Ok(extraction
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic())))
+}
+
+/// Like `extract`, with carriers already parsed.
+pub fn extract_from_carriers(
+    carriers: Vec<EncryptedCarrier>,
+    passwords: Passwords,
+) -> ExtractionResult {
+    extract_from_carriers_with(carriers, passwords, &ExtractionOptions::default())
+}
+
+/// Like `extract_from_carriers`, with extraction options.
+pub fn extract_from_carriers_with(
+    carriers: Vec<EncryptedCarrier>,
+    passwords: Passwords,
+    options: &ExtractionOptions,
+) -> ExtractionResult {
+    let embeddings = chain::decrypt_carrier_chain(carriers, passwords);
+    let (data, decoy) = chain::concatenate_embeddings(&embeddings);
+
+    let parse = if options.constant_time {
+        EmbeddedFile::from_bits_constant_time
+    } else {
+        EmbeddedFile::from_bits
+    };
+
+    ExtractionResult {
+        data: parse(&data).map(EmbeddedFileBuf::from),
+        decoy: parse(&decoy).map(EmbeddedFileBuf::from),
+        unvalidated: (!options.validate).then_some(CarrierEmbeddings { data, decoy }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "fs")]
+    use crate::bit_selection::BitSelection;
+    use crate::test_utils;
+
+    /// Chain of two carriers hiding `secret.txt` as data file, across both carriers, and
+    /// `decoy.txt` as decoy file, with the passwords of `passwords`.
+    fn hiding_carriers() -> Vec<EncryptedCarrier> {
+        let mut data = test_utils::embedded_file_bytes(b"secret.txt", &secret_content());
+        let mut decoy = test_utils::embedded_file_bytes(b"decoy.txt", b"nothing to see here");
+        data.resize(1024, 0x5a);
+        decoy.resize(1024, 0xa5);
+
+        let embeddings = vec![
+            CarrierEmbeddings {
+                data: data[..512].to_vec(),
+                decoy: decoy[..512].to_vec(),
+            },
+            CarrierEmbeddings {
+                data: data[512..].to_vec(),
+                decoy: decoy[512..].to_vec(),
+            },
+        ];
+        let decrypted_ivs = [[0x17; 256], [0x2c; 256]];
+
+        chain::encrypt_carrier_chain(embeddings, &decrypted_ivs, &passwords())
+    }
+
+    fn secret_content() -> Vec<u8> {
+        (0..700u16).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn passwords() -> Passwords<'static> {
+        Passwords::from_fields(
+            "firstpassword",
+            Some("secondpassword"),
+            Some("thirdpassword"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn output_size_limit() {
+        let bits = test_utils::embedded_file_bytes(b"file", &[0x42; 1000]);
+
+        let result = ExtractionResult {
+            data: None,
+            decoy: EmbeddedFile::from_bits(&bits).map(EmbeddedFileBuf::from),
+            unvalidated: None,
+        };
+        assert!(result.check_size(1000).is_ok());
+        assert!(matches!(
+            result.check_size(999),
+            Err(Error::FileTooLarge {
+                size: 1000,
+                limit: 999
+            })
+        ));
+        assert!(ExtractionResult::default().check_size(0).is_ok());
+    }
+
+    #[test]
+    fn extraction() {
+        let result = extract_from_carriers(hiding_carriers(), passwords());
+
+        let data = result.data.unwrap();
+        assert_eq!(data.filename, b"secret.txt");
+        assert_eq!(data.content, secret_content());
+        let decoy = result.decoy.unwrap();
+        assert_eq!(decoy.filename, b"decoy.txt");
+        assert_eq!(decoy.content, b"nothing to see here");
+
+        // Out of order, the carriers are decrypted with the wrong keys
+        let mut carriers = hiding_carriers();
+        carriers.swap(0, 1);
+        assert!(extract_from_carriers(carriers, passwords()).is_empty());
+
+        let wrong_passwords = Passwords::from_fields(
+            "firstpassword",
+            Some("wrongpassword"),
+            Some("thirdpassword"),
+        )
+        .unwrap();
+        assert!(extract_from_carriers(hiding_carriers(), wrong_passwords).is_empty());
+
+        assert!(ExtractionResult::default().is_empty());
+    }
+
+    #[test]
+    fn constant_time_extraction() {
+        let options = ExtractionOptions {
+            constant_time: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            extract_from_carriers_with(hiding_carriers(), passwords(), &options),
+            extract_from_carriers(hiding_carriers(), passwords())
+        );
+
+        let wrong_passwords = Passwords::from_fields(
+            "wrongpassword",
+            Some("secondpassword"),
+            Some("thirdpassword"),
+        )
+        .unwrap();
+        assert!(
+            extract_from_carriers_with(hiding_carriers(), wrong_passwords, &options).is_empty()
+        );
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn extraction_from_files() {
+        let directory = test_utils::TemporaryDirectory::new("extraction_from_files");
+        let paths: Vec<PathBuf> = (0..2u16)
+            .map(|i| {
+                let samples: Vec<u16> = (0..13000).map(|j| 0b1000 | ((j + i) % 5)).collect();
+                directory.file(
+                    &format!("extraction_{i}.wav"),
+                    &test_utils::wav_file(&samples),
+                )
+            })
+            .collect();
+        let passwords = passwords();
+        let options = CarrierOptions::new().selection(BitSelection::High);
+
+        // Nothing was hidden in these carriers
+        let result = extract(&paths, passwords.clone(), &options).unwrap();
+        assert!(result.is_empty());
+
+        // A carrier too small to hold anything, left out of the chain
+        let silent_path = directory.file("silent.wav", &test_utils::wav_file(&[0; 13000]));
+        let tiny_path = directory.file("tiny.wav", &test_utils::wav_file(&[0b1000; 100]));
+        let wavs = test_utils::hiding_wav_files(
+            &test_utils::embedded_file_bytes(b"secret.txt", &secret_content()),
+            &test_utils::embedded_file_bytes(b"decoy.txt", b"nothing to see here"),
+            2,
+            BitSelection::High,
+            &passwords,
+        );
+        let hiding_paths = [
+            directory.file("hiding_0.wav", &wavs[0]),
+            tiny_path,
+            directory.file("hiding_1.wav", &wavs[1]),
+            silent_path,
+        ];
+        let result = extract(&hiding_paths, passwords.clone(), &options).unwrap();
+        let data = result.data.unwrap();
+        assert_eq!(data.filename, b"secret.txt");
+        assert_eq!(data.content, secret_content());
+        assert_eq!(result.decoy.unwrap().content, b"nothing to see here");
+
+        let missing = [paths[0].clone(), PathBuf::from("./does/not/exist.wav")];
+        assert!(matches!(
+            extract(&missing, passwords, &options),
+            Err(Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn unvalidated_extraction() {
+        let carriers = || {
+            (0..2u8).map(|i| EncryptedCarrier {
+                iv: [i; 256],
+                data: vec![i; 512],
+                decoy: vec![i.wrapping_add(1); 512],
+                other_bits: bit_vec::BitVec::new(),
+                warnings: Vec::new(),
+            })
+        };
+        let passwords = Passwords::from_fields("wrongpassword", None, None).unwrap();
+
+        // No file can be found with a wrong password, but its bytes are still returned
+        let validated = extract_from_carriers(carriers().collect(), passwords.clone());
+        assert!(validated.is_empty());
+        assert_eq!(validated.unvalidated, None);
+
+        let options = ExtractionOptions {
+            validate: false,
+            ..Default::default()
+        };
+        let unvalidated =
+            extract_from_carriers_with(carriers().collect(), passwords.clone(), &options);
+        assert!(unvalidated.is_empty());
+
+        let embeddings = chain::decrypt_carrier_chain(carriers(), passwords);
+        let (data, decoy) = chain::concatenate_embeddings(&embeddings);
+        assert_eq!(
+            unvalidated.unvalidated,
+            Some(CarrierEmbeddings { data, decoy })
+        );
+    }
+}
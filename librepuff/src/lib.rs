@@ -19,32 +19,91 @@
 use std::error;
 use std::fmt::{self, Display};
 use std::io;
+use std::path::PathBuf;
 
 pub mod bit_selection;
+pub mod bitutil;
 pub mod carrier;
+pub mod carrier_parser;
 pub mod carrier_type;
 pub mod chain;
 pub mod crc32;
 pub mod embedded_file;
+pub mod extraction;
+#[cfg(feature = "fuzzing")]
+pub mod parser;
+#[cfg(not(feature = "fuzzing"))]
 mod parser;
 pub mod passwords;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod wav_options;
 
-use parser::ParsingError;
+use carrier_type::CarrierType;
+#[cfg(feature = "fs")]
+pub use extraction::extract;
+pub use extraction::{ExtractionOptions, ExtractionResult};
+pub use parser::{ParserWarning, ParsingError};
 
 #[derive(Debug)]
 pub enum Error {
     IoError(io::Error),
     UnknownFiletype,
+    UnknownBitSelection,
+    UnknownFilenameEncoding,
+    UnsupportedCarrier(CarrierType),
+    InvalidCarrierFormat,
+    /// The carrier at this path has data past the end of its file format.
+    TrailingData(PathBuf),
     CarrierTooSmall,
+    NoUsableBits,
+    NoSelectableBits,
+    TooManyBits,
+    FileTooLarge {
+        size: usize,
+        limit: usize,
+    },
     PasswordTooLong,
 }
+impl Error {
+    /// Returns whether the error is about a carrier too small to hold anything: `NoUsableBits`,
+    /// `CarrierTooSmall` or `NoSelectableBits`. OpenPuff leaves such carriers out of the chain.
+    pub fn is_carrier_too_small(&self) -> bool {
+        matches!(
+            self,
+            Self::NoUsableBits | Self::CarrierTooSmall | Self::NoSelectableBits
+        )
+    }
+}
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::IoError(err) => write!(f, "I/O error: {err}"),
             Self::UnknownFiletype => write!(f, "unknown file type"),
+            Self::UnknownBitSelection => write!(f, "unknown bit selection level"),
+            Self::UnknownFilenameEncoding => write!(f, "unknown filename encoding"),
+            Self::UnsupportedCarrier(file_type) => {
+                write!(f, "{file_type} carriers are not yet supported")
+            }
+            Self::InvalidCarrierFormat => write!(f, "invalid carrier format"),
+            Self::TrailingData(path) => write!(f, "{} has trailing data", path.display()),
             Self::CarrierTooSmall => write!(f, "carrier too small"),
-            Self::PasswordTooLong => write!(f, "password is longer than 32 characters"),
+            Self::NoUsableBits => write!(
+                f,
+                "no bits of the carrier can hold data (is it silent or blank?)"
+            ),
+            Self::NoSelectableBits => {
+                write!(f, "carrier too small to hide data at this bit selection level")
+            }
+            Self::TooManyBits => write!(
+                f,
+                "the total number of selected bits of the carriers overflows 32 bits"
+            ),
+            Self::FileTooLarge { size, limit } => write!(
+                f,
+                "extracted file of {size} bytes is larger than the limit of {limit} bytes"
+            ),
+            Self::PasswordTooLong => write!(f, "password is too long"),
         }
     }
 }
@@ -56,9 +115,36 @@ impl From<io::Error> for Error {
 impl From<ParsingError> for Error {
     fn from(error: ParsingError) -> Error {
         match error {
-            ParsingError::InvalidFormat => Self::UnknownFiletype,
+            ParsingError::InvalidFormat => Self::InvalidCarrierFormat,
             ParsingError::IoError(error) => Self::IoError(error),
         }
     }
 }
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::IoError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use std::path::Path;
+
+    #[test]
+    fn error_source() {
+        let err = carrier::from_file(Path::new("./does/not/exist.wav"), Default::default())
+            .unwrap_err();
+        let source = err.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<io::Error>().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        assert!(Error::CarrierTooSmall.source().is_none());
+    }
+}
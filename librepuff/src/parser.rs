@@ -14,13 +14,31 @@
 // You should have received a copy of the GNU General Public License
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
-use std::io::{self, ErrorKind};
+use std::error;
+use std::fmt::{self, Display};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
 
 #[derive(Debug)]
 pub enum ParsingError {
     InvalidFormat,
     IoError(io::Error),
 }
+impl Display for ParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "invalid format"),
+            Self::IoError(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+impl error::Error for ParsingError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::IoError(err) => Some(err),
+            Self::InvalidFormat => None,
+        }
+    }
+}
 impl From<io::Error> for ParsingError {
     fn from(error: io::Error) -> Self {
         match error.kind() {
@@ -32,11 +50,116 @@ impl From<io::Error> for ParsingError {
     }
 }
 
+/// Oddity found while parsing a carrier, which doesn't prevent it from being used.
+///
+/// None of these are reported by OpenPuff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParserWarning {
+    /// The BlockAlign and BitsPerSample fields of a WAVE file don't agree.
+    BlockAlignMismatch,
+    /// The 'fmt ' subchunk of a WAVE file is larger than its fields.
+    FmtTrailingData,
+}
+impl Display for ParserWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockAlignMismatch => write!(
+                f,
+                "there is a discrepancy between the BlockAlign and BitsPerSample fields in the 'fmt ' header"
+            ),
+            Self::FmtTrailingData => write!(f, "'fmt ' header contains trailing data"),
+        }
+    }
+}
+
+/// Reader able to skip bytes.
+pub trait Skip: Read {
+    /// Skips the next `count` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParsingError::InvalidFormat` if fewer than `count` bytes are left.
+    fn skip(&mut self, count: u64) -> Result<(), ParsingError>;
+}
+
+/// Reader skipping bytes by reading and discarding them, for readers that can't seek.
+pub struct SkipByReading<R>(pub R);
+
+impl<R: Read> Read for SkipByReading<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<R: Read> Skip for SkipByReading<R> {
+    fn skip(&mut self, count: u64) -> Result<(), ParsingError> {
+        let skipped = io::copy(&mut (&mut self.0).take(count), &mut io::sink())?;
+        if skipped < count {
+            return Err(ParsingError::InvalidFormat);
+        }
+
+        Ok(())
+    }
+}
+
+/// Reader skipping bytes by seeking past them.
+pub struct SkipBySeeking<R> {
+    reader: R,
+    /// Length of the stream, queried on the first skip.
+    length: Option<u64>,
+}
+
+impl<R: Read + Seek> SkipBySeeking<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            length: None,
+        }
+    }
+
+    fn length(&mut self) -> io::Result<u64> {
+        if let Some(length) = self.length {
+            return Ok(length);
+        }
+
+        let position = self.reader.stream_position()?;
+        let length = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(position))?;
+
+        self.length = Some(length);
+        Ok(length)
+    }
+}
+
+impl<R: Read + Seek> Read for SkipBySeeking<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: Read + Seek> Skip for SkipBySeeking<R> {
+    fn skip(&mut self, count: u64) -> Result<(), ParsingError> {
+        // Seeking past the end of a stream succeeds: like when reading, skipping past the end of
+        // the stream must fail.
+        let length = self.length()?;
+        let offset = i64::try_from(count).map_err(|_| ParsingError::InvalidFormat)?;
+        let position = self.reader.seek(SeekFrom::Current(offset))?;
+        if position > length {
+            return Err(ParsingError::InvalidFormat);
+        }
+
+        Ok(())
+    }
+}
+
 /// Parsing modules for the different file types.
 ///
-/// Each module exports a `parse(mut reader: &mut impl Read)` function,
-/// which returns a `Result<BitVec, ParsingError>`.
+/// Each module exports a `parse(mut reader: &mut impl Read, ...)` function, possibly taking
+/// format-specific options, which returns a `Result<(BitVec, Vec<ParserWarning>), ParsingError>`.
+/// Parsers skipping parts of files also export a `parse_seekable` function, taking an
+/// `&mut (impl Read + Seek)` reader to seek past them instead of reading them.
 /// Each parser must strictly only read bytes part of the file format.
 /// This allows users of this module to tell if a file has trailing data, for instance.
+///
+/// They are made available to `carrier` through `carrier_parser::ParserRegistry`.
 pub mod wav;
-
@@ -16,11 +16,20 @@
 
 use bit_vec::BitVec;
 use byteorder::{LittleEndian, ReadBytesExt};
-use log::{debug, warn};
+use log::debug;
 use std::cmp;
-use std::io::Read;
+use std::io::{Read, Seek};
 
-use super::ParsingError;
+use super::{ParserWarning, ParsingError, Skip, SkipByReading, SkipBySeeking};
+use crate::wav_options::{SampleSelection, WavOptions};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// GUID of the PCM SubFormat of WAVE_FORMAT_EXTENSIBLE files, as stored in the file.
+const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
 
 #[derive(Default)]
 struct Metadata {
@@ -30,28 +39,54 @@ struct Metadata {
     byte_rate: u32,
     block_align: u16,
     bits_per_sample: u16,
+    /// Size of the samples, as computed by OpenPuff.
+    sample_bytes: u32,
 }
 
 /// Determine whether a sample should be chosen to contain a bit in its least significant position.
-fn should_choose_sample(sample: u16, first_relevant_bit: usize) -> bool {
-    // Don't count the sign bit
-    let sample = sample & !0b10000000_00000000;
-    let ones = (sample >> (first_relevant_bit - 1)).count_ones();
+///
+/// `sample` is a signed sample of `bits_per_sample` bits. OpenPuff only uses 16-bit samples. See
+/// `SampleSelection`.
+fn should_choose_sample(sample: u32, bits_per_sample: u32, selection: &SampleSelection) -> bool {
+    let sample = if selection.ignore_sign_bit {
+        sample & !(1 << (bits_per_sample - 1))
+    } else {
+        sample
+    };
+    // Counting the least significant bit would make the choice depend on the bit it holds.
+    if selection.first_relevant_bit < 2 {
+        return false;
+    }
+    let Some(max_ones) = bits_per_sample.checked_sub(2 + selection.first_relevant_bit) else {
+        return false;
+    };
+    let ones = (sample >> (selection.first_relevant_bit - 1)).count_ones();
 
-    ones > 0 && ones <= (14 - first_relevant_bit) as u32
+    ones > 0 && ones <= max_ones
 }
 
-/// Extract bits from WAVE PCM data
-fn extract_bits_from_data(
+/// Extract bits from WAVE PCM data, made of samples of `SAMPLE_BYTES` bytes.
+fn extract_bits_from_samples<const SAMPLE_BYTES: usize>(
     reader: &mut impl Read,
     samples_count: u32,
+    selection: &SampleSelection,
 ) -> Result<BitVec, ParsingError> {
     let mut bit_storage = BitVec::new();
 
     for _ in 0..samples_count {
-        let sample = reader.read_u16::<LittleEndian>()?;
+        let mut bytes = [0u8; SAMPLE_BYTES];
+        reader.read_exact(&mut bytes)?;
+
+        let mut sample = bytes
+            .iter()
+            .rev()
+            .fold(0u32, |sample, &byte| sample << 8 | u32::from(byte));
+        // 8-bit samples are unsigned
+        if SAMPLE_BYTES == 1 {
+            sample ^= 0x80;
+        }
 
-        if should_choose_sample(sample, 4) {
+        if should_choose_sample(sample, 8 * SAMPLE_BYTES as u32, selection) {
             bit_storage.push(sample & 1 == 1);
         }
     }
@@ -59,8 +94,67 @@ fn extract_bits_from_data(
     Ok(bit_storage)
 }
 
-pub fn parse(mut reader: &mut impl Read) -> Result<BitVec, ParsingError> {
-    let mut bit_storage = None;
+/// Extract bits from WAVE PCM data
+fn extract_bits_from_data(
+    reader: &mut impl Read,
+    samples_count: u32,
+    metadata: &Metadata,
+    selection: &SampleSelection,
+) -> Result<BitVec, ParsingError> {
+    match metadata.sample_bytes {
+        1 => extract_bits_from_samples::<1>(reader, samples_count, selection),
+        2 => extract_bits_from_samples::<2>(reader, samples_count, selection),
+        3 => extract_bits_from_samples::<3>(reader, samples_count, selection),
+        _ => unreachable!("unsupported sample sizes are rejected with the 'fmt ' subchunk"),
+    }
+}
+
+/// Whether `size` is a placeholder left in a size field by a tool streaming the file.
+fn is_placeholder(size: u32) -> bool {
+    size == 0 || size == u32::MAX
+}
+
+/// Returns the number of samples of a 'data' subchunk of `subchunk_size` bytes.
+fn count_samples(subchunk_size: u32, metadata: &Metadata) -> Result<u32, ParsingError> {
+    let num_samples_per_channel = subchunk_size / (metadata.block_align as u32);
+    let num_samples = num_samples_per_channel * (metadata.num_channels as u32);
+    if num_samples == 0 {
+        debug!("expected the WAVE file to contain at least one sample");
+        return Err(ParsingError::InvalidFormat);
+    }
+
+    Ok(num_samples)
+}
+
+/// Appends the bits of a 'data' subchunk to those of the previous ones.
+fn append_bits(bit_storage: &mut Option<BitVec>, mut new_bits: BitVec) {
+    match bit_storage {
+        None => *bit_storage = Some(new_bits),
+        Some(bit_storage) => bit_storage.append(&mut new_bits),
+    }
+}
+
+pub fn parse(
+    reader: &mut impl Read,
+    options: &WavOptions,
+) -> Result<(BitVec, Vec<ParserWarning>), ParsingError> {
+    parse_with_skip(&mut SkipByReading(reader), options)
+}
+
+/// Like `parse`, seeking past the unsupported subchunks instead of reading them.
+pub fn parse_seekable(
+    reader: &mut (impl Read + Seek),
+    options: &WavOptions,
+) -> Result<(BitVec, Vec<ParserWarning>), ParsingError> {
+    parse_with_skip(&mut SkipBySeeking::new(reader), options)
+}
+
+fn parse_with_skip(
+    mut reader: &mut impl Skip,
+    options: &WavOptions,
+) -> Result<(BitVec, Vec<ParserWarning>), ParsingError> {
+    let mut bit_storage: Option<BitVec> = None;
+    let mut warnings = Vec::new();
 
     // Can info->file_offset be anything other than 0 here?
     // TODO: SetFilePointer(hFile,info->file_offset,(PLONG)0x0,FILE_BEGIN);
@@ -80,11 +174,14 @@ pub fn parse(mut reader: &mut impl Read) -> Result<BitVec, ParsingError> {
     // The size of the entire WAVE file minus 8 bytes for the two fields not included in this
     // count: ChunkID and ChunkSize.
     let chunk_size = reader.read_u32::<LittleEndian>()?;
-    if chunk_size & 0x80000000 != 0 {
+    // Not supported by OpenPuff: placeholder sizes, when enabled
+    let chunk_size_is_placeholder = options.placeholder_size_fallback && is_placeholder(chunk_size);
+    if chunk_size_is_placeholder {
+        debug!("ChunkSize is a placeholder, reading up to the 'data' subchunk");
+    } else if chunk_size & 0x80000000 != 0 {
         debug!("expected the 32th bit of ChunkSize to be zero, for compatibility with OpenPuff");
         return Err(ParsingError::InvalidFormat);
-    }
-    if chunk_size < 4 {
+    } else if chunk_size < 4 {
         debug!("expected ChunkSize to be at least 4");
         return Err(ParsingError::InvalidFormat);
     }
@@ -96,8 +193,13 @@ pub fn parse(mut reader: &mut impl Read) -> Result<BitVec, ParsingError> {
         return Err(ParsingError::InvalidFormat);
     }
 
-    let data_size = chunk_size - 4;
-    let mut data_read = 0;
+    // Sizes are accumulated as u64, so that hostile subchunk sizes can't overflow them.
+    let data_size = if chunk_size_is_placeholder {
+        u64::MAX
+    } else {
+        u64::from(chunk_size - 4)
+    };
+    let mut data_read: u64 = 0;
 
     // RIFF subchunks: 'fmt ' and 'data'
     let mut processed_fmt_subchunk = false;
@@ -121,6 +223,10 @@ pub fn parse(mut reader: &mut impl Read) -> Result<BitVec, ParsingError> {
                 debug!("expected the 32th bit of the 'fmt ' SubchunkSize to be zero, for compatibility with OpenPuff");
                 return Err(ParsingError::InvalidFormat);
             }
+            if subchunk_size < 16 {
+                debug!("expected the 'fmt ' SubchunkSize to be at least 16");
+                return Err(ParsingError::InvalidFormat);
+            }
 
             // Read the header fields
             // BUG: OpenPuff reads `subchunk_size` bytes to a heap-array of 0x400000 bytes, resulting in a
@@ -132,36 +238,66 @@ pub fn parse(mut reader: &mut impl Read) -> Result<BitVec, ParsingError> {
             metadata.byte_rate = reader.read_u32::<LittleEndian>()?;
             metadata.block_align = reader.read_u16::<LittleEndian>()?;
             metadata.bits_per_sample = reader.read_u16::<LittleEndian>()?;
+            let mut fmt_size_read = 16;
+
+            // Not supported by OpenPuff: WAVE_FORMAT_EXTENSIBLE files whose samples are PCM
+            let mut is_pcm = metadata.audio_format == WAVE_FORMAT_PCM;
+            let mut valid_bits_per_sample = None;
+            if metadata.audio_format == WAVE_FORMAT_EXTENSIBLE {
+                if subchunk_size < 16 + 24 {
+                    debug!("'fmt ' header too small to contain a WAVE_FORMAT_EXTENSIBLE extension");
+                    return Err(ParsingError::InvalidFormat);
+                }
+
+                let extension_size = reader.read_u16::<LittleEndian>()?;
+                valid_bits_per_sample = Some(reader.read_u16::<LittleEndian>()?);
+                let _channel_mask = reader.read_u32::<LittleEndian>()?;
+                let mut sub_format = [0u8; 16];
+                reader.read_exact(&mut sub_format)?;
+                fmt_size_read += 24;
+
+                is_pcm = extension_size >= 22 && sub_format == KSDATAFORMAT_SUBTYPE_PCM;
+            }
+
+            if metadata.num_channels == 0 {
+                debug!("expected the WAVE file to have at least one channel");
+                return Err(ParsingError::InvalidFormat);
+            }
 
             // OpenPuff computes the number of bits per sample by using that a "normal" WAVE will
             // have BlockAlign = NumChannels * BitsPerSample/8
-            let computed_bits_per_sample = metadata.block_align / metadata.num_channels * 8;
+            let computed_bits_per_sample =
+                u32::from(metadata.block_align) / u32::from(metadata.num_channels) * 8;
 
             // Oddities detection - not present in OpenPuff
-            if computed_bits_per_sample != metadata.bits_per_sample {
-                warn!("there is a discrepancy between the BlockAlign and BitsPerSample fields in the 'fmt ' header");
+            if computed_bits_per_sample != u32::from(metadata.bits_per_sample) {
+                warnings.push(ParserWarning::BlockAlignMismatch);
             }
-            if subchunk_size != 16 {
-                warn!("'fmt ' header contains trailing data");
+            if subchunk_size != fmt_size_read {
+                warnings.push(ParserWarning::FmtTrailingData);
             }
 
             // OpenPuff only accepts WAVE file having this specific format
-            if metadata.audio_format != 1
-                || metadata.num_channels == 0
-                || computed_bits_per_sample != 16
-            {
-                debug!("for compatibility with OpenPuff, only PCM WAVE files with 16 bits per sample and at least one channel are accepted");
+            // Not supported by OpenPuff: 8-bit and 24-bit samples, in extended mode
+            let supported_sample_size = computed_bits_per_sample == 16
+                || (options.extended_sample_sizes && matches!(computed_bits_per_sample, 8 | 24));
+            let all_bits_valid = valid_bits_per_sample
+                .is_none_or(|valid_bits| u32::from(valid_bits) == computed_bits_per_sample);
+            if !is_pcm || !all_bits_valid || !supported_sample_size {
+                debug!("for compatibility with OpenPuff, only PCM WAVE files with 16 bits per sample (or 8 and 24 with extended sample sizes) and at least one channel are accepted");
                 return Err(ParsingError::InvalidFormat);
             }
+            metadata.sample_bytes = computed_bits_per_sample / 8;
 
-            data_read += 4 + 16;
-            for _ in data_read..cmp::min(data_read + subchunk_size - 16, data_size) {
-                reader.read_u8()?;
-            }
-            data_read += subchunk_size - 16;
+            // `subchunk_size` is at least `fmt_size_read`, which was checked before reading.
+            let skipped_size = u64::from(subchunk_size - fmt_size_read);
+            data_read += 4 + u64::from(fmt_size_read);
+            reader.skip(cmp::min(skipped_size, data_size.saturating_sub(data_read)))?;
+            data_read += skipped_size;
         } else if subchunk_id.eq_ignore_ascii_case(b"data") {
             // It can only be read once, after having read the format subchunk.
-            if processed_data_subchunk || !processed_fmt_subchunk {
+            // Not supported by OpenPuff: multiple 'data' subchunks, in non-strict mode
+            if (options.strict && processed_data_subchunk) || !processed_fmt_subchunk {
                 if processed_data_subchunk {
                     debug!("file cannot have multiple 'data' header");
                 } else {
@@ -173,22 +309,46 @@ pub fn parse(mut reader: &mut impl Read) -> Result<BitVec, ParsingError> {
 
             let subchunk_size = reader.read_u32::<LittleEndian>()?;
             data_read += 4;
+
+            if options.placeholder_size_fallback && is_placeholder(subchunk_size) {
+                debug!("'data' SubchunkSize is a placeholder, reading up to the end of the file");
+
+                let mut samples = Vec::new();
+                reader.read_to_end(&mut samples)?;
+                let subchunk_size =
+                    u32::try_from(samples.len()).map_err(|_| ParsingError::InvalidFormat)?;
+
+                let num_samples = count_samples(subchunk_size, &metadata)?;
+                let maybe_bit_storage = extract_bits_from_data(
+                    &mut &samples[..],
+                    num_samples,
+                    &metadata,
+                    &options.sample_selection,
+                )?;
+                append_bits(&mut bit_storage, maybe_bit_storage);
+
+                break;
+            }
+
             if subchunk_size == 0 {
                 debug!("expected the data SubchunkSize to be non-zero");
                 return Err(ParsingError::InvalidFormat);
             }
 
-            let num_samples_per_channel = subchunk_size / (metadata.block_align as u32);
-            let num_samples = num_samples_per_channel * (metadata.num_channels as u32);
-            if num_samples == 0 {
-                debug!("expected the WAVE file to contain at least one sample");
-                return Err(ParsingError::InvalidFormat);
-            }
+            let num_samples = count_samples(subchunk_size, &metadata)?;
+            let maybe_bit_storage = extract_bits_from_data(
+                &mut reader,
+                num_samples,
+                &metadata,
+                &options.sample_selection,
+            )?;
+            append_bits(&mut bit_storage, maybe_bit_storage);
 
-            let maybe_bit_storage = extract_bits_from_data(&mut reader, num_samples)?;
-            bit_storage = Some(maybe_bit_storage);
+            data_read += u64::from(subchunk_size);
 
-            data_read += subchunk_size;
+            if chunk_size_is_placeholder {
+                break;
+            }
         } else {
             // Other unsupported subchunk, skipping it
             let subchunk_size = reader.read_u32::<LittleEndian>()?;
@@ -198,18 +358,479 @@ pub fn parse(mut reader: &mut impl Read) -> Result<BitVec, ParsingError> {
                 return Err(ParsingError::InvalidFormat);
             }
 
-            for _ in data_read..cmp::min(data_read + subchunk_size, data_size) {
-                reader.read_u8()?;
-            }
-            data_read += subchunk_size;
+            reader.skip(cmp::min(
+                u64::from(subchunk_size),
+                data_size.saturating_sub(data_read),
+            ))?;
+            data_read += u64::from(subchunk_size);
         }
     }
 
     match bit_storage {
         // OpenPuff considers a WAVE file without a 'data' subchunk valid.
         // So, we have to return a new BitVec even if parsing the file didn't produce one.
-        None => Ok(BitVec::new()),
+        None => Ok((BitVec::new(), warnings)),
+
+        Some(bit_storage) => Ok((bit_storage, warnings)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use std::io::{self, Cursor, SeekFrom};
+
+    /// Inserts a 'LIST' subchunk of `size` bytes, holding `content`, after the 'fmt ' subchunk.
+    fn with_list_subchunk(wav: &[u8], size: u32, content: &[u8]) -> Vec<u8> {
+        let fmt_end = 12 + 8 + 16;
+        let riff_size = u32::from_le_bytes(wav[4..8].try_into().unwrap()) + 8 + size;
+
+        let mut new_wav = Vec::new();
+        new_wav.extend_from_slice(b"RIFF");
+        new_wav.extend_from_slice(&riff_size.to_le_bytes());
+        new_wav.extend_from_slice(&wav[8..fmt_end]);
+        new_wav.extend_from_slice(b"LIST");
+        new_wav.extend_from_slice(&size.to_le_bytes());
+        new_wav.extend_from_slice(content);
+        new_wav.extend_from_slice(&wav[fmt_end..]);
+
+        new_wav
+    }
+
+    /// Reader counting the bytes read through it.
+    struct CountingReader<R> {
+        reader: R,
+        bytes_read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = self.reader.read(buf)?;
+            self.bytes_read += count;
+            Ok(count)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.reader.seek(pos)
+        }
+    }
+
+    #[test]
+    fn seeking_past_subchunks() {
+        let samples: Vec<u16> = (0..1000).map(|i| 0b1000 | (i % 3)).collect();
+        let list_size = 1 << 20;
+        let wav = with_list_subchunk(
+            &test_utils::wav_file(&samples),
+            list_size,
+            &vec![0x55; list_size as usize],
+        );
+
+        let mut reader = CountingReader {
+            reader: Cursor::new(&wav),
+            bytes_read: 0,
+        };
+        let parsed = parse_seekable(&mut reader, &WavOptions::default()).unwrap();
+        assert_eq!(
+            parsed,
+            parse(&mut &wav[..], &WavOptions::default()).unwrap()
+        );
+        assert_eq!(reader.bytes_read, wav.len() - list_size as usize);
+
+        // Truncated subchunk, which can't be seeked past
+        let wav = with_list_subchunk(&test_utils::wav_file(&samples), list_size, &[]);
+        let truncated = &wav[..wav.len() - 2 * samples.len() - 8];
+        assert!(matches!(
+            parse_seekable(&mut Cursor::new(truncated), &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+        assert!(matches!(
+            parse(&mut &truncated[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+    }
+
+    fn extensible_format(valid_bits_per_sample: u16, sub_format: [u8; 16]) -> Vec<u8> {
+        let mut format = test_utils::wav_format(WAVE_FORMAT_EXTENSIBLE);
+        format.extend_from_slice(&22u16.to_le_bytes()); // cbSize
+        format.extend_from_slice(&valid_bits_per_sample.to_le_bytes());
+        format.extend_from_slice(&0x4u32.to_le_bytes()); // dwChannelMask: front center
+        format.extend_from_slice(&sub_format);
+
+        format
+    }
+
+    #[test]
+    fn extensible_format_pcm() {
+        let samples: Vec<u16> = (0..1000).map(|i| 0b1000 | (i % 3)).collect();
+        let pcm_bits = parse(
+            &mut &test_utils::wav_file(&samples)[..],
+            &WavOptions::default(),
+        )
+        .unwrap()
+        .0;
+
+        let wav = test_utils::wav_file_with_format(
+            &extensible_format(16, KSDATAFORMAT_SUBTYPE_PCM),
+            &samples,
+        );
+        assert_eq!(
+            parse(&mut &wav[..], &WavOptions::default()).unwrap().0,
+            pcm_bits
+        );
+    }
+
+    #[test]
+    fn extensible_format_not_pcm() {
+        let samples = [0b1000; 100];
+
+        let mut ieee_float = KSDATAFORMAT_SUBTYPE_PCM;
+        ieee_float[0] = 0x03;
+        let wav = test_utils::wav_file_with_format(&extensible_format(16, ieee_float), &samples);
+        assert!(matches!(
+            parse(&mut &wav[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+
+        let wav = test_utils::wav_file_with_format(
+            &extensible_format(12, KSDATAFORMAT_SUBTYPE_PCM),
+            &samples,
+        );
+        assert!(matches!(
+            parse(&mut &wav[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+
+        // Truncated extension
+        let wav = test_utils::wav_file_with_format(
+            &extensible_format(16, KSDATAFORMAT_SUBTYPE_PCM)[..30],
+            &samples,
+        );
+        assert!(matches!(
+            parse(&mut &wav[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn multiple_data_subchunks() {
+        let first_samples: Vec<u16> = (0..500).map(|i| 0b1000 | (i % 3)).collect();
+        let second_samples: Vec<u16> = (0..300).map(|i| 0b11000 | (i % 5)).collect();
+
+        let mut wav = test_utils::wav_file(&first_samples);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(2 * second_samples.len() as u32).to_le_bytes());
+        for sample in &second_samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        let chunk_size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&chunk_size.to_le_bytes());
+
+        assert!(matches!(
+            parse(
+                &mut &wav[..],
+                &WavOptions {
+                    strict: true,
+                    ..Default::default()
+                }
+            ),
+            Err(ParsingError::InvalidFormat)
+        ));
+
+        let all_samples = [first_samples, second_samples].concat();
+        let expected_bits = parse(
+            &mut &test_utils::wav_file(&all_samples)[..],
+            &WavOptions::default(),
+        )
+        .unwrap()
+        .0;
+        assert_eq!(
+            parse(
+                &mut &wav[..],
+                &WavOptions {
+                    strict: false,
+                    ..Default::default()
+                }
+            )
+            .unwrap()
+            .0,
+            expected_bits
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let samples = [0b1000; 100];
+
+        let (_, warnings) = parse(
+            &mut &test_utils::wav_file(&samples)[..],
+            &WavOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(warnings, []);
+
+        // BitsPerSample disagrees with BlockAlign, and the header has 4 more bytes.
+        let mut format = test_utils::wav_format(WAVE_FORMAT_PCM);
+        format[14..16].copy_from_slice(&24u16.to_le_bytes());
+        format.extend_from_slice(&[0; 4]);
+
+        let wav = test_utils::wav_file_with_format(&format, &samples);
+        let (_, warnings) = parse(&mut &wav[..], &WavOptions::default()).unwrap();
+        assert_eq!(
+            warnings,
+            [
+                ParserWarning::BlockAlignMismatch,
+                ParserWarning::FmtTrailingData
+            ]
+        );
+    }
+
+    #[test]
+    fn placeholder_sizes() {
+        let samples: Vec<u16> = (0..1000).map(|i| 0b1000 | (i % 3)).collect();
+        let wav = test_utils::wav_file(&samples);
+        let expected_bits = parse(&mut &wav[..], &WavOptions::default()).unwrap().0;
+
+        let fallback = WavOptions {
+            placeholder_size_fallback: true,
+            ..Default::default()
+        };
+
+        for placeholder in [0u32, u32::MAX] {
+            let mut streamed_wav = wav.clone();
+            streamed_wav[4..8].copy_from_slice(&placeholder.to_le_bytes()); // ChunkSize
+            streamed_wav[40..44].copy_from_slice(&placeholder.to_le_bytes()); // data SubchunkSize
+
+            assert!(matches!(
+                parse(&mut &streamed_wav[..], &WavOptions::default()),
+                Err(ParsingError::InvalidFormat)
+            ));
+            assert_eq!(
+                parse(&mut &streamed_wav[..], &fallback).unwrap().0,
+                expected_bits
+            );
+        }
 
-        Some(bit_storage) => Ok(bit_storage),
+        // Only the 'data' SubchunkSize is a placeholder
+        let mut streamed_wav = wav.clone();
+        streamed_wav[40..44].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            parse(&mut &streamed_wav[..], &fallback).unwrap().0,
+            expected_bits
+        );
+    }
+
+    #[test]
+    fn small_fmt_subchunk_sizes() {
+        let wav = test_utils::wav_file(&[0b1000; 100]);
+
+        for fmt_size in 0..16u32 {
+            let mut malformed_wav = wav.clone();
+            malformed_wav[16..20].copy_from_slice(&fmt_size.to_le_bytes());
+
+            assert!(matches!(
+                parse(&mut &malformed_wav[..], &WavOptions::default()),
+                Err(ParsingError::InvalidFormat)
+            ));
+        }
+    }
+
+    #[test]
+    fn garbage_fmt_fields() {
+        let samples = [0b1000; 100];
+
+        // NumChannels = 0
+        let mut format = test_utils::wav_format(WAVE_FORMAT_PCM);
+        format[2..4].copy_from_slice(&0u16.to_le_bytes());
+        let wav = test_utils::wav_file_with_format(&format, &samples);
+        assert!(matches!(
+            parse(&mut &wav[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+
+        // BlockAlign = 0xFFFF
+        let mut format = test_utils::wav_format(WAVE_FORMAT_PCM);
+        format[12..14].copy_from_slice(&u16::MAX.to_le_bytes());
+        let wav = test_utils::wav_file_with_format(&format, &samples);
+        assert!(matches!(
+            parse(&mut &wav[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+    }
+
+    /// Returns the content of the 'fmt ' subchunk of a mono PCM WAVE file with
+    /// `bits_per_sample`-bit samples.
+    fn wav_format_with_depth(bits_per_sample: u16) -> Vec<u8> {
+        let mut format = test_utils::wav_format(WAVE_FORMAT_PCM);
+        let block_align = bits_per_sample / 8;
+        format[8..12].copy_from_slice(&(44100 * u32::from(block_align)).to_le_bytes());
+        format[12..14].copy_from_slice(&block_align.to_le_bytes());
+        format[14..16].copy_from_slice(&bits_per_sample.to_le_bytes());
+        format
+    }
+
+    #[test]
+    fn sample_selection() {
+        // Selection of 16-bit samples, as originally written for them
+        fn should_choose_16_bit_sample(sample: u16, first_relevant_bit: usize) -> bool {
+            let sample = sample & !0b10000000_00000000;
+            let ones = (sample >> (first_relevant_bit - 1)).count_ones();
+
+            ones > 0 && ones <= (14 - first_relevant_bit) as u32
+        }
+
+        for sample in 0..=u16::MAX {
+            assert_eq!(
+                should_choose_sample(u32::from(sample), 16, &SampleSelection::default()),
+                should_choose_16_bit_sample(sample, 4)
+            );
+        }
+    }
+
+    #[test]
+    fn sample_selection_thresholds() {
+        let openpuff = SampleSelection::default();
+        let choose = |sample: u32| should_choose_sample(sample, 16, &openpuff);
+
+        // Bits below the 4th one aren't counted
+        assert!(!choose(0b0000_0000_0000_0111));
+        assert!(choose(0b0000_0000_0000_1000));
+        assert!(choose(0b0000_0000_0000_1111));
+        // At most 10 ones, from the 4th bit up to the 15th one
+        assert!(choose(0b0001_1111_1111_1000));
+        assert!(!choose(0b0011_1111_1111_1000));
+        assert!(choose(0b0011_1111_1110_1111));
+
+        // The sign bit
+        assert!(!choose(0b1000_0000_0000_0000));
+        assert!(choose(0b1001_1111_1111_1000));
+        let with_sign_bit = SampleSelection {
+            ignore_sign_bit: false,
+            ..openpuff
+        };
+        assert!(should_choose_sample(
+            0b1000_0000_0000_0000,
+            16,
+            &with_sign_bit
+        ));
+        assert!(!should_choose_sample(
+            0b1001_1111_1111_1000,
+            16,
+            &with_sign_bit
+        ));
+
+        // Counting from the second bit, up to 12 ones
+        let from_second_bit = SampleSelection {
+            first_relevant_bit: 2,
+            ..openpuff
+        };
+        assert!(!should_choose_sample(
+            0b0000_0000_0000_0001,
+            16,
+            &from_second_bit
+        ));
+        assert!(should_choose_sample(
+            0b0000_0000_0000_0010,
+            16,
+            &from_second_bit
+        ));
+        assert!(should_choose_sample(
+            0b0001_1111_1111_1111,
+            16,
+            &from_second_bit
+        ));
+        assert!(!should_choose_sample(
+            0b0011_1111_1111_1110,
+            16,
+            &from_second_bit
+        ));
+
+        // The least significant bit can't be counted
+        for first_relevant_bit in [0, 1] {
+            let with_lsb = SampleSelection {
+                first_relevant_bit,
+                ..openpuff
+            };
+            assert!(!should_choose_sample(0b0000_0000_0000_1000, 16, &with_lsb));
+            assert!(!should_choose_sample(0b0000_0000_0000_1001, 16, &with_lsb));
+        }
+
+        // No room left for ones
+        let too_high = SampleSelection {
+            first_relevant_bit: 14,
+            ..openpuff
+        };
+        assert!(!should_choose_sample(0b0010_0000_0000_0000, 16, &too_high));
+    }
+
+    #[test]
+    fn extended_sample_sizes() {
+        let extended = WavOptions {
+            extended_sample_sizes: true,
+            ..Default::default()
+        };
+
+        // 8-bit samples are unsigned
+        let wav =
+            test_utils::wav_file_with_data(&wav_format_with_depth(8), &[0x89, 0x80, 0xF8, 0x08]);
+        assert!(matches!(
+            parse(&mut &wav[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+        let (bits, _) = parse(&mut &wav[..], &extended).unwrap();
+        assert_eq!(bits, BitVec::from_iter([true, false]));
+
+        let samples: [u32; 5] = [0x000009, 0x000008, 0x000001, 0x7FFFF8, 0x800009];
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes()[..3].to_vec())
+            .collect();
+        let wav = test_utils::wav_file_with_data(&wav_format_with_depth(24), &data);
+        assert!(matches!(
+            parse(&mut &wav[..], &WavOptions::default()),
+            Err(ParsingError::InvalidFormat)
+        ));
+        let (bits, _) = parse(&mut &wav[..], &extended).unwrap();
+        assert_eq!(bits, BitVec::from_iter([true, false, true]));
+
+        // Other sample sizes are still rejected
+        let wav = test_utils::wav_file_with_data(&wav_format_with_depth(32), &[0; 8]);
+        assert!(matches!(
+            parse(&mut &wav[..], &extended),
+            Err(ParsingError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn corrupted_files_do_not_panic() {
+        let samples: Vec<u16> = (0..64).map(|i| 0b1000 | (i % 3)).collect();
+        let wav = test_utils::wav_file(&samples);
+
+        let options = [
+            WavOptions::default(),
+            WavOptions {
+                strict: false,
+                placeholder_size_fallback: true,
+                extended_sample_sizes: true,
+                ..Default::default()
+            },
+        ];
+
+        // Overwrite the header bytes with deterministic pseudo-random values
+        let mut state: u32 = 0x12345678;
+        for _ in 0..2000 {
+            let mut corrupted_wav = wav.clone();
+            for _ in 0..4 {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let position = (state >> 8) as usize % 44;
+                corrupted_wav[position] = (state >> 24) as u8;
+            }
+
+            for options in &options {
+                let _ = parse(&mut &corrupted_wav[..], options);
+            }
+        }
     }
 }
@@ -14,15 +14,34 @@
 // You should have received a copy of the GNU General Public License
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
-use log::warn;
 use std::cmp::max;
+use std::fmt::{self, Display};
 
 use crate::Error;
 
+/// Maximum length of a password accepted by OpenPuff.
+pub const OPENPUFF_MAX_PASSWORD_LENGTH: usize = 32;
+
 /// Computes the hamming distance between `password_1` and `password_2`, returning a percentage
 /// where 100 corresponds to `password_1` and `password_2` being the most different as possible.
-fn compute_hamming_distance(password_1: &[u8], password_2: &[u8]) -> usize {
+///
+/// The shortest password is padded with zeros to the length of the longest one, and the number
+/// of differing bits is divided by the number of bits of the longest one, rounding down. This is
+/// how OpenPuff measures the correlation of passwords: it asks for distances of at least 25%.
+///
+/// ```
+/// use librepuff::passwords::compute_hamming_distance;
+///
+/// assert_eq!(compute_hamming_distance(b"testtest", b"testtest"), 0);
+/// assert_eq!(compute_hamming_distance(b"aaaaaaaa", b"12345678"), 45);
+/// // "aaaaaaaa" is compared to "aaaaaaaa\0"
+/// assert_eq!(compute_hamming_distance(b"aaaaaaaa", b"aaaaaaaaa"), 4);
+/// ```
+pub fn compute_hamming_distance(password_1: &[u8], password_2: &[u8]) -> usize {
     let total = max(password_1.len(), password_2.len());
+    if total == 0 {
+        return 0;
+    }
 
     let mut differences: usize = 0;
     for i in 0..total {
@@ -43,7 +62,57 @@ fn compute_hamming_distance(password_1: &[u8], password_2: &[u8]) -> usize {
     (differences * 100) / (total * 8)
 }
 
-#[derive(Debug)]
+/// Reason why OpenPuff wouldn't accept a set of passwords.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordWarning {
+    /// Password C is specified, but not password B.
+    MissingBWithC,
+    /// Password B is less than 8 bytes long.
+    TooShortB,
+    /// Password C is less than 8 bytes long.
+    TooShortC,
+    /// A password is longer than OpenPuff allows.
+    TooLong,
+    /// A password contains non-ASCII characters, which OpenPuff may encode differently.
+    NonAscii,
+    /// Passwords A and B are too correlated. Holds their distance, as a percentage.
+    CorrelatedAB(usize),
+    /// Passwords A and C are too correlated. Holds their distance, as a percentage.
+    CorrelatedAC(usize),
+    /// Passwords B and C are too correlated. Holds their distance, as a percentage.
+    CorrelatedBC(usize),
+}
+impl Display for PasswordWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingBWithC => write!(
+                f,
+                "password B not specified while password C is, this would be impossible in OpenPuff"
+            ),
+            Self::TooShortB => write!(
+                f,
+                "password B is less than 8 bytes long, OpenPuff wouldn't allow this"
+            ),
+            Self::TooShortC => write!(
+                f,
+                "password C is less than 8 bytes long, OpenPuff wouldn't allow this"
+            ),
+            Self::NonAscii => write!(
+                f,
+                "a password contains non-ASCII characters, which OpenPuff may encode differently"
+            ),
+            Self::TooLong => write!(
+                f,
+                "a password is longer than {OPENPUFF_MAX_PASSWORD_LENGTH} bytes, OpenPuff wouldn't allow this"
+            ),
+            Self::CorrelatedAB(distance) => write!(f, "passwords A and B are too correlated (distance of {distance}% < 25%), OpenPuff would complain."),
+            Self::CorrelatedAC(distance) => write!(f, "passwords A and C are too correlated (distance of {distance}% < 25%), OpenPuff would complain."),
+            Self::CorrelatedBC(distance) => write!(f, "passwords B and C are too correlated (distance of {distance}% < 25%), OpenPuff would complain."),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Passwords<'a> {
     /// Password A. Used for multi-cryptography.
     pub a: &'a str,
@@ -53,62 +122,88 @@ pub struct Passwords<'a> {
     pub c: &'a str,
 }
 impl<'a> Passwords<'a> {
-    /// TODO: be more consistent with when to warn
+    /// Creates the passwords used to decrypt carriers.
+    ///
+    /// If password B or C aren't specified, they default to password A. Oddities OpenPuff
+    /// wouldn't accept aren't errors: see `Passwords::analyze`.
+    ///
+    /// Passwords are passed to libObfuscate as their UTF-8 encoding, so every length limit is
+    /// in bytes rather than in characters: a password of 8 emojis is 32 bytes long. Non-ASCII
+    /// passwords may not match the ones typed in OpenPuff, whose encoding is unknown.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::PasswordTooLong` if any of the passwords, password A included, is longer
+    /// than `OPENPUFF_MAX_PASSWORD_LENGTH` bytes. libObfuscate's password buffers are no longer
+    /// than that: such a password couldn't be used to decrypt carriers.
     pub fn from_fields(a: &'a str, b: Option<&'a str>, c: Option<&'a str>) -> Result<Self, Error> {
-        if !c.is_none() && b.is_none() {
-            warn!("password B not specified while password C is, this would be impossible in OpenPuff");
+        // Length checks
+        let is_too_long = |password: &str| password.len() > OPENPUFF_MAX_PASSWORD_LENGTH;
+        if is_too_long(a) || b.is_some_and(is_too_long) || c.is_some_and(is_too_long) {
+            return Err(Error::PasswordTooLong);
         }
 
-        // Length checks
+        // If password B or C aren't specified, they default to password A.
+        let mut passwords = Passwords { a: a, b: a, c: a };
         if let Some(b) = b {
-            if b.len() < 8 {
-                warn!("password B is less than 8 characters long, OpenPuff wouldn't allow this");
-            }
-            if b.len() > 32 {
-                return Err(Error::PasswordTooLong);
-            }
+            passwords.b = b;
         }
         if let Some(c) = c {
-            if c.len() < 8 {
-                warn!("password C is less than 8 characters long, OpenPuff wouldn't allow this");
-            }
-            if c.len() > 32 {
-                return Err(Error::PasswordTooLong);
-            }
+            passwords.c = c;
+        }
+
+        Ok(passwords)
+    }
+
+    /// Returns the reasons why OpenPuff wouldn't accept these passwords, as passed to
+    /// `Passwords::from_fields`.
+    pub fn analyze(a: &str, b: Option<&str>, c: Option<&str>) -> Vec<PasswordWarning> {
+        let mut warnings = Vec::new();
+
+        if c.is_some() && b.is_none() {
+            warnings.push(PasswordWarning::MissingBWithC);
+        }
+
+        // Length checks
+        let is_too_long = |password: &str| password.len() > OPENPUFF_MAX_PASSWORD_LENGTH;
+        if is_too_long(a) || b.is_some_and(is_too_long) || c.is_some_and(is_too_long) {
+            warnings.push(PasswordWarning::TooLong);
+        }
+        if [Some(a), b, c]
+            .into_iter()
+            .flatten()
+            .any(|password| !password.is_ascii())
+        {
+            warnings.push(PasswordWarning::NonAscii);
+        }
+        if b.is_some_and(|b| b.len() < 8) {
+            warnings.push(PasswordWarning::TooShortB);
+        }
+        if c.is_some_and(|c| c.len() < 8) {
+            warnings.push(PasswordWarning::TooShortC);
         }
 
         // Distance checks
         if let Some(b) = b {
             let distance_ab = compute_hamming_distance(a.as_bytes(), b.as_bytes());
             if distance_ab < 25 {
-                warn!("passwords A and B are too correlated (distance of {distance_ab}% < 25%), OpenPuff would complain.");
+                warnings.push(PasswordWarning::CorrelatedAB(distance_ab));
             }
         }
         if let Some(c) = c {
             let distance_ac = compute_hamming_distance(a.as_bytes(), c.as_bytes());
             if distance_ac < 25 {
-                warn!("passwords A and C are too correlated (distance of {distance_ac}% < 25%), OpenPuff would complain.");
+                warnings.push(PasswordWarning::CorrelatedAC(distance_ac));
             }
         }
-        if let Some(b) = b {
-            if let Some(c) = c {
-                let distance_bc = compute_hamming_distance(b.as_bytes(), c.as_bytes());
-                if distance_bc < 25 {
-                    warn!("passwords B and C are too correlated (distance of {distance_bc}% < 25%), OpenPuff would complain.");
-                }
+        if let (Some(b), Some(c)) = (b, c) {
+            let distance_bc = compute_hamming_distance(b.as_bytes(), c.as_bytes());
+            if distance_bc < 25 {
+                warnings.push(PasswordWarning::CorrelatedBC(distance_bc));
             }
         }
 
-        // If password B or C aren't specified, they default to password A.
-        let mut passwords = Passwords { a: a, b: a, c: a };
-        if let Some(b) = b {
-            passwords.b = b;
-        }
-        if let Some(c) = c {
-            passwords.c = c;
-        }
-
-        Ok(passwords)
+        warnings
     }
 }
 
@@ -135,5 +230,91 @@ mod tests {
             ),
             1
         );
+        assert_eq!(compute_hamming_distance(b"", b""), 0);
+    }
+
+    #[test]
+    fn password_a_length() {
+        // Password A is used for multi-cryptography like password B, with the same limit.
+        assert!(Passwords::from_fields(&"a".repeat(32), None, None).is_ok());
+        assert!(matches!(
+            Passwords::from_fields(&"a".repeat(33), None, None),
+            Err(Error::PasswordTooLong)
+        ));
+    }
+
+    #[test]
+    fn password_lengths() {
+        let password_32 = "a".repeat(32);
+        let password_33 = "a".repeat(33);
+
+        assert!(Passwords::from_fields(&password_32, Some(&password_32), None).is_ok());
+        assert!(matches!(
+            Passwords::from_fields("password", Some(&password_33), None),
+            Err(Error::PasswordTooLong)
+        ));
+
+        assert_eq!(
+            Passwords::analyze(&password_33, None, None),
+            [PasswordWarning::TooLong]
+        );
+    }
+
+    #[test]
+    fn non_ascii_passwords() {
+        // Lengths are in bytes: 16 characters, 32 bytes
+        let accented = "éèàùçâêîôûëïüÿæœ";
+        assert_eq!(accented.len(), 32);
+        assert!(Passwords::from_fields("password", Some(accented), None).is_ok());
+
+        // 8 characters, 32 bytes
+        let emojis = "🔑🔒🔓🗝🔐🛡🧩🎲";
+        assert_eq!(emojis.chars().count(), 8);
+        assert!(Passwords::from_fields("password", None, Some(emojis)).is_ok());
+
+        // 9 characters, 36 bytes
+        let more_emojis = "🔑🔒🔓🗝🔐🛡🧩🎲🎯";
+        assert!(matches!(
+            Passwords::from_fields("password", None, Some(more_emojis)),
+            Err(Error::PasswordTooLong)
+        ));
+
+        // 4 characters, 8 bytes
+        assert_eq!(
+            Passwords::analyze("ɐɐɐɐ", Some("ZYXWVUTS"), None),
+            [PasswordWarning::NonAscii]
+        );
+        assert_eq!(
+            Passwords::analyze("password", Some("ééé"), None),
+            [PasswordWarning::NonAscii, PasswordWarning::TooShortB]
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        assert_eq!(
+            Passwords::analyze("abcdefgh", Some("01234567"), Some("ZYXWVUTS")),
+            []
+        );
+
+        assert_eq!(
+            Passwords::analyze("abcdefgh", None, Some("ZYXWVUTS")),
+            [PasswordWarning::MissingBWithC]
+        );
+
+        assert_eq!(
+            Passwords::analyze("abcdefgh", Some("0123"), Some("abcdefgi")),
+            [PasswordWarning::TooShortB, PasswordWarning::CorrelatedAC(1)]
+        );
+
+        assert_eq!(
+            Passwords::analyze("testtest", Some("testtest"), Some("testtes")),
+            [
+                PasswordWarning::TooShortC,
+                PasswordWarning::CorrelatedAB(0),
+                PasswordWarning::CorrelatedAC(6),
+                PasswordWarning::CorrelatedBC(6)
+            ]
+        );
     }
 }
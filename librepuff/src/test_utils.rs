@@ -0,0 +1,242 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers building carriers for tests, shared by the tests of this crate, its benchmarks and
+//! repuff's tests. Only built with the `test-utils` feature, and not part of the stable API.
+
+use crate::bit_selection::BitSelection;
+use crate::bitutil;
+use crate::carrier::{whitening, EncryptedCarrier, IV_BIT_COUNT, RESERVED_BIT_COUNT};
+use crate::chain::{self, CarrierEmbeddings};
+use crate::crc32;
+use crate::passwords::Passwords;
+#[cfg(feature = "fs")]
+use std::{
+    env, fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// Returns the content of the 'fmt ' subchunk of a 16-bit mono WAVE file using `audio_format`.
+pub fn wav_format(audio_format: u16) -> Vec<u8> {
+    let mut format = Vec::new();
+    format.extend_from_slice(&audio_format.to_le_bytes()); // AudioFormat
+    format.extend_from_slice(&1u16.to_le_bytes()); // NumChannels
+    format.extend_from_slice(&44100u32.to_le_bytes()); // SampleRate
+    format.extend_from_slice(&(2 * 44100u32).to_le_bytes()); // ByteRate
+    format.extend_from_slice(&2u16.to_le_bytes()); // BlockAlign
+    format.extend_from_slice(&16u16.to_le_bytes()); // BitsPerSample
+
+    format
+}
+
+/// Returns a 16-bit mono PCM WAVE file containing `samples`.
+pub fn wav_file(samples: &[u16]) -> Vec<u8> {
+    wav_file_with_format(&wav_format(1), samples)
+}
+
+/// Returns a WAVE file whose 'fmt ' subchunk contains `format`, followed by `samples`.
+pub fn wav_file_with_format(format: &[u8], samples: &[u16]) -> Vec<u8> {
+    let data: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+    wav_file_with_data(format, &data)
+}
+
+/// Returns a WAVE file whose 'fmt ' subchunk contains `format`, and whose 'data' subchunk
+/// contains `data`.
+pub fn wav_file_with_data(format: &[u8], data: &[u8]) -> Vec<u8> {
+    let format_size = format.len() as u32;
+    let data_size = data.len() as u32;
+
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(4 + 8 + format_size + 8 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&format_size.to_le_bytes());
+    wav.extend_from_slice(format);
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    wav.extend_from_slice(data);
+
+    wav
+}
+
+/// Writes `content` to a file named `name` in a temporary directory, and returns its path.
+#[cfg(feature = "fs")]
+pub fn temporary_file(name: &str, content: &[u8]) -> PathBuf {
+    let directory = env::temp_dir().join(format!("librepuff-tests-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+
+    let path = directory.join(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+/// Returns a 16-bit mono WAVE file parsed into `carrier` at `selection_level`, with filler bits
+/// all zeros.
+///
+/// Every sample holds a whitened bit in its least significant bit, and has a single one among
+/// the bits counted by the parser, so that it is always chosen. The number of whitened bits,
+/// which seeds the whitening table, is chosen first so that exactly the data and decoy bits of
+/// `carrier` are selected. Then, each chunk of 6 unwhitened bits is whitened into a chunk of 13
+/// bits found in the table.
+///
+/// # Panics
+///
+/// Panics if `carrier.data` and `carrier.decoy` don't both hold the same non-zero multiple of 16
+/// bytes, the granularity of the selected bit count.
+pub fn hiding_wav_file(carrier: &EncryptedCarrier, selection_level: BitSelection) -> Vec<u8> {
+    let selected_bit_count = 8 * carrier.data.len();
+    assert!(
+        selected_bit_count > 0
+            && selected_bit_count.is_multiple_of(128)
+            && carrier.decoy.len() == carrier.data.len(),
+        "unsupported data and decoy sizes"
+    );
+    let divisor = selection_level.divisor();
+
+    // The IV, then a data bit and a decoy bit out of every `divisor` bits, then zeros
+    let chunk_count = (RESERVED_BIT_COUNT + divisor * selected_bit_count).div_ceil(6);
+    let mut unwhitened_bits = bitutil::unpack_msb_first(&carrier.iv, IV_BIT_COUNT);
+    let data_bits = bitutil::unpack_msb_first(&carrier.data, selected_bit_count);
+    let decoy_bits = bitutil::unpack_msb_first(&carrier.decoy, selected_bit_count);
+    for (data_bit, decoy_bit) in data_bits.iter().zip(&decoy_bits) {
+        unwhitened_bits.push(data_bit);
+        unwhitened_bits.push(decoy_bit);
+        unwhitened_bits.grow(divisor - 2, false);
+    }
+    unwhitened_bits.grow(6 * chunk_count - unwhitened_bits.len(), false);
+
+    let table = whitening::generate_whitening_lookup_table(13 * chunk_count);
+    let mut whitened_chunks = [None; 1 << 6];
+    for (chunk, &entry) in table.iter().enumerate() {
+        whitened_chunks[entry as usize].get_or_insert(chunk as u16);
+    }
+
+    let samples: Vec<u16> = (0..chunk_count)
+        .flat_map(|chunk_index| {
+            let entry = (0..6).fold(0, |entry, j| {
+                entry << 1 | unwhitened_bits[6 * chunk_index + j] as usize
+            });
+            let chunk =
+                whitened_chunks[entry].expect("6-bit value missing from the whitening table");
+            (0..13).rev().map(move |j| 0b1000 | (chunk >> j) & 1)
+        })
+        .collect();
+
+    wav_file(&samples)
+}
+
+/// Returns a chain of WAVE carriers hiding `data` and `decoy` (see `embedded_file_bytes`) with
+/// `passwords` at `selection_level`, like OpenPuff would.
+///
+/// Both are split evenly across `carrier_count` carriers, and padded with zeros. See
+/// `hiding_wav_file`.
+pub fn hiding_wav_files(
+    data: &[u8],
+    decoy: &[u8],
+    carrier_count: usize,
+    selection_level: BitSelection,
+    passwords: &Passwords,
+) -> Vec<Vec<u8>> {
+    let carrier_bytes = data
+        .len()
+        .max(decoy.len())
+        .div_ceil(carrier_count)
+        .max(1)
+        .next_multiple_of(16);
+    let padded = |bytes: &[u8]| {
+        let mut padded = bytes.to_vec();
+        padded.resize(carrier_count * carrier_bytes, 0);
+        padded
+    };
+    let (data, decoy) = (padded(data), padded(decoy));
+
+    let embeddings = data
+        .chunks(carrier_bytes)
+        .zip(decoy.chunks(carrier_bytes))
+        .map(|(data, decoy)| CarrierEmbeddings {
+            data: data.to_vec(),
+            decoy: decoy.to_vec(),
+        })
+        .collect();
+    let decrypted_ivs: Vec<[u8; 256]> = (0..carrier_count)
+        .map(|i| [0x17u8.wrapping_add(41 * i as u8); 256])
+        .collect();
+
+    chain::encrypt_carrier_chain(embeddings, &decrypted_ivs, passwords)
+        .iter()
+        .map(|carrier| hiding_wav_file(carrier, selection_level))
+        .collect()
+}
+
+/// Returns the bytes of an embedded file named `filename` holding `content`, in the format read by
+/// `EmbeddedFile::from_bits`.
+pub fn embedded_file_bytes(filename: &[u8], content: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(filename.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32::compute(content).to_le_bytes());
+    bytes.extend_from_slice(filename);
+    bytes.extend_from_slice(content);
+    bytes
+}
+
+#[cfg(feature = "fs")]
+/// Directory for the files of a test, removed along with its content when dropped.
+#[cfg(feature = "fs")]
+pub struct TemporaryDirectory(PathBuf);
+
+#[cfg(feature = "fs")]
+impl TemporaryDirectory {
+    /// Creates a directory named after `name`, which must be unique among the tests of a test
+    /// binary, and the process ID.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("librepuff-tests-{}-{name}", std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+
+        TemporaryDirectory(path)
+    }
+
+    /// Writes `content` to a file named `name` in the directory, and returns its path.
+    pub fn file(&self, name: &str, content: &[u8]) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Deref for TemporaryDirectory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Drop for TemporaryDirectory {
+    fn drop(&mut self) {
+        // Failing to clean up mustn't fail the test.
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+/// Options of the WAVE carrier parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavOptions {
+    /// Whether to reject files OpenPuff rejects, even though they could be parsed.
+    ///
+    /// When disabled, the samples of every 'data' subchunk are used, instead of erroring on the
+    /// second one.
+    pub strict: bool,
+    /// Whether to read the samples up to the end of the file when the size of the RIFF chunk or
+    /// of the 'data' subchunk is a placeholder (0 or 0xFFFFFFFF), as left by streaming tools.
+    ///
+    /// OpenPuff trusts these sizes. As the whole file is read, trailing data can't be detected.
+    /// The samples are read into memory before being parsed.
+    pub placeholder_size_fallback: bool,
+    /// Whether to accept 8-bit and 24-bit samples, on top of the 16-bit samples OpenPuff uses.
+    ///
+    /// OpenPuff rejects these files, so nothing it hides can be found in them: this is only
+    /// meant for experimenting. Samples are selected as 16-bit ones are, the number of relevant
+    /// bits being adjusted to the sample size.
+    pub extended_sample_sizes: bool,
+    /// How samples are chosen to hold a bit.
+    ///
+    /// Carriers hidden by OpenPuff can only be read with the default selection.
+    pub sample_selection: SampleSelection,
+}
+
+impl Default for WavOptions {
+    fn default() -> Self {
+        // OpenPuff's behaviour
+        Self {
+            strict: true,
+            placeholder_size_fallback: false,
+            extended_sample_sizes: false,
+            sample_selection: SampleSelection::default(),
+        }
+    }
+}
+
+/// Rule choosing the samples which hold a bit, in their least significant bit.
+///
+/// A sample is chosen if the number of ones among its bits from `first_relevant_bit` upwards is
+/// neither zero nor larger than `bits_per_sample - 2 - first_relevant_bit`, so that changing its
+/// least significant bit doesn't change whether it is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleSelection {
+    /// Position of the lowest bit counted, starting at 1 for the least significant bit.
+    ///
+    /// No sample is chosen if it leaves no room for ones, i.e. from `bits_per_sample - 2` upwards,
+    /// nor below 2: counting the least significant bit would make flipping it change whether the
+    /// sample is chosen.
+    pub first_relevant_bit: u32,
+    /// Whether the sign bit, the most significant one, is left out of the count.
+    pub ignore_sign_bit: bool,
+}
+
+impl Default for SampleSelection {
+    fn default() -> Self {
+        // OpenPuff's behaviour
+        Self {
+            first_relevant_bit: 4,
+            ignore_sign_bit: true,
+        }
+    }
+}
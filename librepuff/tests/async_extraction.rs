@@ -0,0 +1,109 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Asynchronous extraction, with tokio.
+
+#![cfg(feature = "async")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use librepuff::bit_selection::BitSelection;
+use librepuff::carrier::{self, CarrierOptions};
+use librepuff::carrier_type::CarrierType;
+use librepuff::extraction;
+use librepuff::passwords::Passwords;
+use librepuff::test_utils::{self, wav_file, TemporaryDirectory};
+use librepuff::Error;
+
+#[tokio::test]
+async fn async_extraction() {
+    let directory = TemporaryDirectory::new("async_extraction");
+
+    let paths: Vec<PathBuf> = (0..2u16)
+        .map(|i| {
+            let samples: Vec<u16> = (0..13000).map(|j| 0b1000 | ((j + i) % 5)).collect();
+            directory.file(&format!("carrier_{i}.wav"), &wav_file(&samples))
+        })
+        .collect();
+
+    let passwords = Passwords::from_fields("firstpassword", None, None).unwrap();
+    let options = CarrierOptions::new().selection(BitSelection::High);
+
+    let wav = fs::read(&paths[0]).unwrap();
+    assert_eq!(
+        carrier::from_async_reader(&wav[..], &paths[0], CarrierType::Wav, &options)
+            .await
+            .unwrap(),
+        carrier::from_file_with(&paths[0], &options).unwrap()
+    );
+
+    assert_eq!(
+        extraction::extract_async(&paths, passwords.clone(), &options)
+            .await
+            .unwrap(),
+        librepuff::extract(&paths, passwords.clone(), &options).unwrap()
+    );
+
+    // Carriers too small to hold anything are left out of the chain
+    let wavs = test_utils::hiding_wav_files(
+        &test_utils::embedded_file_bytes(b"secret.txt", b"secret content"),
+        &[],
+        2,
+        BitSelection::High,
+        &passwords,
+    );
+    let hiding_paths = [
+        directory.file("hiding_0.wav", &wavs[0]),
+        directory.file("tiny.wav", &test_utils::wav_file(&[0b1000; 100])),
+        directory.file("hiding_1.wav", &wavs[1]),
+    ];
+    let result = extraction::extract_async(&hiding_paths, passwords, &options)
+        .await
+        .unwrap();
+    let data = result.data.unwrap();
+    assert_eq!(data.filename, b"secret.txt");
+    assert_eq!(data.content, b"secret content");
+}
+
+#[tokio::test]
+async fn async_strict_trailing_data() {
+    let directory = TemporaryDirectory::new("async_strict_trailing_data");
+
+    let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+    let mut wav = wav_file(&samples);
+    wav.extend_from_slice(b"appended");
+    let path = directory.file("trailing_data.wav", &wav);
+
+    let lenient = CarrierOptions::new();
+    let strict = CarrierOptions::new().strict_trailing(true);
+    assert_eq!(
+        carrier::from_async_reader(&wav[..], &path, CarrierType::Wav, &lenient)
+            .await
+            .unwrap(),
+        carrier::from_file_with(&path, &lenient).unwrap()
+    );
+    assert!(matches!(
+        carrier::from_async_reader(&wav[..], &path, CarrierType::Wav, &strict).await,
+        Err(Error::TrailingData(error_path)) if error_path == path
+    ));
+
+    let passwords = Passwords::from_fields("firstpassword", None, None).unwrap();
+    assert!(matches!(
+        extraction::extract_async(std::slice::from_ref(&path), passwords, &strict).await,
+        Err(Error::TrailingData(error_path)) if error_path == path
+    ));
+}
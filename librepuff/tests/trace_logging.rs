@@ -0,0 +1,86 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Trace logging of the chain decryption.
+//!
+//! The logger is global to the process: this test lives in its own test binary, so that no other
+//! test logs through it, nor has to install a logger of its own.
+
+use std::sync::Mutex;
+
+use bit_vec::BitVec;
+use librepuff::carrier::EncryptedCarrier;
+use librepuff::chain;
+use librepuff::passwords::Passwords;
+
+/// Logger recording the messages logged.
+struct RecordingLogger(Mutex<Vec<String>>);
+
+impl log::Log for RecordingLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: RecordingLogger = RecordingLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn trace_logging() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let carriers = (0..2u8).map(|i| EncryptedCarrier {
+        iv: [i; 256],
+        data: vec![i.wrapping_mul(3); 512],
+        decoy: vec![i.wrapping_mul(5); 512],
+        other_bits: BitVec::new(),
+        warnings: Vec::new(),
+    });
+    let passwords = Passwords::from_fields("password", None, None).unwrap();
+    chain::decrypt_carrier_chain(carriers, passwords);
+
+    let lines = LOGGER.0.lock().unwrap();
+
+    let key_lines: Vec<&String> = lines
+        .iter()
+        .filter(|line| line.contains("prekey"))
+        .collect();
+    assert_eq!(key_lines.len(), 2);
+    let first_key = chain::derive_keys(1, &[])[0];
+    assert!(key_lines[0].starts_with(&format!(
+        "carrier #0: position 0, prekey 0000, key {first_key:08x}, decrypted IV starting with ["
+    )));
+    assert!(key_lines[1].starts_with("carrier #1: position 1, prekey "));
+    // The key of the second carrier depends on the decrypted IV of the first one
+    let second_key = key_lines[1]
+        .split_once(", key ")
+        .and_then(|(_, rest)| rest.get(..8))
+        .unwrap();
+
+    let content_lines: Vec<&String> = lines
+        .iter()
+        .filter(|line| line.contains("decrypted 512 data bytes and 512 decoy bytes"))
+        .collect();
+    assert_eq!(content_lines.len(), 2);
+    assert!(content_lines[0].starts_with(&format!("carrier with key {first_key:08x}")));
+    assert!(content_lines[1].starts_with(&format!("carrier with key {second_key}")));
+}
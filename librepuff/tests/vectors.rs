@@ -0,0 +1,128 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Known-answer tests, extracting files hidden by OpenPuff.
+//!
+//! Each subdirectory of `tests/vectors` is a vector, described by its `vector.txt` manifest. See
+//! `tests/vectors/README.md` for its format.
+
+#![cfg(feature = "fs")]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use librepuff::bit_selection::BitSelection;
+use librepuff::carrier;
+use librepuff::chain;
+use librepuff::crc32;
+use librepuff::embedded_file::EmbeddedFile;
+use librepuff::passwords::Passwords;
+
+/// Known-answer test vector, as described by a manifest.
+struct Vector {
+    directory: PathBuf,
+    fields: HashMap<String, String>,
+}
+
+impl Vector {
+    fn read(directory: &Path) -> Self {
+        let manifest = fs::read_to_string(directory.join("vector.txt")).unwrap();
+
+        let fields = manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (key, value) = line
+                    .split_once('=')
+                    .unwrap_or_else(|| panic!("{}: invalid line {line:?}", directory.display()));
+                (key.trim().to_string(), value.trim().to_string())
+            })
+            .collect();
+
+        Vector {
+            directory: directory.to_path_buf(),
+            fields,
+        }
+    }
+
+    fn field(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    fn required_field(&self, key: &str) -> &str {
+        self.field(key)
+            .unwrap_or_else(|| panic!("{}: missing {key:?}", self.directory.display()))
+    }
+
+    fn check(&self) {
+        let selection_level: BitSelection = self.required_field("bit_selection").parse().unwrap();
+        let carriers = self
+            .required_field("carriers")
+            .split_whitespace()
+            .map(|name| carrier::from_file(&self.directory.join(name), selection_level).unwrap());
+
+        let passwords = Passwords::from_fields(
+            self.required_field("password_a"),
+            self.field("password_b"),
+            self.field("password_c"),
+        )
+        .unwrap();
+
+        let embeddings = chain::decrypt_carrier_chain(carriers, passwords);
+        let (data, decoy) = chain::concatenate_embeddings(&embeddings);
+        let bits = match self.field("embedding") {
+            None | Some("data") => data,
+            Some("decoy") => decoy,
+            Some(embedding) => panic!("unknown embedding {embedding:?}"),
+        };
+
+        let file = EmbeddedFile::from_bits(&bits)
+            .unwrap_or_else(|| panic!("{}: no file found", self.directory.display()));
+
+        let expected_content =
+            fs::read(self.directory.join(self.required_field("content"))).unwrap();
+        let expected_crc32 =
+            u32::from_str_radix(self.required_field("crc32").trim_start_matches("0x"), 16).unwrap();
+
+        assert_eq!(file.filename, self.required_field("filename").as_bytes());
+        assert_eq!(file.content, expected_content);
+        assert_eq!(file.crc32, expected_crc32);
+        assert_eq!(crc32::compute(&expected_content), expected_crc32);
+    }
+}
+
+#[test]
+#[ignore = "no vector produced by OpenPuff is available yet, see tests/vectors/README.md"]
+fn known_answers() {
+    let vectors_directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+
+    let mut vector_directories: Vec<PathBuf> = fs::read_dir(vectors_directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("vector.txt").is_file())
+        .collect();
+    vector_directories.sort();
+    assert!(
+        !vector_directories.is_empty(),
+        "no vector found in tests/vectors"
+    );
+
+    for directory in vector_directories {
+        Vector::read(&directory).check();
+    }
+}
@@ -14,176 +14,2394 @@
 // You should have received a copy of the GNU General Public License
 // along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
 
-use clap::{Parser, ValueEnum};
-use librepuff::{carrier, chain, embedded_file::EmbeddedFile, passwords::Passwords};
-use log::{error, info, warn, LevelFilter};
-use std::path::PathBuf;
+mod project;
+mod report;
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use librepuff::bit_selection::BitSelection;
+use librepuff::carrier_type::CarrierType;
+use librepuff::embedded_file::{self, EmbeddedFile, FilenameEncoding};
+use librepuff::{carrier, chain, passwords::Passwords};
+use log::{debug, error, info, warn, LevelFilter};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use std::fs::File;
-use std::io::{self, Write};
+use std::fs::{self, File};
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal, Read, Write};
+use std::ops::Range;
+
+use project::Project;
+use report::{EmbeddingKind, ExtractionReport, FileReport, RangeReport};
+use serde::Deserialize;
 
 #[derive(Parser, Debug)]
 #[command(author, version, long_about = None)]
+#[command(group(
+    ArgGroup::new("password_a_source")
+        .args(["password_a", "password_a_file", "password_a_env"]),
+))]
+#[command(group(
+    ArgGroup::new("password_b_source")
+        .args(["password_b", "password_b_file", "password_b_env", "prompt_b"]),
+))]
+#[command(group(
+    ArgGroup::new("password_c_source")
+        .args(["password_c", "password_c_file", "password_c_env", "prompt_c"])
+        .requires("password_b_source"),
+))]
+#[command(group(
+    ArgGroup::new("carrier_source")
+        .args(["CARRIER", "carriers_from", "carrier_dir", "project"])
+        .required(true)
+        .multiple(true),
+))]
 struct Cli {
     /// Password A.
+    ///
+    /// If password A isn't specified by any mean, it is prompted for interactively.
     #[arg(short, long = "password", visible_alias = "password-a")]
-    password_a: String,
+    password_a: Option<String>,
+    /// Reads password A from a file.
+    #[arg(long, value_name = "FILE")]
+    password_a_file: Option<PathBuf>,
+    /// Reads password A from an environment variable.
+    #[arg(long, value_name = "VARIABLE")]
+    password_a_env: Option<String>,
+
     /// Password B.
-    #[arg(long, requires = "password_a")]
+    #[arg(long)]
     password_b: Option<String>,
+    /// Reads password B from a file.
+    #[arg(long, value_name = "FILE")]
+    password_b_file: Option<PathBuf>,
+    /// Reads password B from an environment variable.
+    #[arg(long, value_name = "VARIABLE")]
+    password_b_env: Option<String>,
+    /// Prompts for password B interactively.
+    #[arg(long)]
+    prompt_b: bool,
+
     /// Password C.
-    #[arg(long, requires = "password_b")]
+    #[arg(long)]
     password_c: Option<String>,
+    /// Reads password C from a file.
+    #[arg(long, value_name = "FILE")]
+    password_c_file: Option<PathBuf>,
+    /// Reads password C from an environment variable.
+    #[arg(long, value_name = "VARIABLE")]
+    password_c_env: Option<String>,
+    /// Prompts for password C interactively.
+    #[arg(long)]
+    prompt_c: bool,
 
     /// OpenPuff version compatibility.
+    ///
+    /// Files hidden by OpenPuff 4.00 and 4.01 are currently extracted the same way: this option
+    /// has no effect yet.
     #[arg(short = 'c', long = "compatibility")]
     #[arg(value_enum, default_value_t=VersionCompatibility::V4_01)]
     openpuff_version: VersionCompatibility,
 
+    /// Bit selection level the file was hidden with (e.g. `low` or `veryhigh`).
+    #[arg(long, default_value_t = BitSelection::default())]
+    bit_selection: BitSelection,
+
+    /// Tries every bit selection level, from `minimum` to `maximum`, until a file is extracted.
+    ///
+    /// The carriers are parsed again for each level, so this is slower than specifying the level.
+    #[arg(long, conflicts_with = "bit_selection")]
+    autodetect_selection: bool,
+
+    /// Writes the filler bits of each carrier, which OpenPuff ignores, to a directory.
+    ///
+    /// The bits are packed into bytes, and written to `<position>-<carrier name>.filler`.
+    #[arg(
+        long,
+        value_name = "DIRECTORY",
+        conflicts_with = "autodetect_selection"
+    )]
+    dump_filler: Option<PathBuf>,
+
+    /// Prints information about each carrier instead of extracting a file.
+    ///
+    /// One tab-separated line is printed per carrier: its path, its type, the number of bits
+    /// available in it, the number of data bits selected and whether it has trailing data.
+    #[arg(long)]
+    info: bool,
+
+    /// Checks whether OpenPuff would accept the carriers and passwords, instead of extracting a
+    /// file.
+    ///
+    /// Each issue found is printed on its own line, prefixed by its category (`passwords` or
+    /// `carriers`) and a tab. Fails if any issue is found.
+    #[arg(long, conflicts_with = "info")]
+    lint: bool,
+
+    /// Extracts the file(s) without writing them anywhere.
+    ///
+    /// Only reports the name, size and CRC32 of the recovered file(s), to check passwords and the
+    /// order of the carriers.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Refuses carriers having data past the end of their file format, which can hint at a
+    /// tampered or wrong file, instead of only warning about it.
+    ///
+    /// The carrier read from the standard input isn't checked.
+    #[arg(long)]
+    strict: bool,
+
+    /// Checks the SHA-512 integrity digest stored after each extracted file.
+    ///
+    /// This digest is an extension of OpenPuff's format: files hidden by OpenPuff don't have one,
+    /// and are refused with this option. Each digest only covers its own file: it doesn't bind the
+    /// data file to the decoy file, nor to the carriers it was hidden in.
+    #[arg(long)]
+    integrity: bool,
+
+    /// Outputs the decrypted data (or decoy) bytes as is, without looking for a file in them.
+    ///
+    /// For forensic analysis: the output is the bytes produced by the given passwords, even if
+    /// they're wrong, and is never checked. `--extract auto` outputs the data bytes.
+    #[arg(
+        long,
+        conflicts_with_all = ["integrity", "use_embedded_name", "autodetect_selection"]
+    )]
+    no_validate: bool,
+
+    /// Replaces carriers that don't exist with empty placeholders instead of failing.
+    ///
+    /// For forensic analysis, as OpenPuff can't extract anything without all the carriers: a
+    /// placeholder keeps the position of the missing carrier, but its IV is unknown, so what's
+    /// hidden in the carriers following it is decrypted as garbage. Only the data hidden before
+    /// the first missing carrier can be recovered, which `--no-validate` shows.
+    #[arg(long)]
+    zero_fill_missing: bool,
+
+    /// Refuses to output extracted files larger than this number of bytes.
+    ///
+    /// The size of a hidden file is only bounded by the capacity of the carriers.
+    #[arg(long, value_name = "BYTES")]
+    max_output_size: Option<usize>,
+
+    /// Only outputs the bytes of the extracted file(s) from offset `start` (included) to `end`
+    /// (excluded), e.g. `0:512` to preview the start of a large file.
+    ///
+    /// Either bound can be omitted (`1024:` or `:512`). The range must lie within the file.
+    #[arg(long, value_name = "START:END", value_parser = parse_byte_range)]
+    range: Option<ByteRange>,
+
     /// Specifies a filename where to output the extracted file.
     /// The special value `-` can be used to refer to the standard output.
     #[arg(short, long = "output", default_value_t=String::from("-"))]
     output: String,
 
+    /// Writes the extracted files under their embedded filename, in the directories given by
+    /// `--output` and `--decoy-output`.
+    ///
+    /// Directories and control characters are stripped from the embedded filenames, and existing
+    /// files are never overwritten.
+    #[arg(long)]
+    use_embedded_name: bool,
+
+    /// Writes extracted files to the standard output even if it is a terminal and they aren't
+    /// text, which is refused otherwise.
+    #[arg(long)]
+    force: bool,
+
+    /// File(s) to extract.
+    ///
+    /// `auto` extracts the data file, or the decoy file if no data file could be found.
+    #[arg(long, value_enum, default_value_t=ExtractMode::Auto)]
+    extract: ExtractMode,
+
+    /// Specifies a filename where to output the extracted decoy file, when extracting both files.
+    /// The special value `-` can be used to refer to the standard output.
+    #[arg(long, required_if_eq("extract", "both"))]
+    decoy_output: Option<String>,
+
+    /// Output format of the extraction result.
+    ///
+    /// With `json`, a report is printed on the standard output instead of log lines, so the
+    /// extracted file must be written to a file.
+    #[arg(long, value_enum, default_value_t=OutputFormat::Human)]
+    format: OutputFormat,
+
+    /// Logs more details. Can be repeated (`-vv`) to also log tracing messages.
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only logs errors.
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Format of the log lines, written on the standard error.
+    ///
+    /// With `json`, each line is an object with `level`, `target` and `message` fields. The
+    /// `RUST_LOG` environment variable, if set, overrides the level chosen by `--verbose` and
+    /// `--quiet`.
+    #[arg(long, value_enum, default_value_t=LogFormat::Human)]
+    log_format: LogFormat,
+
+    /// Encoding of the extracted filenames, when logging them (e.g. `utf-8`, `windows-1252` or
+    /// `shift_jis`).
+    ///
+    /// Files hidden on Windows have their filename encoded using the system's code page.
+    #[arg(long, value_parser = parse_filename_encoding, default_value = "utf-8")]
+    filename_encoding: FilenameEncoding,
+
+    /// Type of the carrier read from the standard input, as a name or a file extension (e.g.
+    /// `wav`, `jpeg` or `jpg`).
+    ///
+    /// When a single carrier is given, it is parsed as this type whatever its extension.
+    #[arg(long, value_parser = parse_carrier_type)]
+    carrier_type: Option<CarrierType>,
+
+    /// Types of the carriers, separated by commas and in the order of the carriers (e.g.
+    /// `wav,jpg,wav`), overriding their extensions.
+    #[arg(
+        long,
+        value_parser = parse_carrier_type,
+        value_delimiter = ',',
+        conflicts_with = "carrier_type"
+    )]
+    carrier_types: Vec<CarrierType>,
+
     /// Carrier(s) to unhide a file from.
     ///
     /// The ordering of the carriers matters.
-    #[arg(required = true)]
+    /// The special value `-` can be used to read a carrier from the standard input, in which case
+    /// its type must be specified using `--carrier-type`.
     #[clap(name = "CARRIER")]
     carriers: Vec<PathBuf>,
+
+    /// Reads the carriers from a file listing one path per line, in hiding order.
+    ///
+    /// Empty lines are ignored. Relative paths are relative to the working directory, not to the
+    /// listing.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["CARRIER", "carrier_dir"])]
+    carriers_from: Option<PathBuf>,
+
+    /// Uses every file of a directory as a carrier, sorted by file name.
+    ///
+    /// File names are compared byte by byte (so `B.wav` comes before `a.wav`, and `10.wav` before
+    /// `9.wav`): they must be named so this order is the hiding order. Subdirectories are ignored.
+    #[arg(long, value_name = "DIRECTORY", conflicts_with = "CARRIER")]
+    carrier_dir: Option<PathBuf>,
+
+    /// Hiding order of the carriers, as a permutation of their positions separated by commas.
+    ///
+    /// Positions start at 1: with `--carrier-order 3,1,2`, the third carrier given is the first
+    /// one the file was hidden in. This applies to carriers from any source, and
+    /// `--carrier-types` is then given in hiding order.
+    #[arg(long, value_name = "INDICES", value_delimiter = ',')]
+    carrier_order: Vec<usize>,
+
+    /// Reads the carriers, bit selection level, compatibility version and output from a TOML
+    /// project file, or a JSON one if its extension is `.json`. Relative carrier paths are
+    /// relative to the directory of the project file.
+    ///
+    /// Command-line arguments override the settings of the project, e.g.:
+    ///
+    /// carriers = ["first.wav", "second.wav"]
+    /// bit_selection = "high"
+    /// version = "v4.01"
+    /// output = "secret.txt"
+    #[arg(long, value_name = "FILE")]
+    project: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, Deserialize)]
 enum VersionCompatibility {
     #[clap(name = "v4.00")]
+    #[serde(rename = "v4.00")]
     V4_00,
 
     #[clap(name = "v4.01")]
+    #[serde(rename = "v4.01")]
     V4_01,
 }
 
-fn is_there_duplicate_paths(paths: &[PathBuf]) -> bool {
-    for i in 1..paths.len() {
-        for j in 0..i {
-            if paths[i] == paths[j] {
-                return true;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExtractMode {
+    Auto,
+    Data,
+    Decoy,
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Human,
+    Json,
+}
+
+/// Returns the log level requested by the command-line arguments.
+fn log_level(cli: &Cli) -> LevelFilter {
+    // The JSON report replaces log lines.
+    if cli.format == OutputFormat::Json {
+        return LevelFilter::Off;
+    }
+
+    if cli.quiet {
+        return LevelFilter::Error;
+    }
+
+    match cli.verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Sets up the logger. `RUST_LOG` takes precedence over `level`.
+fn init_logger(level: LevelFilter, format: LogFormat) {
+    let mut builder = pretty_env_logger::formatted_builder();
+    builder.filter_level(level);
+
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{line}")
+        });
+    }
+
+    builder.init();
+}
+
+fn parse_carrier_type(name: &str) -> Result<CarrierType, String> {
+    name.parse()
+        .ok()
+        .or_else(|| CarrierType::from_extension(name))
+        .ok_or_else(|| format!("unknown carrier type '{name}'"))
+}
+
+/// Range of bytes to output, given by `--range`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ByteRange {
+    start: usize,
+    /// End of the range, excluded. `None` for the end of the file.
+    end: Option<usize>,
+}
+
+fn parse_byte_range(range: &str) -> Result<ByteRange, String> {
+    let invalid = || format!("invalid range '{range}', expected START:END");
+
+    let (start, end) = range.split_once(':').ok_or_else(invalid)?;
+    let parse_bound = |bound: &str| -> Result<Option<usize>, String> {
+        if bound.is_empty() {
+            Ok(None)
+        } else {
+            bound.parse().map(Some).map_err(|_| invalid())
+        }
+    };
+
+    let range = ByteRange {
+        start: parse_bound(start)?.unwrap_or(0),
+        end: parse_bound(end)?,
+    };
+    if range.end.is_some_and(|end| end < range.start) {
+        return Err(format!(
+            "invalid range '{start}:{end}', its end precedes its start"
+        ));
+    }
+
+    Ok(range)
+}
+
+/// Keeps the bytes of `range` in the content of `file`, recording their offsets in `file.range`.
+fn apply_range(file: &mut ExtractedFile, range: ByteRange) -> Result<(), String> {
+    let end = range.end.unwrap_or(file.content.len());
+    if end > file.content.len() || range.start > end {
+        return Err(format!(
+            "the range {}:{end} lies outside of the {} file, which is {} bytes long.",
+            range.start,
+            file.kind,
+            file.content.len()
+        ));
+    }
+
+    file.content.truncate(end);
+    file.content.drain(..range.start);
+    file.range = Some(range.start..end);
+    Ok(())
+}
+
+fn parse_filename_encoding(name: &str) -> Result<FilenameEncoding, String> {
+    name.parse().map_err(|_| {
+        let names: Vec<String> = FilenameEncoding::all().map(|e| e.to_string()).collect();
+        format!(
+            "unknown filename encoding '{name}' (supported: {})",
+            names.join(", ")
+        )
+    })
+}
+
+/// Whether `path` refers to the standard input.
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// A carrier read from the standard input.
+///
+/// It is kept in memory, as the standard input can only be read once.
+struct StdinCarrier {
+    file_type: CarrierType,
+    bytes: Vec<u8>,
+}
+
+/// Reads the carrier given on the standard input, whose type is `stdin_type`.
+fn read_stdin_carrier(stdin_type: Option<CarrierType>) -> Result<StdinCarrier, String> {
+    let file_type = stdin_type.ok_or_else(|| {
+        String::from("the type of a carrier read from the standard input must be specified using --carrier-type or --carrier-types.")
+    })?;
+
+    let mut bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut bytes)
+        .map_err(|err| format!("could not read the standard input: {err}."))?;
+
+    Ok(StdinCarrier { file_type, bytes })
+}
+
+/// Returns how to refer to the carrier at `path` in messages.
+fn carrier_name(path: &Path) -> String {
+    if is_stdin(path) {
+        String::from("the standard input")
+    } else {
+        path.display().to_string()
+    }
+}
+
+/// Detects the type of a carrier, unless it is given by `forced_type`, and parses it.
+///
+/// `stdin` must hold the content of the standard input if `path` refers to it.
+fn read_carrier(
+    path: &Path,
+    forced_type: Option<CarrierType>,
+    stdin: Option<&StdinCarrier>,
+    selection_level: BitSelection,
+    strict_trailing: bool,
+) -> Result<(CarrierType, carrier::EncryptedCarrier), librepuff::Error> {
+    if is_stdin(path) {
+        let stdin = stdin.expect("the standard input should have been read");
+
+        let carrier = carrier::from_bytes(&stdin.bytes, stdin.file_type, selection_level)?;
+
+        return Ok((stdin.file_type, carrier));
+    }
+
+    let options = carrier::CarrierOptions::new()
+        .selection(selection_level)
+        .strict_trailing(strict_trailing);
+    let file_type = forced_type.map_or_else(|| carrier::file_type_from_path(path), Ok)?;
+    Ok((file_type, carrier::from_file_as(path, file_type, &options)?))
+}
+
+/// Reads the list of carrier paths in `listing`, one per line.
+fn read_carrier_listing(listing: &Path) -> Result<Vec<PathBuf>, String> {
+    let content = fs::read_to_string(listing)
+        .map_err(|err| format!("could not read {}: {err}.", listing.display()))?;
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Lists the files of `directory`, sorted by file name.
+fn read_carrier_directory(directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let error = |err: io::Error| format!("could not read {}: {err}.", directory.display());
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory).map_err(error)? {
+        let entry = entry.map_err(error)?;
+        if entry.file_type().map_err(error)?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    Ok(paths)
+}
+
+/// Fills in the settings of `cli` which weren't given on the command line from `project`.
+///
+/// `matches` must be the matches `cli` was created from.
+fn apply_project(cli: &mut Cli, matches: &ArgMatches, project: Project) {
+    let from_command_line = |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    if cli.carriers.is_empty() && cli.carriers_from.is_none() && cli.carrier_dir.is_none() {
+        cli.carriers = project.carriers;
+    }
+    if let Some(bit_selection) = project.bit_selection {
+        if !from_command_line("bit_selection") {
+            cli.bit_selection = bit_selection;
+        }
+    }
+    if let Some(version) = project.version {
+        if !from_command_line("openpuff_version") {
+            cli.openpuff_version = version;
+        }
+    }
+    if let Some(output) = project.output {
+        if !from_command_line("output") {
+            cli.output = output;
+        }
+    }
+}
+
+/// Returns the paths of the carriers, in hiding order.
+fn carrier_paths(cli: &Cli) -> Result<Vec<PathBuf>, String> {
+    let paths = if let Some(listing) = &cli.carriers_from {
+        read_carrier_listing(listing)?
+    } else if let Some(directory) = &cli.carrier_dir {
+        read_carrier_directory(directory)?
+    } else {
+        cli.carriers.clone()
+    };
+
+    if cli.carrier_order.is_empty() {
+        Ok(paths)
+    } else {
+        reorder_carriers(paths, &cli.carrier_order)
+    }
+}
+
+/// Reorders `paths` according to `--carrier-order`: the carrier at 1-based position `order[i]`
+/// ends up at position `i`.
+fn reorder_carriers(paths: Vec<PathBuf>, order: &[usize]) -> Result<Vec<PathBuf>, String> {
+    if order.len() != paths.len() {
+        return Err(format!(
+            "the carrier order lists {} positions for {} carriers.",
+            order.len(),
+            paths.len()
+        ));
+    }
+
+    let count = paths.len();
+    let mut paths: Vec<Option<PathBuf>> = paths.into_iter().map(Some).collect();
+    order
+        .iter()
+        .map(|&position| {
+            position
+                .checked_sub(1)
+                .and_then(|i| paths.get_mut(i))
+                .and_then(Option::take)
+                .ok_or_else(|| {
+                    format!("invalid or repeated position {position} in the carrier order (1 to {count}).")
+                })
+        })
+        .collect()
+}
+
+/// Returns the type forced for each carrier of `carrier_paths`, if any.
+///
+/// `--carrier-type` applies to the carrier read from the standard input, or to the only carrier.
+fn carrier_type_overrides(
+    cli: &Cli,
+    carrier_paths: &[PathBuf],
+) -> Result<Vec<Option<CarrierType>>, String> {
+    if !cli.carrier_types.is_empty() {
+        if cli.carrier_types.len() != carrier_paths.len() {
+            return Err(format!(
+                "{} carrier types given for {} carriers.",
+                cli.carrier_types.len(),
+                carrier_paths.len()
+            ));
+        }
+
+        return Ok(cli.carrier_types.iter().copied().map(Some).collect());
+    }
+
+    Ok(carrier_paths
+        .iter()
+        .map(|path| {
+            if carrier_paths.len() == 1 || is_stdin(path) {
+                cli.carrier_type
+            } else {
+                None
             }
+        })
+        .collect())
+}
+
+/// Removes a single trailing newline (`\n` or `\r\n`) from `password`.
+fn trim_trailing_newline(password: &mut String) {
+    if password.ends_with('\n') {
+        password.pop();
+        if password.ends_with('\r') {
+            password.pop();
         }
     }
+}
+
+/// Returns a password given on the command line, read from a file, or read from an environment
+/// variable. At most one of these sources is expected to be specified.
+fn read_password(
+    inline: Option<String>,
+    file: Option<&Path>,
+    variable: Option<&str>,
+) -> Result<Option<String>, String> {
+    let mut password = if let Some(path) = file {
+        fs::read_to_string(path)
+            .map_err(|err| format!("could not read password from {}: {err}", path.display()))?
+    } else if let Some(variable) = variable {
+        env::var(variable).map_err(|err| {
+            format!("could not read password from environment variable {variable}: {err}")
+        })?
+    } else {
+        return Ok(inline);
+    };
+
+    trim_trailing_newline(&mut password);
+    Ok(Some(password))
+}
+
+/// Prompts for password `name` on the terminal, without echoing it.
+fn prompt_password(name: &str) -> Result<String, String> {
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "password {name} must be specified, as standard input is not a terminal"
+        ));
+    }
 
-    false
+    rpassword::prompt_password(format!("Password {name}: "))
+        .map_err(|err| format!("could not read password {name}: {err}"))
 }
 
-fn output_extracted_file(content: &[u8], destination: &str) {
+/// Formats information about a carrier, as printed by `--info`.
+fn format_carrier_info(path: &Path, info: &carrier::CarrierInfo) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        path.display(),
+        info.file_type,
+        info.available_bit_count,
+        info.selected_bit_count,
+        if info.has_trailing_data {
+            "trailing-data"
+        } else {
+            "no-trailing-data"
+        }
+    )
+}
+
+/// Prints information about each carrier, returning whether all of them could be parsed.
+///
+/// `forced_types` holds the type forced for each carrier, if any.
+fn print_carriers_info(
+    paths: &[PathBuf],
+    forced_types: &[Option<CarrierType>],
+    selection_level: BitSelection,
+) -> bool {
+    let mut success = true;
+
+    for (path, forced_type) in paths.iter().zip(forced_types) {
+        let info = match forced_type {
+            Some(file_type) => carrier::info_from_file_as(path, *file_type, selection_level),
+            None => carrier::info_from_file(path, selection_level),
+        };
+        match info {
+            Ok(info) => {
+                for warning in &info.warnings {
+                    warn!("{}: {warning}.", path.display());
+                }
+                println!("{}", format_carrier_info(path, &info));
+            }
+            Err(err) => {
+                error!("could not parse {}: {err}.", path.display());
+                success = false;
+            }
+        }
+    }
+
+    success
+}
+
+/// Returns whether writing `content` to `destination` must be refused, as it would dump binary
+/// data (anything that isn't valid UTF-8) on a terminal, unless `force` is set.
+fn refuses_binary_output(
+    content: &[u8],
+    destination: &str,
+    stdout_is_terminal: bool,
+    force: bool,
+) -> bool {
+    destination == "-" && stdout_is_terminal && !force && std::str::from_utf8(content).is_err()
+}
+
+fn output_extracted_file(content: &[u8], destination: &str) -> io::Result<()> {
     if destination == "-" {
         let mut stdout = io::stdout();
-        stdout.write_all(content).unwrap();
+        stdout.write_all(content)?;
+        stdout.flush()
     } else {
-        let mut file = File::create(destination).unwrap();
-        file.write_all(content).unwrap();
+        let mut file = File::create(destination)?;
+        file.write_all(content)
+    }
+}
+
+/// Writes `file` in `directory`, under its sanitized embedded filename.
+fn write_under_embedded_name(
+    file: &ExtractedFile,
+    directory: &Path,
+    encoding: FilenameEncoding,
+) -> Result<(), String> {
+    if is_stdin(directory) {
+        return Err(String::from(
+            "an output directory must be specified when using the embedded filenames.",
+        ));
+    }
+
+    let filename = embedded_file::sanitize_filename(&file.filename, encoding).ok_or_else(|| {
+        format!(
+            "the embedded filename of the {} file is unusable.",
+            file.kind
+        )
+    })?;
+    let path = directory.join(filename);
+
+    File::create_new(&path)
+        .and_then(|mut output| output.write_all(&file.content))
+        .map_err(|err| format!("could not write {}: {err}.", path.display()))
+}
+
+/// Checks that the extracted files can be written where requested, before extracting anything.
+fn check_outputs(cli: &Cli) -> Result<(), String> {
+    if cli.dry_run {
+        return Ok(());
+    }
+
+    let decoy_to_stdout = cli
+        .decoy_output
+        .as_ref()
+        .is_some_and(|output| output == "-");
+    if cli.format == OutputFormat::Json && (cli.output == "-" || decoy_to_stdout) {
+        return Err(String::from(
+            "the JSON report is printed on the standard output, an output file must be specified.",
+        ));
+    }
+    if cli.extract == ExtractMode::Both && cli.output == "-" && decoy_to_stdout {
+        return Err(String::from(
+            "the data and decoy files can't both be written to the standard output.",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns where the extracted `kind` file is written: a path, a directory with
+/// `--use-embedded-name`, or `-` for the standard output.
+fn destination(cli: &Cli, kind: EmbeddingKind) -> &str {
+    match (cli.extract, kind) {
+        (ExtractMode::Both, EmbeddingKind::Decoy) => cli.decoy_output.as_ref().unwrap(),
+        _ => &cli.output,
+    }
+}
+
+/// Writes the extracted files to their destination, or only reports them in a dry run.
+///
+/// Nothing is written if any of the files can't be: too large, or binary and written to the
+/// terminal (`stdout_is_terminal`).
+fn write_extracted_files(
+    cli: &Cli,
+    extraction: &Extraction,
+    stdout_is_terminal: bool,
+) -> Result<(), String> {
+    if let Some(limit) = cli.max_output_size {
+        for file in &extraction.files {
+            librepuff::extraction::check_output_size(file.content.len(), limit)
+                .map_err(|err| format!("{err}, not writing the {} file.", file.kind))?;
+        }
+    }
+
+    if cli.dry_run {
+        for file in &extraction.files {
+            if let (true, Some(range)) = (file.validated, &file.range) {
+                info!(
+                    "dry run: bytes {}:{} of the {} file, whose CRC32 ({:08x}) is valid, not written",
+                    range.start, range.end, file.kind, file.crc32
+                );
+            } else if file.validated {
+                info!(
+                    "dry run: {} file of {} bytes with a valid CRC32 ({:08x}) not written",
+                    file.kind,
+                    file.content.len(),
+                    file.crc32
+                );
+            } else {
+                info!(
+                    "dry run: {} unvalidated bytes of the {} embedding not written",
+                    file.content.len(),
+                    file.kind
+                );
+            }
+        }
+
+        return Ok(());
+    }
+
+    if !cli.use_embedded_name {
+        for file in &extraction.files {
+            if refuses_binary_output(
+                &file.content,
+                destination(cli, file.kind),
+                stdout_is_terminal,
+                cli.force,
+            ) {
+                return Err(format!(
+                    "the {} file is binary, not writing it to the terminal: specify an output file with --output, redirect the standard output, or use --force.",
+                    file.kind
+                ));
+            }
+        }
+    }
+
+    for file in &extraction.files {
+        let destination = destination(cli, file.kind);
+
+        if cli.use_embedded_name {
+            write_under_embedded_name(file, Path::new(destination), cli.filename_encoding)?;
+            continue;
+        }
+
+        output_extracted_file(&file.content, destination).map_err(|err| {
+            if destination == "-" {
+                format!("could not write to the standard output: {err}.")
+            } else {
+                format!("could not write {destination}: {err}.")
+            }
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Reads the passwords given on the command line, prompting for the ones not specified otherwise.
+fn read_passwords(cli: &Cli) -> Result<(String, Option<String>, Option<String>), String> {
+    let password_a = read_password(
+        cli.password_a.clone(),
+        cli.password_a_file.as_deref(),
+        cli.password_a_env.as_deref(),
+    )?;
+    let password_b = read_password(
+        cli.password_b.clone(),
+        cli.password_b_file.as_deref(),
+        cli.password_b_env.as_deref(),
+    )?;
+    let password_c = read_password(
+        cli.password_c.clone(),
+        cli.password_c_file.as_deref(),
+        cli.password_c_env.as_deref(),
+    )?;
+
+    let password_a = match password_a {
+        Some(password_a) => password_a,
+        None => prompt_password("A")?,
+    };
+    let password_b = match password_b {
+        None if cli.prompt_b => Some(prompt_password("B")?),
+        password_b => password_b,
+    };
+    let password_c = match password_c {
+        None if cli.prompt_c => Some(prompt_password("C")?),
+        password_c => password_c,
     };
 
+    Ok((password_a, password_b, password_c))
 }
 
-fn main() -> ExitCode {
-    pretty_env_logger::formatted_builder()
-        .filter_level(LevelFilter::Debug)
-        .init();
+/// A file extracted from a set of carriers.
+struct ExtractedFile {
+    kind: EmbeddingKind,
+    filename: Vec<u8>,
+    content: Vec<u8>,
+    crc32: u32,
+    /// Whether the file was found with a valid header and CRC32, or is raw decrypted bytes.
+    validated: bool,
+    /// Offsets in the file of the bytes `content` is restricted to by `--range`. `crc32` is still
+    /// the CRC32 of the whole file.
+    range: Option<Range<usize>>,
+}
 
-    // Parses command-line arguments.
-    let cli = Cli::parse();
+/// Files extracted from a set of carriers.
+struct Extraction {
+    /// Type of each carrier of the chain, `None` for the placeholders of missing carriers.
+    carrier_types: Vec<Option<CarrierType>>,
+    files: Vec<ExtractedFile>,
+}
 
-    // Creates passwords.
-    let passwords = match Passwords::from_fields(
-        cli.password_a.as_ref(),
-        cli.password_b.as_ref().map(|b| b.as_str()),
-        cli.password_c.as_ref().map(|c| c.as_str()),
-    ) {
-        Err(e) => {
-            error!("{e}");
-            return ExitCode::FAILURE;
+/// Extracts the file(s) requested by `mode` out of the decrypted embeddings.
+///
+/// If `integrity` is set, the integrity digest of the files is checked too (see
+/// `EmbeddedFile::verify_integrity`).
+fn select_files(
+    mode: ExtractMode,
+    data_embedding: &[u8],
+    decoy_embedding: &[u8],
+    filename_encoding: FilenameEncoding,
+    integrity: bool,
+) -> Result<Vec<ExtractedFile>, String> {
+    // `None` if no file could be found, an error if its integrity couldn't be verified.
+    let extract_file = |kind: EmbeddingKind| {
+        let embedding = match kind {
+            EmbeddingKind::Data => data_embedding,
+            EmbeddingKind::Decoy => decoy_embedding,
+        };
+
+        EmbeddedFile::from_bits(embedding).map(|file| {
+            if integrity && !file.verify_integrity() {
+                return Err(format!(
+                    "the {kind} file '{}' was found, but its integrity digest doesn't match.",
+                    file.filename_str(filename_encoding)
+                ));
+            }
+
+            info!(
+                "sucessfully extracted {kind} file: '{}'",
+                file.filename_str(filename_encoding)
+            );
+
+            Ok(ExtractedFile {
+                kind,
+                filename: file.filename.to_vec(),
+                content: file.content.to_vec(),
+                crc32: file.crc32,
+                validated: true,
+                range: None,
+            })
+        })
+    };
+    let missing_file = |kind| format!("could not extract a {kind} file using the given passwords.");
+
+    match mode {
+        ExtractMode::Auto => extract_file(EmbeddingKind::Data)
+            .or_else(|| extract_file(EmbeddingKind::Decoy))
+            .ok_or_else(|| {
+                String::from("could not extract a data or decoy file using the given passwords.")
+            })?
+            .map(|file| vec![file]),
+        ExtractMode::Data => extract_file(EmbeddingKind::Data)
+            .ok_or_else(|| missing_file(EmbeddingKind::Data))?
+            .map(|file| vec![file]),
+        ExtractMode::Decoy => extract_file(EmbeddingKind::Decoy)
+            .ok_or_else(|| missing_file(EmbeddingKind::Decoy))?
+            .map(|file| vec![file]),
+        ExtractMode::Both => {
+            let data_file = extract_file(EmbeddingKind::Data)
+                .ok_or_else(|| missing_file(EmbeddingKind::Data))??;
+            let decoy_file = extract_file(EmbeddingKind::Decoy)
+                .ok_or_else(|| missing_file(EmbeddingKind::Decoy))??;
+
+            Ok(vec![data_file, decoy_file])
         }
-        Ok(passwords) => passwords,
+    }
+}
+
+/// Returns the decrypted embedding(s) requested by `mode` as is, for `--no-validate`.
+///
+/// `ExtractMode::Auto` selects the data embedding. The CRC32 of the files is computed from their
+/// content.
+fn raw_embeddings(
+    mode: ExtractMode,
+    data_embedding: &[u8],
+    decoy_embedding: &[u8],
+) -> Vec<ExtractedFile> {
+    let kinds: &[EmbeddingKind] = match mode {
+        ExtractMode::Auto | ExtractMode::Data => &[EmbeddingKind::Data],
+        ExtractMode::Decoy => &[EmbeddingKind::Decoy],
+        ExtractMode::Both => &[EmbeddingKind::Data, EmbeddingKind::Decoy],
     };
 
-    if is_there_duplicate_paths(&cli.carriers) {
-        warn!("duplicate carriers used, OpenPuff would complain.");
+    kinds
+        .iter()
+        .map(|&kind| {
+            let content = match kind {
+                EmbeddingKind::Data => data_embedding,
+                EmbeddingKind::Decoy => decoy_embedding,
+            };
+            warn!(
+                "outputting the {} bytes of the {kind} embedding without validation: they may not hold any file.",
+                content.len()
+            );
+
+            ExtractedFile {
+                kind,
+                filename: Vec::new(),
+                content: content.to_vec(),
+                crc32: librepuff::crc32::compute(content),
+                validated: false,
+                range: None,
+            }
+        })
+        .collect()
+}
+
+/// Inserts placeholders (`None`) for missing carriers among `carriers`, at the positions
+/// `missing_positions` of the resulting chain, given in increasing order.
+fn with_placeholders<T>(carriers: Vec<T>, missing_positions: &[usize]) -> Vec<Option<T>> {
+    let mut chain: Vec<Option<T>> = carriers.into_iter().map(Some).collect();
+    for &position in missing_positions {
+        chain.insert(position, None);
     }
 
+    chain
+}
+
+/// Carriers given on the command line.
+struct CarrierSources {
+    paths: Vec<PathBuf>,
+    /// Type forced for each carrier, if any.
+    forced_types: Vec<Option<CarrierType>>,
+    /// Carrier read from the standard input, if any.
+    stdin: Option<StdinCarrier>,
+}
+
+/// Returns the carriers given on the command line, reading the standard input if it is one.
+fn read_carrier_sources(cli: &Cli) -> Result<CarrierSources, String> {
+    let carrier_paths = carrier_paths(cli)?;
+    if carrier_paths.is_empty() {
+        return Err(String::from("no carrier specified."));
+    }
+
+    if carrier_paths.iter().filter(|path| is_stdin(path)).count() > 1 {
+        return Err(String::from(
+            "the standard input can only be used for one carrier.",
+        ));
+    }
+
+    let forced_types = carrier_type_overrides(cli, &carrier_paths)?;
+
+    let stdin = match carrier_paths.iter().position(|path| is_stdin(path)) {
+        Some(i) => Some(read_stdin_carrier(forced_types[i])?),
+        None => None,
+    };
+
+    Ok(CarrierSources {
+        paths: carrier_paths,
+        forced_types,
+        stdin,
+    })
+}
+
+/// Category of an issue found by `--lint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LintCategory {
+    Passwords,
+    Carriers,
+}
+
+impl fmt::Display for LintCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passwords => write!(f, "passwords"),
+            Self::Carriers => write!(f, "carriers"),
+        }
+    }
+}
+
+/// Reason why OpenPuff wouldn't accept the carriers or passwords, found by `--lint`.
+#[derive(Debug, PartialEq, Eq)]
+struct LintIssue {
+    category: LintCategory,
+    message: String,
+}
+
+impl LintIssue {
+    fn new(category: LintCategory, message: impl fmt::Display) -> Self {
+        // Messages are printed on their own, without a final period.
+        let message = message.to_string().trim_end_matches('.').to_owned();
+        LintIssue { category, message }
+    }
+}
+
+/// Runs the checks OpenPuff does on the carriers and passwords, without decrypting anything.
+fn lint(cli: &Cli) -> Result<Vec<LintIssue>, String> {
+    let (password_a, password_b, password_c) = read_passwords(cli)?;
+    let mut issues: Vec<LintIssue> =
+        Passwords::analyze(&password_a, password_b.as_deref(), password_c.as_deref())
+            .into_iter()
+            .map(|warning| LintIssue::new(LintCategory::Passwords, warning))
+            .collect();
+
+    let carriers = read_carrier_sources(cli)?;
+    issues.extend(lint_carriers(
+        &carriers.paths,
+        &carriers.forced_types,
+        carriers.stdin.as_ref(),
+        cli.bit_selection,
+    ));
+
+    Ok(issues)
+}
+
+/// Checks the carriers at `carrier_paths` the way OpenPuff does when adding them to a chain.
+fn lint_carriers(
+    carrier_paths: &[PathBuf],
+    forced_types: &[Option<CarrierType>],
+    stdin: Option<&StdinCarrier>,
+    selection_level: BitSelection,
+) -> Vec<LintIssue> {
+    let issue = |message| LintIssue::new(LintCategory::Carriers, message);
+    let mut issues = Vec::new();
+
+    for (j, path) in carrier_paths.iter().enumerate() {
+        if !is_stdin(path) && carrier_paths[..j].contains(path) {
+            issues.push(issue(format!("{} is given more than once", path.display())));
+        }
+    }
+
+    let mut used_paths = Vec::new();
+    let mut carriers = Vec::new();
+    for (path, forced_type) in carrier_paths.iter().zip(forced_types) {
+        match read_carrier(path, *forced_type, stdin, selection_level, false) {
+            Ok((_, carrier)) => {
+                used_paths.push(path);
+                carriers.push(carrier);
+            }
+            Err(err) => issues.push(issue(format!("{}: {err}", carrier_name(path)))),
+        }
+    }
+
+    let preflight = chain::preflight(&carriers);
+    for &(i, j) in &preflight.duplicates {
+        if used_paths[i] != used_paths[j] {
+            issues.push(issue(format!(
+                "{} and {} are the same carrier",
+                used_paths[i].display(),
+                used_paths[j].display()
+            )));
+        }
+    }
+    if preflight.too_many_carriers {
+        issues.push(issue(format!(
+            "{} or more carriers are used",
+            chain::MAX_CARRIER_COUNT
+        )));
+    }
+    if preflight.too_many_bits {
+        issues.push(issue(librepuff::Error::TooManyBits.to_string()));
+    }
+
+    issues
+}
+
+fn extract(cli: &Cli) -> Result<Extraction, String> {
+    // Reads passwords.
+    let (password_a, password_b, password_c) = read_passwords(cli)?;
+
+    // Creates passwords.
+    let passwords =
+        Passwords::from_fields(&password_a, password_b.as_deref(), password_c.as_deref())
+            .map_err(|e| e.to_string())?;
+    for warning in Passwords::analyze(&password_a, password_b.as_deref(), password_c.as_deref()) {
+        warn!("{warning}");
+    }
+
+    let CarrierSources {
+        paths: carrier_paths,
+        forced_types,
+        stdin,
+    } = read_carrier_sources(cli)?;
+
+    if !cli.autodetect_selection {
+        return extract_at_level(
+            cli,
+            &carrier_paths,
+            &forced_types,
+            stdin.as_ref(),
+            passwords,
+            cli.bit_selection,
+            true,
+        );
+    }
+
+    let first_level = BitSelection::all()[0];
+    let (level, extraction) = first_successful_level(BitSelection::all(), |level| {
+        debug!("trying the {level} bit selection level");
+        extract_at_level(
+            cli,
+            &carrier_paths,
+            &forced_types,
+            stdin.as_ref(),
+            passwords.clone(),
+            level,
+            level == first_level,
+        )
+    })?;
+    info!("the file was hidden using the {level} bit selection level.");
+
+    Ok(extraction)
+}
+
+/// Calls `attempt` with each level of `levels` until it succeeds, returning the level it
+/// succeeded with.
+fn first_successful_level<T>(
+    levels: impl IntoIterator<Item = BitSelection>,
+    mut attempt: impl FnMut(BitSelection) -> Result<T, String>,
+) -> Result<(BitSelection, T), String> {
+    for level in levels {
+        match attempt(level) {
+            Ok(result) => return Ok((level, result)),
+            Err(err) => debug!("{level} bit selection level: {err}"),
+        }
+    }
+
+    Err(String::from(
+        "could not extract a file at any bit selection level using the given passwords.",
+    ))
+}
+
+/// Writes the filler bits of `carriers`, read from `carrier_paths`, to `directory`.
+fn dump_filler(
+    directory: &Path,
+    carrier_paths: &[PathBuf],
+    carriers: &[carrier::EncryptedCarrier],
+) -> Result<(), String> {
+    for (i, (path, carrier)) in carrier_paths.iter().zip(carriers).enumerate() {
+        let name = match path.file_name() {
+            Some(name) if !is_stdin(path) => name.to_string_lossy(),
+            _ => "stdin".into(),
+        };
+        let filler_path = directory.join(format!("{i}-{name}.filler"));
+
+        fs::write(&filler_path, carrier.filler_bytes())
+            .map_err(|err| format!("could not write {}: {err}.", filler_path.display()))?;
+        debug!(
+            "wrote {} filler bits of {} to {}",
+            carrier.other_bits.len(),
+            path.display(),
+            filler_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Extracts the file(s) hidden in the carriers at `carrier_paths`, using the bit selection level
+/// `selection_level`.
+///
+/// Warnings about the carriers are only logged if `report_warnings` is set, so they're reported
+/// once when trying several levels.
+fn extract_at_level(
+    cli: &Cli,
+    carrier_paths: &[PathBuf],
+    forced_types: &[Option<CarrierType>],
+    stdin: Option<&StdinCarrier>,
+    passwords: Passwords,
+    selection_level: BitSelection,
+    report_warnings: bool,
+) -> Result<Extraction, String> {
     // Reads carriers.
+    let mut used_paths = Vec::new();
+    let mut carrier_types = Vec::new();
     let mut carriers = Vec::new();
-    for path in cli.carriers {
-        let carrier = match carrier::from_file(&path, Default::default()) {
+    // Positions in the chain of the carriers replaced with placeholders.
+    let mut missing_positions = Vec::new();
+    for (path, forced_type) in carrier_paths.iter().zip(forced_types) {
+        let (file_type, carrier) = match read_carrier(
+            path,
+            *forced_type,
+            stdin,
+            selection_level,
+            cli.strict,
+        ) {
             Ok(carrier) => carrier,
+            // Like OpenPuff, carriers too small to hold anything are left out of the chain.
+            Err(err) if err.is_carrier_too_small() => {
+                if report_warnings {
+                    warn!("{}: {err}, skipping it.", carrier_name(path));
+                }
+                continue;
+            }
+            Err(librepuff::Error::IoError(err))
+                if cli.zero_fill_missing && err.kind() == io::ErrorKind::NotFound =>
+            {
+                if report_warnings {
+                    warn!(
+                        "{} is missing, replacing it with a placeholder: the data hidden after it will be garbage.",
+                        path.display()
+                    );
+                }
+                missing_positions.push(used_paths.len() + missing_positions.len());
+                continue;
+            }
             Err(err) => {
-                error!("could not parse {}: {err}.", path.display());
+                return Err(format!("could not parse {}: {err}.", carrier_name(path)));
+            }
+        };
+        debug!("{}: {carrier}", carrier_name(path));
+        if report_warnings {
+            for warning in &carrier.warnings {
+                warn!("{}: {warning}.", path.display());
+            }
+        }
+
+        used_paths.push(path.clone());
+        carrier_types.push(file_type);
+        carriers.push(carrier);
+    }
+
+    if let Some(directory) = &cli.dump_filler {
+        dump_filler(directory, &used_paths, &carriers)?;
+    }
 
+    let preflight = chain::preflight(&carriers);
+    debug!(
+        "{} carriers, holding {} bits of data and as many bits of decoy at the {selection_level} level",
+        preflight.carrier_count, preflight.total_capacity_bits
+    );
+    if report_warnings {
+        for &(i, j) in &preflight.duplicates {
+            warn!(
+                "{} and {} are the same carrier, OpenPuff would complain.",
+                used_paths[i].display(),
+                used_paths[j].display()
+            );
+        }
+        if preflight.too_many_carriers {
+            warn!(
+                "{} or more carriers used, OpenPuff would complain.",
+                chain::MAX_CARRIER_COUNT
+            );
+        }
+    }
+
+    // Carriers are parsed the way OpenPuff does, which refuses such chains.
+    if preflight.too_many_bits {
+        return Err(format!(
+            "too many carriers: {}.",
+            librepuff::Error::TooManyBits
+        ));
+    }
+
+    // Decrypts carriers.
+    let carriers_embeddings = if missing_positions.is_empty() {
+        chain::decrypt_carrier_chain_with_progress(carriers, passwords, |completed, total| {
+            debug!("decrypted carrier {completed}/{total}");
+        })
+    } else {
+        chain::decrypt_partial_carrier_chain(
+            with_placeholders(carriers, &missing_positions),
+            passwords,
+        )
+    };
+
+    debug!(
+        "decrypted {} data and decoy bytes, {} carrier(s) holding none",
+        carriers_embeddings
+            .iter()
+            .map(chain::CarrierEmbeddings::total_bytes)
+            .sum::<usize>(),
+        carriers_embeddings
+            .iter()
+            .filter(|embeddings| embeddings.is_empty())
+            .count()
+    );
+    let (data_embedding, decoy_embedding) = chain::concatenate_embeddings(&carriers_embeddings);
+
+    let mut files = if cli.no_validate {
+        raw_embeddings(cli.extract, &data_embedding, &decoy_embedding)
+    } else {
+        select_files(
+            cli.extract,
+            &data_embedding,
+            &decoy_embedding,
+            cli.filename_encoding,
+            cli.integrity,
+        )?
+    };
+    if let Some(range) = cli.range {
+        for file in &mut files {
+            apply_range(file, range)?;
+        }
+    }
+
+    Ok(Extraction {
+        carrier_types: with_placeholders(carrier_types, &missing_positions),
+        files,
+    })
+}
+
+fn main() -> ExitCode {
+    // Parses command-line arguments.
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    init_logger(log_level(&cli), cli.log_format);
+
+    if let Some(path) = &cli.project {
+        match Project::from_file(path) {
+            Ok(project) => apply_project(&mut cli, &matches, project),
+            Err(err) => {
+                error!("{err}");
                 return ExitCode::FAILURE;
             }
+        }
+    }
+
+    // Carrier inspection doesn't need any password.
+    if cli.info {
+        let success = match carrier_paths(&cli).and_then(|paths| {
+            let forced_types = carrier_type_overrides(&cli, &paths)?;
+            Ok((paths, forced_types))
+        }) {
+            Ok((paths, forced_types)) => {
+                print_carriers_info(&paths, &forced_types, cli.bit_selection)
+            }
+            Err(err) => {
+                error!("{err}");
+                false
+            }
         };
 
-        carriers.push(carrier);
+        return if success {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
     }
 
-    if carriers.len() >= 65535 {
-        warn!("65535 or more carriers used, OpenPuff would complain.");
+    if cli.lint {
+        return match lint(&cli) {
+            Ok(issues) => {
+                for issue in &issues {
+                    println!("{}\t{}", issue.category, issue.message);
+                }
+
+                if issues.is_empty() {
+                    info!("no issue found, OpenPuff would accept these carriers and passwords.");
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::FAILURE
+                }
+            }
+            Err(err) => {
+                error!("{err}");
+                ExitCode::FAILURE
+            }
+        };
     }
 
-    fn are_there_too_many_bits(carriers: &Vec<carrier::EncryptedCarrier>) -> bool {
-        let mut total: u32 = 0;
-        for carrier in carriers {
-            let selected_bit_count = match u32::try_from(carrier.selected_bit_count()) {
-                Err(_) => return true,
-                Ok(v) => v,
-            };
+    let extraction = check_outputs(&cli)
+        .and_then(|()| extract(&cli))
+        .and_then(|extraction| {
+            write_extracted_files(&cli, &extraction, io::stdout().is_terminal())
+                .map(|()| extraction)
+        });
 
-            total = match total.checked_add(selected_bit_count) {
-                None => return true,
-                Some(v) => v,
+    let report = match extraction {
+        Ok(extraction) => ExtractionReport::success(
+            &extraction.carrier_types,
+            extraction
+                .files
+                .iter()
+                .map(|file| FileReport {
+                    unvalidated: !file.validated,
+                    range: file.range.as_ref().map(|range| RangeReport {
+                        start: range.start,
+                        end: range.end,
+                        crc32: librepuff::crc32::compute(&file.content),
+                    }),
+                    ..FileReport::new(file.kind, &file.filename, &file.content, file.crc32)
+                })
+                .collect(),
+        ),
+        Err(e) => {
+            if cli.format == OutputFormat::Human {
+                error!("{e}");
             }
+
+            ExtractionReport::failure(e)
         }
+    };
 
-        false
+    if cli.format == OutputFormat::Json {
+        println!("{}", serde_json::to_string(&report).unwrap());
     }
-    if are_there_too_many_bits(&carriers) {
-        warn!("too many carriers (the total number of selected bits overflows 32 bits), OpenPuff would complain.");
+
+    if report.success {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
+}
 
-    // Decrypts carriers.
-    let carriers_embeddings = chain::decrypt_carrier_chain(carriers, passwords);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use librepuff::test_utils::{self, embedded_file_bytes, TemporaryDirectory};
+    use std::ffi::OsStr;
 
-    let mut data_embedding = Vec::new();
-    let mut decoy_embedding = Vec::new();
-    for mut embeddings in carriers_embeddings {
-        data_embedding.append(&mut embeddings.data);
-        decoy_embedding.append(&mut embeddings.decoy);
+    /// Returns the extraction of a data file `file.txt`, holding `content`, from a WAVE carrier.
+    fn text_file_extraction() -> Extraction {
+        Extraction {
+            carrier_types: vec![Some(CarrierType::Wav)],
+            files: vec![ExtractedFile {
+                kind: EmbeddingKind::Data,
+                filename: b"file.txt".to_vec(),
+                content: b"content".to_vec(),
+                crc32: 0,
+                validated: true,
+                range: None,
+            }],
+        }
     }
 
-    let data_file = EmbeddedFile::from_bits(&data_embedding);
-    if let Some(data_file) = data_file {
-        info!(
-            "sucessfully extracted data file: '{}'",
-            String::from_utf8_lossy(data_file.filename)
+    #[test]
+    fn trailing_newline_trimming() {
+        let mut password = String::from("password\n\n");
+        trim_trailing_newline(&mut password);
+        assert_eq!(password, "password\n");
+
+        let mut password = String::from("password\r\n");
+        trim_trailing_newline(&mut password);
+        assert_eq!(password, "password");
+
+        let mut password = String::from("password");
+        trim_trailing_newline(&mut password);
+        assert_eq!(password, "password");
+    }
+
+    #[test]
+    fn carrier_info_formatting() {
+        let info = carrier::CarrierInfo {
+            file_type: CarrierType::Wav,
+            available_bit_count: 13000,
+            selected_bit_count: 512,
+            has_trailing_data: false,
+            warnings: Vec::new(),
+        };
+
+        assert_eq!(
+            format_carrier_info(Path::new("carrier.wav"), &info),
+            "carrier.wav\tWAV\t13000\t512\tno-trailing-data"
         );
+    }
+
+    #[test]
+    fn file_selection() {
+        let data = embedded_file_bytes(b"data.txt", b"data content");
+        let decoy = embedded_file_bytes(b"decoy.txt", b"decoy content");
+        let garbage = [0xffu8; 32];
+
+        let files =
+            select_files(ExtractMode::Both, &data, &decoy, Default::default(), false).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].kind, EmbeddingKind::Data);
+        assert_eq!(files[0].filename, b"data.txt");
+        assert_eq!(files[0].content, b"data content");
+        assert_eq!(files[1].kind, EmbeddingKind::Decoy);
+        assert_eq!(files[1].filename, b"decoy.txt");
+        assert_eq!(files[1].content, b"decoy content");
+
+        let files =
+            select_files(ExtractMode::Auto, &data, &decoy, Default::default(), false).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].kind, EmbeddingKind::Data);
+
+        let files = select_files(
+            ExtractMode::Auto,
+            &garbage,
+            &decoy,
+            Default::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].kind, EmbeddingKind::Decoy);
 
-        output_extracted_file(data_file.content, &cli.output);
+        let files =
+            select_files(ExtractMode::Decoy, &data, &decoy, Default::default(), false).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].kind, EmbeddingKind::Decoy);
 
-        return ExitCode::SUCCESS;
+        assert!(select_files(
+            ExtractMode::Both,
+            &data,
+            &garbage,
+            Default::default(),
+            false
+        )
+        .is_err());
+        assert!(select_files(
+            ExtractMode::Data,
+            &garbage,
+            &decoy,
+            Default::default(),
+            false
+        )
+        .is_err());
     }
 
-    let decoy_file = EmbeddedFile::from_bits(&decoy_embedding);
-    if let Some(decoy_file) = decoy_file {
-        info!(
-            "sucessfully extracted decoy file: '{}'",
-            String::from_utf8_lossy(decoy_file.filename)
+    #[test]
+    fn byte_ranges() {
+        let range = |start, end| ByteRange { start, end };
+        assert_eq!(parse_byte_range("10:20"), Ok(range(10, Some(20))));
+        assert_eq!(parse_byte_range("10:"), Ok(range(10, None)));
+        assert_eq!(parse_byte_range(":20"), Ok(range(0, Some(20))));
+        assert_eq!(parse_byte_range(":"), Ok(range(0, None)));
+        assert_eq!(parse_byte_range("5:5"), Ok(range(5, Some(5))));
+        for invalid in ["10", "10-20", "a:20", "-1:20", "20:10", "1:2:3"] {
+            assert!(parse_byte_range(invalid).is_err(), "{invalid}");
+        }
+
+        let content: Vec<u8> = (0..100).collect();
+        let data = embedded_file_bytes(b"data.bin", &content);
+        let decoy = embedded_file_bytes(b"decoy.txt", b"decoy content");
+        let extract = |range| {
+            let mut files =
+                select_files(ExtractMode::Data, &data, &decoy, Default::default(), false).unwrap();
+            apply_range(&mut files[0], range).map(|()| files.remove(0).content)
+        };
+
+        assert_eq!(
+            extract(range(10, Some(20))).unwrap(),
+            (10..20).collect::<Vec<u8>>()
         );
+        assert_eq!(
+            extract(range(90, None)).unwrap(),
+            (90..100).collect::<Vec<u8>>()
+        );
+        assert_eq!(extract(range(0, Some(100))).unwrap(), content);
+        assert!(extract(range(100, None)).unwrap().is_empty());
+        assert!(extract(range(90, Some(101))).is_err());
+        assert!(extract(range(101, None)).is_err());
+
+        // The offsets are recorded, and the CRC32 is still the whole file's
+        let mut files =
+            select_files(ExtractMode::Data, &data, &decoy, Default::default(), false).unwrap();
+        apply_range(&mut files[0], range(90, None)).unwrap();
+        assert_eq!(files[0].range, Some(90..100));
+        assert_eq!(files[0].crc32, librepuff::crc32::compute(&content));
+    }
 
-        output_extracted_file(decoy_file.content, &cli.output);
+    #[test]
+    fn binary_output_to_terminal() {
+        let binary = b"\x89PNG\r\n\x1a\n\xff";
+        let text = "text, même accentué\n".as_bytes();
 
-        return ExitCode::SUCCESS;
+        assert!(refuses_binary_output(binary, "-", true, false));
+        // Forced
+        assert!(!refuses_binary_output(binary, "-", true, true));
+        // Not a terminal, or not the standard output
+        assert!(!refuses_binary_output(binary, "-", false, false));
+        assert!(!refuses_binary_output(binary, "file.png", true, false));
+        // Text
+        assert!(!refuses_binary_output(text, "-", true, false));
+        assert!(!refuses_binary_output(b"", "-", true, false));
+
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "--force", "a.wav"]).unwrap();
+        assert!(cli.force);
+    }
+
+    #[test]
+    fn missing_carrier_placeholders() {
+        assert_eq!(
+            with_placeholders(vec!['a', 'b'], &[]),
+            [Some('a'), Some('b')]
+        );
+        // A missing middle carrier
+        assert_eq!(
+            with_placeholders(vec!['a', 'c'], &[1]),
+            [Some('a'), None, Some('c')]
+        );
+        assert_eq!(
+            with_placeholders(vec!['c'], &[0, 1, 3]),
+            [None, None, Some('c'), None]
+        );
+    }
+
+    #[test]
+    fn unvalidated_output() {
+        let data = embedded_file_bytes(b"data.txt", b"data content");
+        let garbage = [0xffu8; 32];
+
+        // The CRC32 check fails, but the bytes are still output
+        assert!(select_files(
+            ExtractMode::Data,
+            &garbage,
+            &data,
+            Default::default(),
+            false
+        )
+        .is_err());
+        let files = raw_embeddings(ExtractMode::Data, &garbage, &data);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].kind, EmbeddingKind::Data);
+        assert_eq!(files[0].content, garbage);
+        assert_eq!(files[0].crc32, librepuff::crc32::compute(&garbage));
+        assert!(!files[0].validated);
+
+        // Valid files are output as is too
+        let files = raw_embeddings(ExtractMode::Auto, &data, &garbage);
+        assert_eq!(files[0].content, data);
+        let files = raw_embeddings(ExtractMode::Both, &data, &garbage);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].kind, EmbeddingKind::Decoy);
+        assert_eq!(files[1].content, garbage);
+
+        assert!(Cli::try_parse_from(["repuff", "-p", "a", "--no-validate", "a.wav"]).is_ok());
+        assert!(Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--no-validate",
+            "--integrity",
+            "a.wav"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn integrity_check() {
+        let data = embedded_file_bytes(b"data.txt", b"data content");
+        let mut checked_data = data.clone();
+        let digest = EmbeddedFile::from_bits(&data).unwrap().integrity_digest();
+        checked_data.extend_from_slice(&digest);
+        let decoy = embedded_file_bytes(b"decoy.txt", b"decoy content");
+
+        let files = select_files(
+            ExtractMode::Auto,
+            &checked_data,
+            &decoy,
+            Default::default(),
+            true,
+        );
+        assert_eq!(files.unwrap()[0].content, b"data content");
+        assert!(select_files(ExtractMode::Auto, &data, &decoy, Default::default(), false).is_ok());
+
+        // A file is found, but it can't be trusted: the decoy file isn't used instead.
+        let err = select_files(ExtractMode::Auto, &data, &decoy, Default::default(), true).err();
+        assert_eq!(
+            err.unwrap(),
+            "the data file 'data.txt' was found, but its integrity digest doesn't match."
+        );
+        assert!(select_files(
+            ExtractMode::Both,
+            &checked_data,
+            &decoy,
+            Default::default(),
+            true
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn decoy_output_required_when_extracting_both() {
+        assert!(Cli::try_parse_from(["repuff", "-p", "a", "--extract", "both", "c.wav"]).is_err());
+        assert!(Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--extract",
+            "both",
+            "--decoy-output",
+            "decoy.bin",
+            "c.wav"
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn stdin_carrier_type() {
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "--carrier-type", "wav", "-"]).unwrap();
+        assert_eq!(cli.carrier_type, Some(CarrierType::Wav));
+        assert!(is_stdin(&cli.carriers[0]));
+
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "--carrier-type", "JPG", "-"]).unwrap();
+        assert_eq!(cli.carrier_type, Some(CarrierType::Jpeg));
+
+        assert!(Cli::try_parse_from(["repuff", "-p", "a", "--carrier-type", "txt", "-"]).is_err());
+        assert!(read_stdin_carrier(None).is_err());
+    }
+
+    #[test]
+    fn carrier_type_overrides() {
+        let overrides = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["repuff", "-p", "a"].iter().chain(args)).unwrap();
+            super::carrier_type_overrides(&cli, &cli.carriers)
+        };
+
+        assert_eq!(overrides(&["a", "b.wav"]), Ok(vec![None, None]));
+        assert_eq!(
+            overrides(&["--carrier-type", "wav", "a"]),
+            Ok(vec![Some(CarrierType::Wav)])
+        );
+        assert_eq!(
+            overrides(&["--carrier-type", "wav", "a.jpg", "-"]),
+            Ok(vec![None, Some(CarrierType::Wav)])
+        );
+        assert_eq!(
+            overrides(&["--carrier-types", "wav,jpg", "a", "b"]),
+            Ok(vec![Some(CarrierType::Wav), Some(CarrierType::Jpeg)])
+        );
+        assert!(overrides(&["--carrier-types", "wav", "a", "b"]).is_err());
+
+        assert!(Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--carrier-type",
+            "wav",
+            "--carrier-types",
+            "wav",
+            "a"
+        ])
+        .is_err());
     }
 
-    error!("could not extract a data or decoy file using the given passwords.");
+    #[test]
+    fn filename_encoding_option() {
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "c.wav"]).unwrap();
+        assert_eq!(cli.filename_encoding, FilenameEncoding::Utf8);
+
+        let cli = Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--filename-encoding",
+            "Windows-1252",
+            "c.wav",
+        ])
+        .unwrap();
+        assert_eq!(cli.filename_encoding, FilenameEncoding::Windows1252);
 
-    ExitCode::FAILURE
+        assert!(
+            Cli::try_parse_from(["repuff", "--filename-encoding", "latin-9", "c.wav"]).is_err()
+        );
+    }
+
+    #[test]
+    fn password_sources_are_exclusive() {
+        assert!(Cli::try_parse_from(["repuff", "-p", "a", "carrier.wav"]).is_ok());
+        assert!(Cli::try_parse_from(["repuff", "--password-a-env", "A", "carrier.wav"]).is_ok());
+        assert!(Cli::try_parse_from(["repuff", "carrier.wav"]).is_ok());
+        assert!(Cli::try_parse_from(["repuff", "--prompt-c", "carrier.wav"]).is_err());
+        assert!(Cli::try_parse_from(["repuff", "--prompt-b", "--prompt-c", "carrier.wav"]).is_ok());
+        assert!(Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--password-a-file",
+            "a.txt",
+            "carrier.wav"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--password-c-file",
+            "c.txt",
+            "carrier.wav"
+        ])
+        .is_err());
+        assert!(
+            Cli::try_parse_from(["repuff", "--password-b", "b", "--prompt-b", "carrier.wav"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn carrier_reordering() {
+        let paths = || vec![PathBuf::from("a.wav"), PathBuf::from("b.wav")];
+
+        assert_eq!(reorder_carriers(paths(), &[1, 2]).unwrap(), paths());
+        assert_eq!(
+            reorder_carriers(paths(), &[2, 1]).unwrap(),
+            [PathBuf::from("b.wav"), PathBuf::from("a.wav")]
+        );
+
+        assert!(reorder_carriers(paths(), &[1]).is_err());
+        assert!(reorder_carriers(paths(), &[1, 2, 3]).is_err());
+        assert!(reorder_carriers(paths(), &[1, 1]).is_err());
+        assert!(reorder_carriers(paths(), &[0, 1]).is_err());
+        assert!(reorder_carriers(paths(), &[1, 3]).is_err());
+    }
+
+    #[test]
+    fn carrier_sources() {
+        let directory = TemporaryDirectory::new("carrier_sources");
+        let carrier_dir = directory.join("carriers");
+        fs::create_dir_all(&carrier_dir).unwrap();
+
+        // The listing order is kept, even though it isn't sorted.
+        let listing = directory.join("carriers.txt");
+        fs::write(&listing, "second.wav\nfirst.wav\n\nsub/third.wav\r\n").unwrap();
+        let cli = Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--carriers-from",
+            listing.to_str().unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(
+            carrier_paths(&cli).unwrap(),
+            [
+                PathBuf::from("second.wav"),
+                PathBuf::from("first.wav"),
+                PathBuf::from("sub/third.wav")
+            ]
+        );
+
+        for name in ["b.wav", "a.wav", "C.wav", "10.wav", "9.wav"] {
+            fs::write(carrier_dir.join(name), b"").unwrap();
+        }
+        fs::create_dir_all(carrier_dir.join("subdirectory")).unwrap();
+        let cli = Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--carrier-dir",
+            carrier_dir.to_str().unwrap(),
+        ])
+        .unwrap();
+        let names: Vec<_> = carrier_paths(&cli)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["10.wav", "9.wav", "C.wav", "a.wav", "b.wav"]);
+
+        // Positional carriers are still accepted, but can't be mixed with the other sources.
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "b.wav", "a.wav"]).unwrap();
+        assert_eq!(
+            carrier_paths(&cli).unwrap(),
+            [PathBuf::from("b.wav"), PathBuf::from("a.wav")]
+        );
+        assert!(Cli::try_parse_from(["repuff", "-p", "a"]).is_err());
+        assert!(
+            Cli::try_parse_from(["repuff", "-p", "a", "--carrier-dir", "carriers", "a.wav"])
+                .is_err()
+        );
+
+        // The order is applied to the list of carriers, which is then parsed and decrypted.
+        let cli = Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--carrier-order",
+            "3,1,2",
+            "a.wav",
+            "b.wav",
+            "c.wav",
+        ])
+        .unwrap();
+        assert_eq!(
+            carrier_paths(&cli).unwrap(),
+            [
+                PathBuf::from("c.wav"),
+                PathBuf::from("a.wav"),
+                PathBuf::from("b.wav")
+            ]
+        );
+        let cli = Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--carriers-from",
+            listing.to_str().unwrap(),
+            "--carrier-order",
+            "2,1,3",
+        ])
+        .unwrap();
+        assert_eq!(
+            carrier_paths(&cli).unwrap(),
+            [
+                PathBuf::from("first.wav"),
+                PathBuf::from("second.wav"),
+                PathBuf::from("sub/third.wav")
+            ]
+        );
+
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "--carriers-from", "/does/not/exist"])
+            .unwrap();
+        assert!(carrier_paths(&cli).is_err());
+    }
+
+    #[test]
+    fn project_settings() {
+        let project = || Project {
+            carriers: vec![PathBuf::from("first.wav"), PathBuf::from("second.wav")],
+            bit_selection: Some(BitSelection::Low),
+            version: Some(VersionCompatibility::V4_00),
+            output: Some(String::from("secret.txt")),
+        };
+        let cli = |args: &[&str]| {
+            let matches = Cli::command()
+                .try_get_matches_from(
+                    ["repuff", "-p", "a", "--project", "p.toml"]
+                        .iter()
+                        .chain(args),
+                )
+                .unwrap();
+            let mut cli = Cli::from_arg_matches(&matches).unwrap();
+            apply_project(&mut cli, &matches, project());
+            cli
+        };
+
+        let from_project = cli(&[]);
+        assert_eq!(from_project.carriers, project().carriers);
+        assert_eq!(from_project.bit_selection, BitSelection::Low);
+        assert_eq!(from_project.openpuff_version, VersionCompatibility::V4_00);
+        assert_eq!(from_project.output, "secret.txt");
+
+        // The command line takes precedence, even over default values.
+        let overridden = cli(&[
+            "--bit-selection",
+            "medium",
+            "-o",
+            "-",
+            "-c",
+            "v4.01",
+            "c.wav",
+        ]);
+        assert_eq!(overridden.carriers, [PathBuf::from("c.wav")]);
+        assert_eq!(overridden.bit_selection, BitSelection::Medium);
+        assert_eq!(overridden.openpuff_version, VersionCompatibility::V4_01);
+        assert_eq!(overridden.output, "-");
+        assert!(cli(&["--carrier-dir", "carriers"]).carriers.is_empty());
+
+        assert!(Cli::try_parse_from(["repuff", "-p", "a", "--project", "p.toml"]).is_ok());
+        assert!(
+            Cli::try_parse_from(["repuff", "-p", "a", "--carriers-from", "c.txt", "c.wav"])
+                .is_err()
+        );
+        assert!(Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--carriers-from",
+            "c.txt",
+            "--carrier-dir",
+            "carriers"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn embedded_names() {
+        let directory = TemporaryDirectory::new("embedded_names");
+        let output = directory.join("embedded-names");
+        fs::create_dir_all(&output).unwrap();
+
+        let file = |filename: &[u8]| ExtractedFile {
+            kind: EmbeddingKind::Data,
+            filename: filename.to_vec(),
+            content: b"content".to_vec(),
+            crc32: 0,
+            validated: true,
+            range: None,
+        };
+
+        write_under_embedded_name(&file(b"../../escaped.txt"), &output, Default::default())
+            .unwrap();
+        assert_eq!(fs::read(output.join("escaped.txt")).unwrap(), b"content");
+        assert!(!directory.join("escaped.txt").exists());
+
+        // Existing files are kept
+        assert!(
+            write_under_embedded_name(&file(b"escaped.txt"), &output, Default::default()).is_err()
+        );
+        assert!(write_under_embedded_name(&file(b".."), &output, Default::default()).is_err());
+        assert!(
+            write_under_embedded_name(&file(b"file.txt"), Path::new("-"), Default::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn bit_selection_options() {
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "c.wav"]).unwrap();
+        assert_eq!(cli.bit_selection, BitSelection::Medium);
+        assert!(!cli.autodetect_selection);
+
+        let cli =
+            Cli::try_parse_from(["repuff", "-p", "a", "--bit-selection", "veryhigh", "c.wav"])
+                .unwrap();
+        assert_eq!(cli.bit_selection, BitSelection::VeryHigh);
+
+        assert!(Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--bit-selection",
+            "high",
+            "--autodetect-selection",
+            "c.wav"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn log_levels() {
+        let level = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["repuff", "-p", "a"].iter().chain(args)).unwrap();
+            log_level(&cli)
+        };
+
+        assert_eq!(level(&["c.wav"]), LevelFilter::Info);
+        assert_eq!(level(&["-v", "c.wav"]), LevelFilter::Debug);
+        assert_eq!(level(&["-vv", "c.wav"]), LevelFilter::Trace);
+        assert_eq!(
+            level(&["--verbose", "-v", "-v", "c.wav"]),
+            LevelFilter::Trace
+        );
+        assert_eq!(level(&["--quiet", "c.wav"]), LevelFilter::Error);
+        assert_eq!(
+            level(&["-v", "--format", "json", "-o", "f", "c.wav"]),
+            LevelFilter::Off
+        );
+
+        assert!(Cli::try_parse_from(["repuff", "-p", "a", "-v", "-q", "c.wav"]).is_err());
+
+        let cli = Cli::try_parse_from(["repuff", "-p", "a", "--log-format", "json", "c.wav"]);
+        assert_eq!(cli.unwrap().log_format, LogFormat::Json);
+    }
+
+    #[test]
+    fn selection_level_autodetection() {
+        // A file hidden at the high level is only extracted at that level.
+        let mut attempts = Vec::new();
+        let result = first_successful_level(BitSelection::all(), |level| {
+            attempts.push(level);
+            if level == BitSelection::High {
+                Ok("file")
+            } else {
+                Err(String::from("could not extract a file"))
+            }
+        });
+        assert_eq!(result, Ok((BitSelection::High, "file")));
+        assert_eq!(
+            attempts,
+            [
+                BitSelection::Minimum,
+                BitSelection::VeryLow,
+                BitSelection::Low,
+                BitSelection::Medium,
+                BitSelection::High
+            ]
+        );
+
+        let result = first_successful_level(BitSelection::all(), |_| {
+            Err::<(), _>(String::from("could not extract a file"))
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn zero_filled_missing_carrier() {
+        let directory = TemporaryDirectory::new("zero_filled_missing_carrier");
+        let passwords = Passwords::from_fields("firstpassword", None, None).unwrap();
+        let data = embedded_file_bytes(b"secret.txt", &[b'x'; 76]);
+        let wavs = test_utils::hiding_wav_files(&data, &[], 3, BitSelection::Medium, &passwords);
+        let first = directory.file("first.wav", &wavs[0]);
+        let last = directory.file("last.wav", &wavs[2]);
+        let missing = directory.join("missing.wav");
+
+        let cli = Cli::try_parse_from([
+            OsStr::new("repuff"),
+            OsStr::new("-p"),
+            OsStr::new("firstpassword"),
+            OsStr::new("--zero-fill-missing"),
+            OsStr::new("--no-validate"),
+            first.as_os_str(),
+            missing.as_os_str(),
+            last.as_os_str(),
+        ])
+        .unwrap();
+        let extraction = extract(&cli).unwrap();
+
+        assert_eq!(
+            extraction.carrier_types,
+            [Some(CarrierType::Wav), None, Some(CarrierType::Wav)]
+        );
+        // The placeholder holds nothing, and only what the first carrier holds is recovered
+        let content = &extraction.files[0].content;
+        assert_eq!(content.len(), 2 * 32);
+        assert_eq!(content[..32], data[..32]);
+        assert_ne!(content[32..], data[64..]);
+    }
+
+    #[test]
+    fn hidden_level_autodetection() {
+        let directory = TemporaryDirectory::new("hidden_level_autodetection");
+        let passwords = Passwords::from_fields("firstpassword", None, None).unwrap();
+        let wavs = test_utils::hiding_wav_files(
+            &embedded_file_bytes(b"secret.txt", b"hidden at the high level"),
+            &[],
+            2,
+            BitSelection::High,
+            &passwords,
+        );
+        let paths: Vec<String> = wavs
+            .iter()
+            .enumerate()
+            .map(|(i, wav)| {
+                let path = directory.file(&format!("carrier_{i}.wav"), wav);
+                path.to_str().unwrap().to_owned()
+            })
+            .collect();
+        let cli = |args: &[&str]| {
+            let mut arguments = vec!["repuff", "-p", "firstpassword"];
+            arguments.extend_from_slice(args);
+            arguments.extend(paths.iter().map(String::as_str));
+            Cli::try_parse_from(arguments).unwrap()
+        };
+
+        // Not found at the default level, nor at any other wrong one
+        assert!(extract(&cli(&[])).is_err());
+        assert!(extract(&cli(&["--bit-selection", "veryhigh"])).is_err());
+
+        let extraction = extract(&cli(&["--autodetect-selection"])).unwrap();
+        assert_eq!(extraction.files.len(), 1);
+        assert_eq!(extraction.files[0].filename, b"secret.txt");
+        assert_eq!(extraction.files[0].content, b"hidden at the high level");
+    }
+
+    #[test]
+    fn dry_run() {
+        let directory = TemporaryDirectory::new("dry_run");
+        let output = directory.join("dry-run.txt");
+        let cli = |dry_run: bool| {
+            let args = ["repuff", "-p", "a", "-o", output.to_str().unwrap()];
+            let dry_run = dry_run.then_some("--dry-run");
+            Cli::try_parse_from(args.into_iter().chain(dry_run).chain(["carrier.wav"])).unwrap()
+        };
+        let extraction = text_file_extraction();
+
+        write_extracted_files(&cli(true), &extraction, false).unwrap();
+        assert!(!output.exists());
+
+        // The same extraction writes the file when it isn't a dry run.
+        write_extracted_files(&cli(false), &extraction, false).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"content");
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn filler_dump() {
+        let directory = TemporaryDirectory::new("filler_dump");
+
+        let carrier = |filler_bit_count: usize| carrier::EncryptedCarrier {
+            iv: [0; 256],
+            data: vec![0; 16],
+            decoy: vec![0; 16],
+            other_bits: (0..filler_bit_count).map(|i| i % 3 == 0).collect(),
+            warnings: Vec::new(),
+        };
+        let carriers = [carrier(13), carrier(64)];
+        let paths = [PathBuf::from("dir/a.wav"), PathBuf::from("-")];
+
+        dump_filler(&directory, &paths, &carriers).unwrap();
+        assert_eq!(
+            fs::read(directory.join("0-a.wav.filler")).unwrap(),
+            carriers[0].filler_bytes()
+        );
+        assert_eq!(fs::read(directory.join("0-a.wav.filler")).unwrap().len(), 2);
+        assert_eq!(fs::read(directory.join("1-stdin.filler")).unwrap().len(), 8);
+
+        assert!(dump_filler(Path::new("/does/not/exist"), &paths, &carriers).is_err());
+    }
+
+    #[test]
+    fn max_output_size() {
+        let directory = TemporaryDirectory::new("max_output_size");
+        let output = directory.join("max-output-size.txt");
+        let extraction = text_file_extraction();
+        let cli = |max_output_size: &str| {
+            Cli::try_parse_from([
+                "repuff",
+                "-p",
+                "a",
+                "--max-output-size",
+                max_output_size,
+                "-o",
+                output.to_str().unwrap(),
+                "carrier.wav",
+            ])
+            .unwrap()
+        };
+
+        assert!(write_extracted_files(&cli("6"), &extraction, false).is_err());
+        assert!(!output.exists());
+
+        write_extracted_files(&cli("7"), &extraction, false).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"content");
+        fs::remove_file(&output).unwrap();
+
+        assert!(
+            Cli::try_parse_from(["repuff", "-p", "a", "--max-output-size", "1M", "c.wav"]).is_err()
+        );
+    }
+
+    #[test]
+    fn binary_output_refused_before_writing() {
+        let directory = TemporaryDirectory::new("binary_output_refused_before_writing");
+        let output = directory.join("data.txt");
+        let mut extraction = text_file_extraction();
+        extraction.files.push(ExtractedFile {
+            kind: EmbeddingKind::Decoy,
+            filename: b"decoy.bin".to_vec(),
+            content: vec![0xff, 0xfe],
+            crc32: 0,
+            validated: true,
+            range: None,
+        });
+        let cli = Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "--extract",
+            "both",
+            "-o",
+            output.to_str().unwrap(),
+            "--decoy-output",
+            "-",
+            "carrier.wav",
+        ])
+        .unwrap();
+
+        // The binary decoy file is refused before the data file is written
+        let err = write_extracted_files(&cli, &extraction, true).unwrap_err();
+        assert!(err.starts_with("the decoy file is binary"));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn unwritable_output() {
+        assert!(output_extracted_file(b"content", "/does/not/exist/file.txt").is_err());
+
+        let cli = Cli::try_parse_from([
+            "repuff",
+            "-p",
+            "a",
+            "-o",
+            "/does/not/exist/file.txt",
+            "carrier.wav",
+        ])
+        .unwrap();
+        let extraction = text_file_extraction();
+
+        let err = write_extracted_files(&cli, &extraction, false).unwrap_err();
+        assert!(err.starts_with("could not write /does/not/exist/file.txt: "));
+    }
 }
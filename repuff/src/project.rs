@@ -0,0 +1,148 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use librepuff::bit_selection::BitSelection;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::VersionCompatibility;
+
+/// Settings of an extraction, read from a TOML or JSON file by `--project`.
+///
+/// Every setting is optional, and the command-line arguments take precedence over them.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Project {
+    /// Carriers, in hiding order. Relative paths are relative to the directory of the project
+    /// file, so that a project can be moved along with its carriers.
+    pub carriers: Vec<PathBuf>,
+    #[serde(deserialize_with = "deserialize_bit_selection")]
+    pub bit_selection: Option<BitSelection>,
+    /// OpenPuff version compatibility, e.g. `v4.01`.
+    pub version: Option<VersionCompatibility>,
+    /// Where to output the extracted file, `-` referring to the standard output. Unlike the
+    /// carriers, a relative path is relative to the working directory.
+    pub output: Option<String>,
+}
+
+/// Deserializes the name of a bit selection level (see `BitSelection::from_str`).
+fn deserialize_bit_selection<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<BitSelection>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse()
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("unknown bit selection level '{name}'")))
+}
+
+impl Project {
+    /// Reads the project file at `path`, in JSON if its extension is `json`, in TOML otherwise.
+    ///
+    /// Relative carrier paths are resolved against the directory holding `path`, `-` (the
+    /// standard input) being kept as is.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {err}.", path.display()))?;
+
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let project = if is_json {
+            serde_json::from_str(&content).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&content).map_err(|err| err.to_string())
+        };
+
+        let mut project: Project =
+            project.map_err(|err| format!("invalid project file {}: {err}", path.display()))?;
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        for carrier in &mut project.carriers {
+            if !crate::is_stdin(carrier) {
+                *carrier = directory.join(&*carrier);
+            }
+        }
+
+        Ok(project)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use librepuff::test_utils::TemporaryDirectory;
+    use std::env;
+
+    #[test]
+    fn project_files() {
+        let directory = TemporaryDirectory::new("project_files");
+
+        let expected = Project {
+            carriers: vec![directory.join("second.wav"), directory.join("first.wav")],
+            bit_selection: Some(BitSelection::VeryHigh),
+            version: Some(VersionCompatibility::V4_00),
+            output: Some(String::from("secret.txt")),
+        };
+
+        let toml_project = directory.join("project.toml");
+        fs::write(
+            &toml_project,
+            r#"
+carriers = ["second.wav", "first.wav"]
+bit_selection = "very high"
+version = "v4.00"
+output = "secret.txt"
+"#,
+        )
+        .unwrap();
+        assert_eq!(Project::from_file(&toml_project).unwrap(), expected);
+
+        let json_project = directory.join("project.JSON");
+        fs::write(
+            &json_project,
+            r#"{
+                "carriers": ["second.wav", "first.wav"],
+                "bit_selection": "veryhigh",
+                "version": "v4.00",
+                "output": "secret.txt"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(Project::from_file(&json_project).unwrap(), expected);
+
+        // Settings are optional
+        fs::write(&toml_project, "carriers = [\"a.wav\"]").unwrap();
+        let project = Project::from_file(&toml_project).unwrap();
+        assert_eq!(project.carriers, [directory.join("a.wav")]);
+        assert_eq!(project.bit_selection, None);
+
+        // Absolute paths and the standard input are kept as is
+        let absolute = env::temp_dir().join("a.wav");
+        fs::write(
+            &toml_project,
+            format!("carriers = [{:?}, \"-\"]", absolute.display().to_string()),
+        )
+        .unwrap();
+        let project = Project::from_file(&toml_project).unwrap();
+        assert_eq!(project.carriers, [absolute, PathBuf::from("-")]);
+
+        fs::write(&toml_project, "bit_selection = \"lowest\"").unwrap();
+        assert!(Project::from_file(&toml_project).is_err());
+        fs::write(&toml_project, "carrier = [\"a.wav\"]").unwrap();
+        assert!(Project::from_file(&toml_project).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+use librepuff::carrier_type::CarrierType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Embedding a file is extracted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingKind {
+    Data,
+    Decoy,
+}
+
+impl fmt::Display for EmbeddingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Data => write!(f, "data"),
+            Self::Decoy => write!(f, "decoy"),
+        }
+    }
+}
+
+/// Report of an extracted file.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileReport {
+    pub kind: EmbeddingKind,
+    /// Filename of the extracted file, decoded as UTF-8.
+    pub filename: String,
+    /// Whether invalid UTF-8 sequences had to be replaced when decoding the filename.
+    pub filename_is_lossy: bool,
+    /// Number of bytes output, only those of `range` if any.
+    pub content_length: usize,
+    /// CRC32 of the whole file, even if only a range of it is output.
+    pub crc32: u32,
+    /// Whether the file is raw decrypted bytes, output without checking it holds a valid file
+    /// (see `--no-validate`). Its filename is then empty.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unvalidated: bool,
+    /// Part of the file output, with `--range`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<RangeReport>,
+}
+
+/// Part of an extracted file output with `--range`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeReport {
+    pub start: usize,
+    /// End of the range, excluded.
+    pub end: usize,
+    /// CRC32 of the bytes of the range.
+    pub crc32: u32,
+}
+
+impl FileReport {
+    pub fn new(kind: EmbeddingKind, filename: &[u8], content: &[u8], crc32: u32) -> Self {
+        FileReport {
+            kind,
+            filename: String::from_utf8_lossy(filename).into_owned(),
+            filename_is_lossy: std::str::from_utf8(filename).is_err(),
+            content_length: content.len(),
+            crc32,
+            unvalidated: false,
+            range: None,
+        }
+    }
+}
+
+/// Result of an extraction, as printed by `--format json`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractionReport {
+    pub success: bool,
+    /// Why the extraction failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Type of each carrier of the chain, `missing` for the placeholders of missing carriers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub carrier_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileReport>,
+}
+
+impl ExtractionReport {
+    pub fn success(carrier_types: &[Option<CarrierType>], files: Vec<FileReport>) -> Self {
+        ExtractionReport {
+            success: true,
+            reason: None,
+            carrier_types: carrier_types
+                .iter()
+                .map(|t| t.map_or_else(|| String::from("missing"), |t| t.to_string()))
+                .collect(),
+            files,
+        }
+    }
+
+    pub fn failure(reason: String) -> Self {
+        ExtractionReport {
+            success: false,
+            reason: Some(reason),
+            carrier_types: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let report = ExtractionReport::success(
+            &[Some(CarrierType::Wav), None, Some(CarrierType::Wav)],
+            vec![
+                FileReport::new(
+                    EmbeddingKind::Decoy,
+                    b"secret\xff.txt",
+                    b"content",
+                    0x12345678,
+                ),
+                FileReport {
+                    range: Some(RangeReport {
+                        start: 2,
+                        end: 5,
+                        crc32: 0x9abcdef0,
+                    }),
+                    ..FileReport::new(EmbeddingKind::Data, b"data.bin", b"nte", 0x12345678)
+                },
+            ],
+        );
+
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: ExtractionReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, report);
+
+        assert_eq!(parsed.carrier_types, ["WAV", "missing", "WAV"]);
+        assert_eq!(parsed.files[0].kind, EmbeddingKind::Decoy);
+        assert_eq!(parsed.files[0].filename, "secret\u{fffd}.txt");
+        assert!(parsed.files[0].filename_is_lossy);
+        assert_eq!(parsed.files[0].content_length, 7);
+        assert_eq!(parsed.files[0].range, None);
+        assert_eq!(parsed.files[1].content_length, 3);
+        assert!(json.contains(r#""range":{"start":2,"end":5,"crc32":2596069104}"#));
+    }
+
+    #[test]
+    fn json_failure() {
+        let report = ExtractionReport::failure(String::from("wrong passwords"));
+
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"success":false,"reason":"wrong passwords"}"#
+        );
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Forcing the type of carriers, whatever their extension.
+
+use librepuff::test_utils::{wav_file, TemporaryDirectory};
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn forced_carrier_type() {
+    let directory = TemporaryDirectory::new("forced_carrier_type");
+
+    let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 5)).collect();
+    let path = directory.join("carrier");
+    fs::write(&path, wav_file(&samples)).unwrap();
+
+    let info = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_repuff"))
+            .arg("--info")
+            .args(args)
+            .arg(&path)
+            .output()
+            .unwrap()
+    };
+
+    // Without an extension, the type of the carrier is unknown
+    assert!(!info(&[]).status.success());
+
+    for args in [["--carrier-type", "wav"], ["--carrier-types", "WAV"]] {
+        let output = info(&args);
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let fields: Vec<&str> = stdout.trim_end().split('\t').collect();
+        assert_eq!(fields[0], path.display().to_string());
+        assert_eq!(fields[1], "WAV");
+    }
+}
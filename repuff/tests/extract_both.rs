@@ -0,0 +1,65 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Extracting both the data and the decoy files, with `--extract both`.
+
+use librepuff::bit_selection::BitSelection;
+use librepuff::passwords::Passwords;
+use librepuff::test_utils::{embedded_file_bytes, hiding_wav_files, TemporaryDirectory};
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn both_files_extracted() {
+    let directory = TemporaryDirectory::new("both_files_extracted");
+
+    let passwords = Passwords::from_fields("firstpassword", None, None).unwrap();
+    let wavs = hiding_wav_files(
+        &embedded_file_bytes(b"data.txt", b"the data file"),
+        &embedded_file_bytes(b"decoy.txt", b"the decoy file"),
+        2,
+        BitSelection::Medium,
+        &passwords,
+    );
+    let carriers: Vec<_> = wavs
+        .iter()
+        .enumerate()
+        .map(|(i, wav)| directory.file(&format!("carrier_{i}.wav"), wav))
+        .collect();
+
+    let extract = |outputs: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_repuff"))
+            .args(["-p", "firstpassword", "--extract", "both"])
+            .args(outputs)
+            .args(&carriers)
+            .output()
+            .unwrap()
+    };
+
+    // Both files can't be written to the standard output
+    let output = extract(&["-o", "-", "--decoy-output", "-"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("can't both be written to the standard output"));
+
+    let decoy = directory.join("decoy.txt");
+    let output = extract(&["-o", "-", "--decoy-output", decoy.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"the data file");
+    assert_eq!(fs::read(&decoy).unwrap(), b"the decoy file");
+}
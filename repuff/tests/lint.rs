@@ -0,0 +1,64 @@
+// Copyright 2023 tweqx
+
+// This file is part of LibrePuff.
+//
+// LibrePuff is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+//
+// LibrePuff is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR
+// A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with LibrePuff. If not, see <https://www.gnu.org/licenses/>.
+
+//! Checking carriers and passwords the way OpenPuff does, with `--lint`.
+
+use librepuff::test_utils::{wav_file, TemporaryDirectory};
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn lint_issues() {
+    let directory = TemporaryDirectory::new("lint_issues");
+
+    let samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 5)).collect();
+    let other_samples: Vec<u16> = (0..13000).map(|i| 0b1000 | (i % 7)).collect();
+    let carrier = directory.join("carrier.wav");
+    let copy = directory.join("copy.wav");
+    let other = directory.join("other.wav");
+    fs::write(&carrier, wav_file(&samples)).unwrap();
+    fs::write(&copy, wav_file(&samples)).unwrap();
+    fs::write(&other, wav_file(&other_samples)).unwrap();
+
+    let lint = |password_b: &str, carriers: &[&std::path::Path]| {
+        Command::new(env!("CARGO_BIN_EXE_repuff"))
+            .args(["--lint", "-p", "firstpassword", "--password-b", password_b])
+            .args(carriers)
+            .output()
+            .unwrap()
+    };
+
+    // Correlated passwords and duplicate carriers
+    let output = lint("firstpassworc", &[&carrier, &other, &copy]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let issues: Vec<&str> = stdout.lines().collect();
+    assert_eq!(issues.len(), 2, "{stdout}");
+    assert!(issues[0].starts_with("passwords\tpasswords A and B are too correlated"));
+    assert_eq!(
+        issues[1],
+        format!(
+            "carriers\t{} and {} are the same carrier",
+            carrier.display(),
+            copy.display()
+        )
+    );
+
+    let output = lint("unrelated secret", &[&carrier, &other]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}